@@ -1,28 +1,30 @@
 use futures::TryStreamExt;
 use log::debug;
-use rand::distributions::Alphanumeric;
-use rand::Rng;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use db::internal::error::PPError;
 
 use crate::db;
 use crate::db::bucket::DatabaseBuilder;
-use crate::db::init::Database;
+use crate::db::chat::messages::MessagesDB;
+use crate::db::chat::subscribers::SubscribersDB;
+use crate::db::init::{add_column_if_not_exists, Database};
 use crate::db::internal::error::PPResult;
+use crate::db::internal::ids;
+use crate::db::internal::validate;
 use crate::db::user::UsersDB;
 use crate::server::message::types::chat::Chat;
 use crate::server::message::types::chat::ChatDetails;
 use crate::server::message::types::chat::ChatId;
+use crate::server::message::types::chat::{GroupAdmin, Permission, Permissions, Role};
+use crate::server::message::types::request::send::MessageId;
 use crate::server::message::types::user::UserId;
 
 pub struct ChatsDB {
     session: Arc<scylla::Session>,
 }
 
-// to avoid confusion
-pub type InvitationHash = String;
-
 impl Database for ChatsDB {
     fn new(session: Arc<scylla::Session>) -> Self {
         Self {
@@ -40,12 +42,29 @@ impl Database for ChatsDB {
                 name TEXT,
                 avatar_hash TEXT,
                 tag TEXT,
-                invitation_hash TEXT
+                invitation_hash TEXT,
+                owner_id int,
+                pinned LIST<int>,
+                auto_delete int,
+                admins MAP<int, int>,
+                member_permissions int,
+                banned SET<int>,
+                is_public boolean,
+                is_channel boolean
             );
         "#;
 
         self.session.query_unpaged(create_table_query, &[]).await?;
+        add_column_if_not_exists(&self.session, "chats", "owner_id", "int").await?;
+        add_column_if_not_exists(&self.session, "chats", "pinned", "LIST<int>").await?;
+        add_column_if_not_exists(&self.session, "chats", "auto_delete", "int").await?;
+        add_column_if_not_exists(&self.session, "chats", "admins", "MAP<int, int>").await?;
+        add_column_if_not_exists(&self.session, "chats", "member_permissions", "int").await?;
+        add_column_if_not_exists(&self.session, "chats", "banned", "SET<int>").await?;
+        add_column_if_not_exists(&self.session, "chats", "is_public", "boolean").await?;
+        add_column_if_not_exists(&self.session, "chats", "is_channel", "boolean").await?;
         self.session.query_unpaged("CREATE INDEX IF NOT EXISTS chats_invitation_hash_idx ON ksp.chats (invitation_hash)", &[]).await?;
+        self.session.query_unpaged("CREATE INDEX IF NOT EXISTS chats_tag_idx ON ksp.chats (tag)", &[]).await?;
 
         Ok(())
     }
@@ -57,58 +76,197 @@ impl ChatsDB {
         self_user_id: &UserId,
         with_user_id: &UserId,
     ) -> PPResult<(Chat, ChatDetails)> {
-        let chat_id = rand::thread_rng().gen_range(1..i32::MAX);
-        let insert_query = "INSERT INTO ksp.chats (id, is_group, participants) VALUES (?, ?, ?)";
+        let insert_query =
+            "INSERT INTO ksp.chats (id, is_group, participants) VALUES (?, ?, ?) IF NOT EXISTS";
+        let prepared = self.session.prepare(insert_query).await?;
+        let mut participants = vec![
+            self_user_id.as_i32_unchecked(),
+            with_user_id.as_i32_unchecked(),
+        ];
+        // Saved Messages, the chat with yourself
+        participants.dedup();
+
+        let chat_id = ids::insert_with_unique_id(1..i32::MAX, |chat_id| {
+            let values = (chat_id, false, participants.clone());
+            let prepared = &prepared;
+            async move {
+                let result = self.session.execute_unpaged(prepared, values).await?;
+                Ok(ids::lwt_result(result)?.0)
+            }
+        })
+        .await?;
+
+        Ok(self.fetch_chat(with_user_id, chat_id).await?.unwrap())
+    }
+
+    pub async fn create_group(
+        &self,
+        self_user_id: &UserId,
+        participants: Vec<UserId>,
+        details: ChatDetails,
+    ) -> PPResult<(Chat, ChatDetails)> {
+        if let Some(tag) = details.tag() {
+            self.check_tag_available(tag).await?;
+        } else if details.is_public {
+            return Err(PPError::from("Public groups must have a tag!"));
+        }
+
+        let insert_query = "INSERT INTO ksp.chats (id, is_group, participants, name, avatar_hash, tag, owner_id, is_public, is_channel) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS";
         let prepared = self.session.prepare(insert_query).await?;
+        let participants = participants
+            .iter()
+            .map(|u| u.as_i32_unchecked())
+            .collect::<Vec<i32>>();
+
+        let chat_id = ids::insert_with_unique_id(i32::MIN..-1, |chat_id| {
+            let values = (
+                chat_id,
+                true,
+                participants.clone(),
+                details.name(),
+                details.photo().map_or("", |v| v),
+                details.tag().map_or("", |v| v),
+                self_user_id.as_i32_unchecked(),
+                details.is_public,
+                details.is_channel,
+            );
+            let prepared = &prepared;
+            async move {
+                let result = self.session.execute_unpaged(prepared, values).await?;
+                Ok(ids::lwt_result(result)?.0)
+            }
+        })
+        .await?;
+
+        // The owner is the first subscriber of the channel
+        if details.is_channel {
+            let subscribers_db: SubscribersDB =
+                DatabaseBuilder::from_raw(self.session.clone()).into();
+            subscribers_db.subscribe(chat_id, self_user_id).await?;
+        }
+
+        Ok(self.fetch_chat(self_user_id, chat_id).await?.unwrap())
+    }
+
+    pub async fn add_participant(
+        &self,
+        chat_id: ChatId,
+        participant: &UserId,
+    ) -> Result<(), PPError> {
+        let update_query = "UPDATE ksp.chats SET participants = participants + ? WHERE id = ?;";
+        let prepared = self.session.prepare(update_query).await?;
+        self.session
+            .execute_unpaged(&prepared, (vec![participant.as_i32_unchecked()], chat_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Adds the user to the group, channels only get a new subscriber
+    pub async fn add_member(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<()> {
+        if self.is_channel(chat_id).await? {
+            let subscribers_db: SubscribersDB =
+                DatabaseBuilder::from_raw(self.session.clone()).into();
+            subscribers_db.subscribe(chat_id, user_id).await?;
+            return Ok(());
+        }
+
+        self.add_participant(chat_id, user_id).await
+    }
+
+    /// Also unsubscribes the user, if the chat is a channel
+    pub async fn remove_participant(
+        &self,
+        chat_id: ChatId,
+        participant: &UserId,
+    ) -> Result<(), PPError> {
+        let update_query = "UPDATE ksp.chats SET participants = participants - ? WHERE id = ?;";
+        let prepared = self.session.prepare(update_query).await?;
+        self.session
+            .execute_unpaged(&prepared, (vec![participant.as_i32_unchecked()], chat_id))
+            .await?;
+        self.remove_admin(chat_id, participant).await?;
+
+        if self.is_channel(chat_id).await? {
+            let subscribers_db: SubscribersDB =
+                DatabaseBuilder::from_raw(self.session.clone()).into();
+            subscribers_db.unsubscribe(chat_id, participant).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Banned users can't join the group again, until they are unbanned
+    pub async fn ban(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<()> {
+        let query = "UPDATE ksp.chats SET banned = banned + ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
         self.session
             .execute_unpaged(
                 &prepared,
-                (
-                    chat_id,
-                    false,
-                    vec![
-                        self_user_id.as_i32_unchecked(),
-                        with_user_id.as_i32_unchecked(),
-                    ],
-                ),
+                (HashSet::from([user_id.as_i32_unchecked()]), chat_id),
             )
             .await?;
 
-        Ok(self.fetch_chat(with_user_id, chat_id).await?.unwrap())
+        Ok(())
     }
 
-    /// Creates new unique invitation hash for a group
-    ///
-    /// e.g. For others to join it
-    pub async fn create_invitation_hash(&self, group_chat_id: i32) -> PPResult<InvitationHash> {
-        let hash: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(14)
-            .map(char::from)
-            .collect();
-        let new_invitation_hash = format!("+{}", hash);
-
-        let update_query = "UPDATE ksp.chats SET invitation_hash = ? WHERE id = ?";
-        let prepared = self.session.prepare(update_query).await?;
+    pub async fn unban(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<()> {
+        let query = "UPDATE ksp.chats SET banned = banned - ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
         self.session
-            .execute_unpaged(&prepared, (new_invitation_hash.as_str(), group_chat_id))
+            .execute_unpaged(
+                &prepared,
+                (HashSet::from([user_id.as_i32_unchecked()]), chat_id),
+            )
             .await?;
 
-        Ok(new_invitation_hash)
+        Ok(())
     }
 
-    pub async fn get_chat_by_invitation_hash(
+    pub async fn fetch_banned(&self, chat_id: ChatId) -> PPResult<Vec<i32>> {
+        let query = "SELECT banned FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        let banned = self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(Option<Vec<i32>>,)>()?
+            .try_next()
+            .await?
+            .ok_or("Chat wasn't found!")?
+            .0;
+
+        Ok(banned.unwrap_or_default())
+    }
+
+    pub async fn is_banned(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<bool> {
+        Ok(self
+            .fetch_banned(chat_id)
+            .await?
+            .contains(&user_id.as_i32_unchecked()))
+    }
+
+    pub async fn chat_exists(&self, chat_id: ChatId) -> PPResult<bool> {
+        let query = "SELECT * FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        let res = self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        Ok(res.is_rows())
+    }
+
+    /// Fetch chat by real chat id
+    pub async fn fetch_chat(
         &self,
         self_user_id: &UserId,
-        invitation_hash: InvitationHash,
+        chat_id: ChatId,
     ) -> PPResult<Option<(Chat, ChatDetails)>> {
         let select_query =
-            "SELECT id, is_group, participants, name, avatar_hash, tag FROM ksp.chats WHERE invitation_hash = ?";
+            "SELECT id, is_group, participants, name, avatar_hash, tag, is_public, is_channel FROM ksp.chats WHERE id = ?";
 
         let prepared = self.session.prepare(select_query).await?;
         let res = self
             .session
-            .execute_iter(prepared, (invitation_hash,))
+            .execute_iter(prepared, (chat_id,))
             .await?
             .rows_stream::<(
                 i32,
@@ -117,14 +275,21 @@ impl ChatsDB {
                 Option<String>,
                 Option<String>,
                 Option<String>,
+                Option<bool>,
+                Option<bool>,
             )>()?
             .try_next()
             .await?;
 
-        if let Some((chat_id, is_group, participants, name, avatar_hash, tag)) = res {
-            let users_db: UsersDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        if let Some((chat_id, is_group, participants, name, avatar_hash, tag, is_public, is_channel)) =
+            res
+        {
+            let is_channel = is_channel.unwrap_or_default();
+            // can be bitcasted, because UsersDB and ChatsDB are actually the same
+            let users_db: UsersDB = unsafe { std::mem::transmute(self.session.clone()) };
 
             let mut users = vec![];
+
             for participant in participants {
                 let user = users_db.fetch_user(&participant.into()).await?;
                 if let Some(user) = user {
@@ -132,7 +297,7 @@ impl ChatsDB {
                 }
             }
 
-            let chat = Chat::construct(chat_id, is_group, users);
+            let chat = Chat::construct(chat_id, is_group, is_channel, users);
             let details = if is_group {
                 ChatDetails {
                     name: name.unwrap_or("".into()),
@@ -141,6 +306,8 @@ impl ChatsDB {
                     color: None,
                     photo: avatar_hash.filter(|hash| !hash.is_empty()),
                     tag: tag.filter(|tag| !tag.is_empty()),
+                    is_public: is_public.unwrap_or_default(),
+                    is_channel,
                 }
             } else {
                 chat.get_personal_chat_details(self_user_id).await?
@@ -148,117 +315,573 @@ impl ChatsDB {
 
             return Ok(Some((chat, details)));
         }
+
         Ok(None)
     }
 
-    pub async fn create_group(
+    /// User ids of the participants, None if the chat doesn't exist
+    pub async fn fetch_participants(&self, chat_id: ChatId) -> PPResult<Option<Vec<i32>>> {
+        let query = "SELECT participants FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(Option<Vec<i32>>,)>()?
+            .try_next()
+            .await?
+            .map(|v| v.0.unwrap_or_default()))
+    }
+
+    /// Role and permissions of the user, None if the user isn't a participant
+    ///
+    /// Participants of private chats may do anything.
+    /// Groups, created before owners were stored, may be managed by anyone.
+    /// Subscribers of channels are members without any permissions
+    pub async fn fetch_role(
         &self,
-        self_user_id: &UserId,
-        participants: Vec<UserId>,
-        details: ChatDetails,
-    ) -> PPResult<(Chat, ChatDetails)> {
-        let chat_id = rand::thread_rng().gen_range(i32::MIN..-1);
-        let insert_query = "INSERT INTO ksp.chats (id, is_group, participants, name, avatar_hash, tag) VALUES (?, ?, ?, ?, ?, ?)";
+        chat_id: ChatId,
+        user_id: &UserId,
+    ) -> PPResult<Option<(Role, Permissions)>> {
+        let query = "SELECT is_group, participants, owner_id, admins, member_permissions, is_channel FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        let (is_group, participants, owner_id, admins, member_permissions, is_channel) = self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(
+                bool,
+                Option<Vec<i32>>,
+                Option<i32>,
+                Option<HashMap<i32, i32>>,
+                Option<i32>,
+                Option<bool>,
+            )>()?
+            .try_next()
+            .await?
+            .ok_or("Chat wasn't found!")?;
+
+        let is_channel = is_channel.unwrap_or_default();
+        let participants = participants.unwrap_or_default();
+        if !participants.contains(&user_id.as_i32_unchecked()) {
+            let subscribers_db: SubscribersDB =
+                DatabaseBuilder::from_raw(self.session.clone()).into();
+            if !is_channel || !subscribers_db.is_subscriber(chat_id, user_id).await? {
+                return Ok(None);
+            }
+        }
+        let user_id = user_id.as_i32_unchecked();
+        if !is_group {
+            return Ok(Some((Role::Member, Permissions::ALL)));
+        }
 
-        let prepared = self.session.prepare(insert_query).await?;
-        self.session
-            .execute_iter(
-                prepared,
-                (
-                    chat_id,
-                    true,
-                    participants
-                        .iter()
-                        .map(|u| u.as_i32_unchecked())
-                        .collect::<Vec<i32>>(),
-                    details.name(),
-                    details.photo().map_or("", |v| v),
-                    details.tag().map_or("", |v| v),
+        // Groups created before owners existed are owned by their first participant
+        let owner_id = match (owner_id, participants.first()) {
+            (None, Some(&first)) => Some(self.backfill_owner(chat_id, first).await?),
+            (owner_id, _) => owner_id,
+        };
+
+        let role = match owner_id {
+            Some(owner_id) if owner_id == user_id => (Role::Owner, Permissions::ALL),
+            _ => match admins.unwrap_or_default().get(&user_id) {
+                Some(&bits) => (Role::Admin, Permissions::from_bits(bits)),
+                None if is_channel => (Role::Member, Permissions::default()),
+                None => (
+                    Role::Member,
+                    member_permissions.map_or(Permissions::MEMBER_DEFAULT, Permissions::from_bits),
                 ),
+            },
+        };
+
+        Ok(Some(role))
+    }
+
+    /// Makes `user_id` the owner of the group, unless it already has one
+    ///
+    /// Returns the owner
+    async fn backfill_owner(&self, chat_id: ChatId, user_id: i32) -> PPResult<i32> {
+        let query = "UPDATE ksp.chats SET owner_id = ? WHERE id = ? IF owner_id = null";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (user_id, chat_id))
+            .await?;
+
+        let (applied, row) = ids::lwt_result(result)?;
+        if applied {
+            return Ok(user_id);
+        }
+
+        Ok(row.get("owner_id").copied().flatten().unwrap_or(user_id))
+    }
+
+    /// Passes the group of the leaving `owner` to the oldest admin or, if there are none,
+    /// to the oldest participant. The owner is cleared, when nobody else is left
+    ///
+    /// Returns the new owner, None if `owner` doesn't own the group or nobody is left
+    pub async fn hand_over_ownership(
+        &self,
+        chat_id: ChatId,
+        owner: &UserId,
+    ) -> PPResult<Option<i32>> {
+        let query = "SELECT owner_id, participants, admins FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        let Some((owner_id, participants, admins)) = self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(Option<i32>, Option<Vec<i32>>, Option<HashMap<i32, i32>>)>()?
+            .try_next()
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let owner = owner.as_i32_unchecked();
+        let participants = participants.unwrap_or_default();
+        // Groups without a stored owner are owned by their first participant
+        if owner_id.or(participants.first().copied()) != Some(owner) {
+            return Ok(None);
+        }
+
+        // Participants are kept in the order they joined
+        let admins = admins.unwrap_or_default();
+        let mut others = participants.into_iter().filter(|&u| u != owner);
+        let successor = others
+            .clone()
+            .find(|u| admins.contains_key(u))
+            .or_else(|| others.next());
+
+        let query =
+            "UPDATE ksp.chats SET owner_id = ?, admins = admins - ? WHERE id = ? IF owner_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(
+                &prepared,
+                (successor, Vec::from_iter(successor), chat_id, owner_id),
             )
             .await?;
 
-        Ok(self.fetch_chat(self_user_id, chat_id).await?.unwrap())
+        let (applied, _) = ids::lwt_result(result)?;
+        Ok(successor.filter(|_| applied))
     }
 
-    pub async fn add_participant(
+    /// Fails if the user isn't a participant or isn't allowed to do it
+    pub async fn check_permission(
         &self,
         chat_id: ChatId,
-        participant: &UserId,
-    ) -> Result<(), PPError> {
-        let update_query = "UPDATE ksp.chats SET participants = participants + ? WHERE id = ?;";
-        let prepared = self.session.prepare(update_query).await?;
+        user_id: &UserId,
+        permission: Permission,
+    ) -> PPResult<()> {
+        let (_, permissions) = self
+            .fetch_role(chat_id, user_id)
+            .await?
+            .ok_or("You aren't a participant of the chat!")?;
+
+        if !permissions.allows(permission) {
+            return Err(format!(
+                "You don't have the permission to {}!",
+                permission.description()
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Whether the user is the owner or an admin of the group
+    pub async fn is_admin(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<bool> {
+        Ok(matches!(
+            self.fetch_role(chat_id, user_id).await?,
+            Some((Role::Owner | Role::Admin, _))
+        ))
+    }
+
+    /// Returns `(owner_id, admins, member_permissions)` of the group
+    pub async fn fetch_roles(
+        &self,
+        chat_id: ChatId,
+    ) -> PPResult<(Option<i32>, Vec<GroupAdmin>, Permissions)> {
+        let query = "SELECT owner_id, admins, member_permissions FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        let (owner_id, admins, member_permissions) = self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(Option<i32>, Option<HashMap<i32, i32>>, Option<i32>)>()?
+            .try_next()
+            .await?
+            .ok_or("Chat wasn't found!")?;
+
+        let mut admins: Vec<GroupAdmin> = admins
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(user_id, bits)| GroupAdmin {
+                user_id,
+                permissions: Permissions::from_bits(bits),
+            })
+            .collect();
+        admins.sort_unstable_by_key(|admin| admin.user_id);
+
+        Ok((
+            owner_id,
+            admins,
+            member_permissions.map_or(Permissions::MEMBER_DEFAULT, Permissions::from_bits),
+        ))
+    }
+
+    /// Makes the participant an admin or changes permissions of an existing one
+    ///
+    /// Admins of channels become participants, so they receive events of the staff
+    pub async fn set_admin(
+        &self,
+        chat_id: ChatId,
+        user_id: &UserId,
+        permissions: Permissions,
+    ) -> PPResult<()> {
+        let query = "UPDATE ksp.chats SET admins[?] = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
         self.session
-            .execute_unpaged(&prepared, (vec![participant.as_i32_unchecked()], chat_id))
+            .execute_unpaged(
+                &prepared,
+                (user_id.as_i32_unchecked(), permissions.to_bits(), chat_id),
+            )
             .await?;
 
+        if self.is_channel(chat_id).await?
+            && !self
+                .fetch_participants(chat_id)
+                .await?
+                .unwrap_or_default()
+                .contains(&user_id.as_i32_unchecked())
+        {
+            self.add_participant(chat_id, user_id).await?;
+        }
+
         Ok(())
     }
 
-    pub async fn chat_exists(&self, chat_id: ChatId) -> PPResult<bool> {
-        let query = "SELECT * FROM ksp.chats WHERE id = ?";
+    /// Admins of channels are left only as subscribers
+    pub async fn remove_admin(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<()> {
+        let query = "DELETE admins[?] FROM ksp.chats WHERE id = ?";
         let prepared = self.session.prepare(query).await?;
-        let res = self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+        self.session
+            .execute_unpaged(&prepared, (user_id.as_i32_unchecked(), chat_id))
+            .await?;
 
-        Ok(res.is_rows())
+        if self.is_channel(chat_id).await? {
+            let query = "UPDATE ksp.chats SET participants = participants - ? WHERE id = ?;";
+            let prepared = self.session.prepare(query).await?;
+            self.session
+                .execute_unpaged(&prepared, (vec![user_id.as_i32_unchecked()], chat_id))
+                .await?;
+        }
+
+        Ok(())
     }
 
-    /// Fetch chat by real chat id
-    pub async fn fetch_chat(
+    /// Permissions of the participants, who aren't admins
+    pub async fn set_member_permissions(
         &self,
-        self_user_id: &UserId,
         chat_id: ChatId,
-    ) -> PPResult<Option<(Chat, ChatDetails)>> {
-        let select_query =
-            "SELECT id, is_group, participants, name, avatar_hash, tag FROM ksp.chats WHERE id = ?";
+        permissions: Permissions,
+    ) -> PPResult<()> {
+        let query = "UPDATE ksp.chats SET member_permissions = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (permissions.to_bits(), chat_id))
+            .await?;
 
-        let prepared = self.session.prepare(select_query).await?;
-        let res = self
+        Ok(())
+    }
+
+    /// Pinned message ids, in order of pinning
+    pub async fn fetch_pinned(&self, chat_id: ChatId) -> PPResult<Vec<MessageId>> {
+        let query = "SELECT pinned FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
             .session
             .execute_iter(prepared, (chat_id,))
             .await?
-            .rows_stream::<(
-                i32,
-                bool,
-                Vec<i32>,
-                Option<String>,
-                Option<String>,
-                Option<String>,
-            )>()?
+            .rows_stream::<(Option<Vec<i32>>,)>()?
             .try_next()
+            .await?
+            .and_then(|v| v.0)
+            .unwrap_or_default())
+    }
+
+    /// Returns false if the message is already pinned
+    pub async fn pin_message(&self, chat_id: ChatId, message_id: MessageId) -> PPResult<bool> {
+        if self.fetch_pinned(chat_id).await?.contains(&message_id) {
+            return Ok(false);
+        }
+
+        let query = "UPDATE ksp.chats SET pinned = pinned + ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (vec![message_id], chat_id))
             .await?;
 
-        if let Some((chat_id, is_group, participants, name, avatar_hash, tag)) = res {
-            // can be bitcasted, because UsersDB and ChatsDB are actually the same
-            let users_db: UsersDB = unsafe { std::mem::transmute(self.session.clone()) };
+        Ok(true)
+    }
 
-            let mut users = vec![];
+    pub async fn unpin_message(&self, chat_id: ChatId, message_id: MessageId) -> PPResult<()> {
+        let query = "UPDATE ksp.chats SET pinned = pinned - ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (vec![message_id], chat_id))
+            .await?;
 
-            for participant in participants {
-                let user = users_db.fetch_user(&participant.into()).await?;
-                if let Some(user) = user {
-                    users.push(user)
-                }
+        Ok(())
+    }
+
+    pub async fn clear_pinned(&self, chat_id: ChatId) -> PPResult<()> {
+        let query = "DELETE pinned FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        Ok(())
+    }
+
+    /// Default expiry timer of new messages in seconds
+    pub async fn fetch_auto_delete(&self, chat_id: ChatId) -> PPResult<Option<u32>> {
+        let query = "SELECT auto_delete FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(Option<i32>,)>()?
+            .try_next()
+            .await?
+            .and_then(|v| v.0)
+            .map(|v| v as u32))
+    }
+
+    /// Disables the timer if `ttl_seconds` is None
+    pub async fn set_auto_delete(&self, chat_id: ChatId, ttl_seconds: Option<u32>) -> PPResult<()> {
+        if let Some(ttl_seconds) = ttl_seconds {
+            validate::validate_ttl(ttl_seconds)?;
+        }
+
+        let query = "UPDATE ksp.chats SET auto_delete = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (ttl_seconds.map(|v| v as i32), chat_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Whether some group already has the tag
+    pub async fn tag_exists(&self, tag: &str) -> PPResult<bool> {
+        let query = "SELECT id FROM ksp.chats WHERE tag = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (tag,))
+            .await?
+            .rows_stream::<(i32,)>()?
+            .try_next()
+            .await?
+            .is_some())
+    }
+
+    /// Tags of groups share the namespace with usernames
+    async fn check_tag_available(&self, tag: &str) -> PPResult<()> {
+        validate::validate_username(tag)?;
+
+        let users_db: UsersDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        if users_db.exists(&tag.into()).await? || self.tag_exists(tag).await? {
+            return Err(PPError::from("Tag already taken"));
+        }
+
+        Ok(())
+    }
+
+    pub async fn update_name(&self, chat_id: ChatId, name: &str) -> PPResult<()> {
+        validate::validate_name(name)?;
+
+        let query = "UPDATE ksp.chats SET name = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (name, chat_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Empty hash removes the avatar
+    pub async fn update_avatar(&self, chat_id: ChatId, avatar_hash: &str) -> PPResult<()> {
+        let query = "UPDATE ksp.chats SET avatar_hash = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (avatar_hash, chat_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Empty tag removes it
+    pub async fn update_tag(&self, chat_id: ChatId, tag: &str) -> PPResult<()> {
+        let query = "SELECT tag FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        let current = self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(Option<String>,)>()?
+            .try_next()
+            .await?
+            .ok_or("Chat wasn't found!")?
+            .0;
+        if current.as_deref() == Some(tag) {
+            return Ok(());
+        }
+        if !tag.is_empty() {
+            self.check_tag_available(tag).await?;
+        } else if self.is_public(chat_id).await? {
+            return Err(PPError::from("Public groups must have a tag!"));
+        }
+
+        let query = "UPDATE ksp.chats SET tag = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (tag, chat_id)).await?;
+
+        Ok(())
+    }
+
+    pub async fn is_channel(&self, chat_id: ChatId) -> PPResult<bool> {
+        let query = "SELECT is_channel FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(Option<bool>,)>()?
+            .try_next()
+            .await?
+            .and_then(|v| v.0)
+            .unwrap_or_default())
+    }
+
+    pub async fn is_public(&self, chat_id: ChatId) -> PPResult<bool> {
+        let query = "SELECT is_public FROM ksp.chats WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(Option<bool>,)>()?
+            .try_next()
+            .await?
+            .and_then(|v| v.0)
+            .unwrap_or_default())
+    }
+
+    pub async fn set_public(&self, chat_id: ChatId, is_public: bool) -> PPResult<()> {
+        if is_public {
+            let query = "SELECT tag FROM ksp.chats WHERE id = ?";
+            let prepared = self.session.prepare(query).await?;
+            let tag = self
+                .session
+                .execute_iter(prepared, (chat_id,))
+                .await?
+                .rows_stream::<(Option<String>,)>()?
+                .try_next()
+                .await?
+                .and_then(|v| v.0);
+            if tag.is_none_or(|tag| tag.is_empty()) {
+                return Err(PPError::from("Public groups must have a tag!"));
             }
+        }
 
-            let chat = Chat::construct(chat_id, is_group, users);
-            let details = if is_group {
-                ChatDetails {
-                    name: name.unwrap_or("".into()),
-                    chat_id,
-                    is_group: true,
-                    color: None,
-                    photo: avatar_hash.filter(|hash| !hash.is_empty()),
-                    tag: tag.filter(|tag| !tag.is_empty()),
-                }
-            } else {
-                chat.get_personal_chat_details(self_user_id).await?
-            };
+        let query = "UPDATE ksp.chats SET is_public = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (is_public, chat_id))
+            .await?;
 
-            return Ok(Some((chat, details)));
+        Ok(())
+    }
+
+    /// Id of the public group with the tag, private ones aren't found
+    pub async fn fetch_public_group_id(&self, tag: &str) -> PPResult<Option<ChatId>> {
+        let query = "SELECT id, is_public FROM ksp.chats WHERE tag = ?";
+        let prepared = self.session.prepare(query).await?;
+        let res = self
+            .session
+            .execute_iter(prepared, (tag,))
+            .await?
+            .rows_stream::<(i32, Option<bool>)>()?
+            .try_next()
+            .await?;
+
+        match res {
+            Some((chat_id, Some(true))) => Ok(Some(chat_id)),
+            _ => Ok(None),
         }
+    }
 
-        Ok(None)
+    /// Searches public groups by part of the tag if the query starts with '@', by name otherwise
+    pub async fn fetch_public_groups_by_search_query(
+        &self,
+        query: &str,
+    ) -> PPResult<Vec<ChatDetails>> {
+        if query.is_empty() {
+            return Ok(vec![]);
+        }
+        let (scylla_query, search_query) = match query.strip_prefix('@') {
+            Some(tag) => {
+                if tag.is_empty() {
+                    return Ok(vec![]);
+                }
+                (
+                    "SELECT id, name, avatar_hash, tag, is_channel FROM ksp.chats WHERE is_public = true AND tag LIKE ? LIMIT 50 ALLOW FILTERING;",
+                    format!("%{}%", tag),
+                )
+            }
+            None => (
+                "SELECT id, name, avatar_hash, tag, is_channel FROM ksp.chats WHERE is_public = true AND name LIKE ? LIMIT 50 ALLOW FILTERING;",
+                format!("%{}%", query),
+            ),
+        };
+        let mut rows_stream = self
+            .session
+            .query_iter(scylla_query, (search_query,))
+            .await?
+            .rows_stream::<(
+                i32,
+                Option<String>,
+                Option<String>,
+                Option<String>,
+                Option<bool>,
+            )>()?;
+
+        let mut o = vec![];
+        while let Some((chat_id, name, avatar_hash, tag, is_channel)) =
+            rows_stream.try_next().await?
+        {
+            o.push(ChatDetails {
+                name: name.unwrap_or_default(),
+                chat_id,
+                is_group: true,
+                color: None,
+                photo: avatar_hash.filter(|hash| !hash.is_empty()),
+                tag: tag.filter(|tag| !tag.is_empty()),
+                is_public: true,
+                is_channel: is_channel.unwrap_or_default(),
+            });
+        }
+
+        Ok(o)
     }
 
     /// Deletes a specific chat by its ID
@@ -266,6 +889,9 @@ impl ChatsDB {
         let delete_query = "DELETE FROM ksp.chats WHERE id = ?";
         let prepared = self.session.prepare(delete_query).await?;
         self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        let messages_db: MessagesDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        messages_db.delete_message_ids(chat_id).await?;
         Ok(())
     }
 }
@@ -85,6 +85,17 @@ impl DraftsDB {
             .await?
             .map(|v| v.0))
     }
+
+    /// Deletes drafts of the user in every chat
+    pub async fn delete_drafts(&self, self_user_id: &UserId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.drafts WHERE user_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (self_user_id.as_i32_unchecked(),))
+            .await?;
+
+        Ok(())
+    }
 }
 
 impl From<DatabaseBuilder> for DraftsDB {
@@ -129,4 +129,14 @@ impl HashesDB {
 
         Ok(())
     }
+
+    pub async fn delete_hash(&self, sha256_hash: &str) -> PPResult<()> {
+        let query = "DELETE FROM ksp.hashes WHERE hash = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (sha256_hash,))
+            .await?;
+
+        Ok(())
+    }
 }
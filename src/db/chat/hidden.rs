@@ -0,0 +1,178 @@
+use std::{collections::HashSet, sync::Arc};
+
+use futures::TryStreamExt;
+
+use crate::{
+    db::{
+        bucket::DatabaseBuilder,
+        init::Database,
+        internal::error::{PPError, PPResult},
+    },
+    server::message::types::{
+        chat::ChatId, message::Message, request::send::MessageId, user::UserId,
+    },
+};
+
+/// Per-user tombstones of messages deleted only for that user
+///
+/// Messages themselves stay in the chat for other participants.
+/// `cleared_until` hides everything up to and including that message at once
+pub struct HiddenMessagesDB {
+    session: Arc<scylla::Session>,
+}
+
+impl From<DatabaseBuilder> for HiddenMessagesDB {
+    fn from(value: DatabaseBuilder) -> Self {
+        HiddenMessagesDB {
+            session: value.bucket.get_connection(),
+        }
+    }
+}
+
+impl Database for HiddenMessagesDB {
+    fn new(session: Arc<scylla::Session>) -> Self {
+        Self {
+            session: Arc::clone(&session),
+        }
+    }
+
+    async fn create_table(&self) -> Result<(), PPError> {
+        let create_table_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.hidden_messages (
+                chat_id int,
+                user_id int,
+                message_id int,
+                cleared_until int STATIC,
+                PRIMARY KEY ((chat_id, user_id), message_id)
+            );
+        "#;
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+        Ok(())
+    }
+}
+
+/// Messages hidden for a single user
+#[derive(Default)]
+pub struct HiddenMessages {
+    cleared_until: Option<MessageId>,
+    message_ids: HashSet<MessageId>,
+}
+
+impl HiddenMessages {
+    pub fn is_hidden(&self, message_id: MessageId) -> bool {
+        self.cleared_until.is_some_and(|until| message_id <= until)
+            || self.message_ids.contains(&message_id)
+    }
+}
+
+impl HiddenMessagesDB {
+    pub async fn hide_messages(
+        &self,
+        chat_id: ChatId,
+        user_id: &UserId,
+        message_ids: &[MessageId],
+    ) -> PPResult<()> {
+        let query =
+            "INSERT INTO ksp.hidden_messages (chat_id, user_id, message_id) VALUES (?, ?, ?)";
+        let prepared = self.session.prepare(query).await?;
+        let user_id = user_id.as_i32_unchecked();
+        for &message_id in message_ids {
+            self.session
+                .execute_unpaged(&prepared, (chat_id, user_id, message_id))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Hides every message up to and including `until` for the user
+    pub async fn clear_history(
+        &self,
+        chat_id: ChatId,
+        user_id: &UserId,
+        until: MessageId,
+    ) -> PPResult<()> {
+        let query =
+            "UPDATE ksp.hidden_messages SET cleared_until = ? WHERE chat_id = ? AND user_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (until, chat_id, user_id.as_i32_unchecked()))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_hidden(
+        &self,
+        chat_id: ChatId,
+        user_id: &UserId,
+    ) -> PPResult<HiddenMessages> {
+        let query =
+            "SELECT cleared_until, message_id FROM ksp.hidden_messages WHERE chat_id = ? AND user_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        let mut iter = self
+            .session
+            .execute_iter(prepared, (chat_id, user_id.as_i32_unchecked()))
+            .await?
+            .rows_stream::<(Option<i32>, Option<i32>)>()?;
+
+        let mut hidden = HiddenMessages::default();
+        while let Some((cleared_until, message_id)) = iter.try_next().await? {
+            hidden.cleared_until = cleared_until;
+            hidden.message_ids.extend(message_id);
+        }
+
+        Ok(hidden)
+    }
+
+    /// Drops the messages deleted only for the user
+    pub async fn retain_visible(
+        &self,
+        chat_id: ChatId,
+        user_id: &UserId,
+        messages: &mut Vec<Message>,
+    ) -> PPResult<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let hidden = self.fetch_hidden(chat_id, user_id).await?;
+        messages.retain(|message| !hidden.is_hidden(message.message_id));
+
+        Ok(())
+    }
+
+    /// Drops tombstones of messages deleted for everyone
+    ///
+    /// Ids of deleted messages may be taken again by new ones, which mustn't stay hidden
+    pub async fn forget_messages(
+        &self,
+        chat_id: ChatId,
+        user_ids: &[i32],
+        message_ids: &[MessageId],
+    ) -> PPResult<()> {
+        let query =
+            "DELETE FROM ksp.hidden_messages WHERE chat_id = ? AND user_id = ? AND message_id IN ?";
+        let prepared = self.session.prepare(query).await?;
+        for &user_id in user_ids {
+            self.session
+                .execute_unpaged(&prepared, (chat_id, user_id, message_ids))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete_hidden(&self, chat_id: ChatId, user_ids: &[i32]) -> PPResult<()> {
+        let query = "DELETE FROM ksp.hidden_messages WHERE chat_id = ? AND user_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        for &user_id in user_ids {
+            self.session
+                .execute_unpaged(&prepared, (chat_id, user_id))
+                .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+use rand::{distributions::Alphanumeric, Rng};
+use scylla::DeserializeRow;
+
+use crate::{
+    db::{
+        bucket::DatabaseBuilder,
+        init::Database,
+        internal::{
+            error::{PPError, PPResult},
+            ids,
+        },
+    },
+    server::message::types::chat::{ChatId, Invitation},
+};
+
+const INVITATION_COLUMNS: &str =
+    "link, chat_id, name, creator_id, created_at, expires_at, max_uses, uses, requires_approval";
+
+/// How many times a link is incremented before giving up on concurrent joins
+const MAX_USE_ATTEMPTS: usize = 8;
+
+/// Invitation links of groups, a group may have many of them
+pub struct InvitationsDB {
+    session: Arc<scylla::Session>,
+}
+
+#[derive(DeserializeRow)]
+struct DatabaseInvitation {
+    link: String,
+    chat_id: i32,
+    name: Option<String>,
+    creator_id: i32,
+    created_at: i64,
+    expires_at: Option<i64>,
+    max_uses: Option<i32>,
+    uses: Option<i32>,
+    requires_approval: Option<bool>,
+}
+
+impl From<DatabaseInvitation> for Invitation {
+    fn from(invitation: DatabaseInvitation) -> Self {
+        Invitation {
+            link: invitation.link,
+            chat_id: invitation.chat_id,
+            name: invitation.name.filter(|name| !name.is_empty()),
+            creator_id: invitation.creator_id,
+            created_at: invitation.created_at,
+            expires_at: invitation.expires_at,
+            max_uses: invitation.max_uses.map(|v| v as u32),
+            uses: invitation.uses.unwrap_or_default() as u32,
+            requires_approval: invitation.requires_approval.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<DatabaseBuilder> for InvitationsDB {
+    fn from(value: DatabaseBuilder) -> Self {
+        InvitationsDB {
+            session: value.bucket.get_connection(),
+        }
+    }
+}
+
+impl Database for InvitationsDB {
+    fn new(session: Arc<scylla::Session>) -> Self {
+        Self {
+            session: Arc::clone(&session),
+        }
+    }
+
+    async fn create_table(&self) -> Result<(), PPError> {
+        let create_table_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.invitations (
+                link TEXT PRIMARY KEY,
+                chat_id int,
+                name TEXT,
+                creator_id int,
+                created_at bigint,
+                expires_at bigint,
+                max_uses int,
+                uses int,
+                requires_approval boolean
+            );
+        "#;
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+        self.session
+            .query_unpaged(
+                "CREATE INDEX IF NOT EXISTS invitations_chat_id_idx ON ksp.invitations (chat_id)",
+                &[],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+impl InvitationsDB {
+    /// Stores the invitation under a newly generated unique link
+    pub async fn create_invitation(&self, mut invitation: Invitation) -> PPResult<Invitation> {
+        let hash: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(14)
+            .map(char::from)
+            .collect();
+        invitation.link = format!("+{}", hash);
+        invitation.uses = 0;
+
+        let insert_query = format!(
+            "INSERT INTO ksp.invitations ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            INVITATION_COLUMNS
+        );
+        let prepared = self.session.prepare(insert_query).await?;
+        let result = self
+            .session
+            .execute_unpaged(
+                &prepared,
+                (
+                    invitation.link.as_str(),
+                    invitation.chat_id,
+                    invitation.name.as_deref().unwrap_or(""),
+                    invitation.creator_id,
+                    invitation.created_at,
+                    invitation.expires_at,
+                    invitation.max_uses.map(|v| v as i32),
+                    0,
+                    invitation.requires_approval,
+                ),
+            )
+            .await?;
+        if !ids::lwt_result(result)?.0 {
+            return Err(PPError::Server(
+                "Failed to generate unique invitation link".into(),
+            ));
+        }
+
+        Ok(invitation)
+    }
+
+    pub async fn fetch_invitation(&self, link: &str) -> PPResult<Option<Invitation>> {
+        let query = format!(
+            "SELECT {} FROM ksp.invitations WHERE link = ?",
+            INVITATION_COLUMNS
+        );
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (link,))
+            .await?
+            .rows_stream::<DatabaseInvitation>()?
+            .try_next()
+            .await?
+            .map(Invitation::from))
+    }
+
+    /// Every invitation link of the group, the oldest first
+    pub async fn fetch_invitations(&self, chat_id: ChatId) -> PPResult<Vec<Invitation>> {
+        let query = format!(
+            "SELECT {} FROM ksp.invitations WHERE chat_id = ?",
+            INVITATION_COLUMNS
+        );
+        let prepared = self.session.prepare(query).await?;
+
+        let mut invitations: Vec<Invitation> = self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<DatabaseInvitation>()?
+            .map_ok(Invitation::from)
+            .try_collect()
+            .await?;
+        invitations.sort_by_key(|invitation| invitation.created_at);
+
+        Ok(invitations)
+    }
+
+    /// Counts a use of the link
+    ///
+    /// Returns false if the link can't be used anymore, e.g. the limit was reached by others
+    pub async fn use_invitation(&self, invitation: &Invitation) -> PPResult<bool> {
+        let query = "UPDATE ksp.invitations SET uses = ? WHERE link = ? IF uses = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        let mut uses = invitation.uses as i32;
+        for _ in 0..MAX_USE_ATTEMPTS {
+            if invitation
+                .max_uses
+                .is_some_and(|max_uses| uses >= max_uses as i32)
+            {
+                return Ok(false);
+            }
+
+            let result = self
+                .session
+                .execute_unpaged(&prepared, (uses + 1, invitation.link.as_str(), uses))
+                .await?;
+            let (applied, row) = ids::lwt_result(result)?;
+            if applied {
+                return Ok(true);
+            }
+            match row.get("uses").copied().flatten() {
+                Some(current) => uses = current,
+                // Revoked meanwhile
+                None => return Ok(false),
+            }
+        }
+
+        Err(PPError::Server("Failed to use the invitation link".into()))
+    }
+
+    /// Returns whether the link existed
+    pub async fn revoke_invitation(&self, chat_id: ChatId, link: &str) -> PPResult<bool> {
+        let query = "DELETE FROM ksp.invitations WHERE link = ? IF chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (link, chat_id))
+            .await?;
+
+        Ok(ids::lwt_result(result)?.0)
+    }
+
+    pub async fn delete_invitations(&self, chat_id: ChatId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.invitations WHERE link = ?";
+        let prepared = self.session.prepare(query).await?;
+        for invitation in self.fetch_invitations(chat_id).await? {
+            self.session
+                .execute_unpaged(&prepared, (invitation.link.as_str(),))
+                .await?;
+        }
+
+        Ok(())
+    }
+}
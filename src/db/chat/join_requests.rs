@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+
+use crate::{
+    db::{
+        bucket::DatabaseBuilder,
+        init::Database,
+        internal::{
+            error::{PPError, PPResult},
+            ids,
+        },
+    },
+    server::message::types::{
+        chat::{ChatId, JoinRequest},
+        user::UserId,
+    },
+};
+
+/// Users waiting for an admin to let them into a group
+pub struct JoinRequestsDB {
+    session: Arc<scylla::Session>,
+}
+
+impl From<DatabaseBuilder> for JoinRequestsDB {
+    fn from(value: DatabaseBuilder) -> Self {
+        JoinRequestsDB {
+            session: value.bucket.get_connection(),
+        }
+    }
+}
+
+impl Database for JoinRequestsDB {
+    fn new(session: Arc<scylla::Session>) -> Self {
+        Self {
+            session: Arc::clone(&session),
+        }
+    }
+
+    async fn create_table(&self) -> Result<(), PPError> {
+        let create_table_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.join_requests (
+                chat_id int,
+                user_id int,
+                link TEXT,
+                requested_at bigint,
+                PRIMARY KEY (chat_id, user_id)
+            );
+        "#;
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+        Ok(())
+    }
+}
+
+impl JoinRequestsDB {
+    /// Returns false if the user has already requested to join
+    pub async fn add_request(&self, request: &JoinRequest) -> PPResult<bool> {
+        let query = "INSERT INTO ksp.join_requests (chat_id, user_id, link, requested_at) VALUES (?, ?, ?, ?) IF NOT EXISTS";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(
+                &prepared,
+                (
+                    request.chat_id,
+                    request.user_id,
+                    request.link.as_str(),
+                    request.requested_at,
+                ),
+            )
+            .await?;
+
+        Ok(ids::lwt_result(result)?.0)
+    }
+
+    /// Requests of the group, the oldest first
+    pub async fn fetch_requests(&self, chat_id: ChatId) -> PPResult<Vec<JoinRequest>> {
+        let query = "SELECT user_id, link, requested_at FROM ksp.join_requests WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        let mut requests: Vec<JoinRequest> = self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(i32, String, i64)>()?
+            .map_ok(|(user_id, link, requested_at)| JoinRequest {
+                chat_id,
+                user_id,
+                link,
+                requested_at,
+            })
+            .try_collect()
+            .await?;
+        requests.sort_by_key(|request| request.requested_at);
+
+        Ok(requests)
+    }
+
+    /// Returns whether the request existed
+    pub async fn delete_request(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<bool> {
+        let query = "DELETE FROM ksp.join_requests WHERE chat_id = ? AND user_id = ? IF EXISTS";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (chat_id, user_id.as_i32_unchecked()))
+            .await?;
+
+        Ok(ids::lwt_result(result)?.0)
+    }
+
+    pub async fn delete_requests(&self, chat_id: ChatId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.join_requests WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        Ok(())
+    }
+}
@@ -1,24 +1,48 @@
 use futures::TryStreamExt;
 use log::debug;
+use log::error;
+use log::info;
 use scylla::DeserializeRow;
 use scylla::SerializeRow;
 
 use crate::db::bucket::DatabaseBuilder;
-use crate::db::init::Database;
+use crate::db::chat::chats::ChatsDB;
+use crate::db::chat::hashes::{HashInfo, HashesDB};
+use crate::db::chat::hidden::HiddenMessagesDB;
+use crate::db::chat::reactions::ReactionsDB;
+use crate::db::chat::read_cursors::ReadCursorsDB;
+use crate::db::chat::revisions::RevisionsDB;
+use crate::db::chat::threads::ThreadsDB;
+use crate::db::chat::views::ViewsDB;
+use crate::db::init::{add_column_if_not_exists, Database};
 use crate::db::internal::error::PPError;
 use crate::db::internal::error::PPResult;
-use crate::db::internal::validate::validate_range;
+use crate::db::internal::ids;
+use crate::db::internal::validate::{validate_range, validate_ttl};
+use crate::fs::media::MediaType;
+use crate::fs::search;
 use crate::server::message::types::chat::ChatId;
-use crate::server::message::types::message::Message;
+use crate::server::message::types::message::{
+    ForwardedFrom, MediaKind, Message, ReplyPreview, REPLY_PREVIEW_LENGTH,
+};
 use crate::server::message::types::request::send::*;
 use crate::server::message::types::user::UserId;
 use core::range::RangeInclusive;
+use std::collections::BTreeMap;
 use std::ops::Range;
 use std::sync::Arc;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
 const AS_LAST_MESSAGE_IDX: i32 = -1;
+/// Expiry is bucketed by minutes, see [`expiry_minute`]
+const EXPIRY_BUCKET_SECONDS: i64 = 60;
+/// Compare-and-set retries when allocating a message id
+const MAX_ID_ATTEMPTS: usize = 32;
+/// Columns of [`DatabaseMessage`], in the order of its fields
+const MESSAGE_COLUMNS: &str = "id, is_unread, from_id, chat_id, edited, date, has_reply, \
+    reply_to, has_content, content, has_hashes, sha256_hashes, \
+    forwarded_from_user, forwarded_from_chat, expires_at, edited_at";
 
 pub struct MessagesDB {
     session: Arc<scylla::Session>,
@@ -46,11 +70,49 @@ impl Database for MessagesDB {
                 content TEXT,
                 has_hashes boolean,
                 sha256_hashes LIST<TEXT>,
+                forwarded_from_user int,
+                forwarded_from_chat int,
+                expires_at bigint,
+                edited_at bigint,
                 PRIMARY KEY (chat_id, id)
             ) WITH CLUSTERING ORDER BY (id DESC);
         "#;
 
         self.session.query_unpaged(create_table_query, &[]).await?;
+        add_column_if_not_exists(&self.session, "messages", "forwarded_from_user", "int").await?;
+        add_column_if_not_exists(&self.session, "messages", "forwarded_from_chat", "int").await?;
+        add_column_if_not_exists(&self.session, "messages", "expires_at", "bigint").await?;
+        add_column_if_not_exists(&self.session, "messages", "edited_at", "bigint").await?;
+
+        // Messages with expiry, partitioned by the minute they expire in,
+        // so the sweeper reads only the due partitions
+        self.session
+            .query_unpaged(
+                r#"
+                    CREATE TABLE IF NOT EXISTS ksp.expiring_messages_by_minute (
+                        minute bigint,
+                        chat_id int,
+                        message_id int,
+                        expires_at bigint,
+                        PRIMARY KEY (minute, chat_id, message_id)
+                    );
+                "#,
+                &[],
+            )
+            .await?;
+
+        // Last allocated message id of each chat, advanced with lightweight transactions
+        self.session
+            .query_unpaged(
+                r#"
+                    CREATE TABLE IF NOT EXISTS ksp.message_ids (
+                        chat_id int PRIMARY KEY,
+                        last_id int
+                    );
+                "#,
+                &[],
+            )
+            .await?;
 
         self.session
             .query_unpaged(
@@ -96,35 +158,113 @@ struct DatabaseMessage {
     content: String,
     has_hashes: bool,
     sha256_hashes: Vec<String>,
+    forwarded_from_user: Option<i32>,
+    forwarded_from_chat: Option<i32>,
+    expires_at: Option<i64>,
+    edited_at: Option<i64>,
+}
+
+impl From<DatabaseMessage> for Message {
+    fn from(msg: DatabaseMessage) -> Self {
+        Message {
+            message_id: msg.id,
+            is_unread: msg.is_unread,
+            from_id: msg.from_id,
+            chat_id: msg.chat_id,
+            is_edited: msg.edited,
+            date: msg.date,
+            edited_at: msg.edited_at,
+            reply_to: if msg.has_reply {
+                Some(msg.reply_to)
+            } else {
+                None
+            },
+            content: if msg.has_content {
+                Some(msg.content)
+            } else {
+                None
+            },
+            sha256_hashes: if msg.has_hashes {
+                Some(msg.sha256_hashes)
+            } else {
+                None
+            },
+            forwarded_from: msg.forwarded_from_user.map(|user_id| ForwardedFrom {
+                user_id,
+                chat_id: msg.forwarded_from_chat,
+            }),
+            expires_at: msg.expires_at,
+            reactions: vec![],
+            my_reaction: None,
+            thread: None,
+            reply_preview: None,
+        }
+    }
+}
+
+/// Partition of `ksp.expiring_messages_by_minute`, the message expiring at `expires_at` is in
+pub fn expiry_minute(expires_at: i64) -> i64 {
+    expires_at.div_euclid(EXPIRY_BUCKET_SECONDS)
+}
+
+/// Search index is derived from the database, so failing to update it
+/// must not fail the operation itself
+async fn update_search_index(update: impl std::future::Future<Output = PPResult<()>>) {
+    if let Err(err) = update.await {
+        error!("Failed to update search index: {}", err);
+    }
+}
+
+fn media_kind(info: &HashInfo) -> MediaKind {
+    if !info.is_media {
+        return MediaKind::Document;
+    }
+    match MediaType::try_from(info.file_name.as_str()) {
+        Ok(MediaType::Photo(_)) => MediaKind::Photo,
+        Ok(MediaType::Video(_)) => MediaKind::Video,
+        Err(_) => MediaKind::Document,
+    }
 }
 
 impl MessagesDB {
+    /// Indexes every message in the database, if the search index is empty
+    ///
+    /// e.g. on the first start, or if the index was removed
+    pub async fn build_search_index(&self) -> PPResult<()> {
+        let index = search::message_index()?;
+        if !index.is_empty() {
+            return Ok(());
+        }
+        info!("Search index is empty, indexing all messages");
+
+        let query = "SELECT chat_id, id, has_content, content FROM ksp.messages";
+        let mut iter = self
+            .session
+            .query_iter(query, &[])
+            .await?
+            .rows_stream::<(i32, i32, bool, Option<String>)>()?;
+
+        let mut batch = vec![];
+        while let Some((chat_id, message_id, has_content, content)) = iter.try_next().await? {
+            if let (true, Some(content)) = (has_content, content) {
+                batch.push((chat_id, message_id, content));
+            }
+            if batch.len() >= 1000 {
+                index.index_messages(std::mem::take(&mut batch)).await?;
+            }
+        }
+        index.index_messages(batch).await?;
+        index.commit().await
+    }
+
     pub async fn add_message(
         &self,
         msg: &SendMessageRequest,
         sender_id: &UserId,
         target_chat_id: ChatId,
     ) -> Result<Message, PPError> {
-        let insert_query = r#"
-            INSERT INTO ksp.messages
-                (id, is_unread, from_id, chat_id, edited, date, has_reply,
-                reply_to, has_content, content,
-                has_hashes, sha256_hashes)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-        "#;
-
         let mut v: DatabaseMessage = Default::default();
-        match self.get_latest(target_chat_id).await? {
-            Some(id) => {
-                v.id = id + 1;
-            }
-            None => {
-                v.id = 0;
-            }
-        };
-        let prepared = self.session.prepare(insert_query).await?;
 
-        v.is_unread = true;
         match sender_id {
             UserId::UserId(user_id) => {
                 v.from_id = *user_id;
@@ -134,13 +274,11 @@ impl MessagesDB {
             }
         }
         v.chat_id = target_chat_id;
-        v.edited = false;
-        v.date = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
-        v.has_reply = msg.common.reply_to.is_some();
-        v.reply_to = msg.common.reply_to.unwrap_or(0);
+        let reply_to = self
+            .validate_reply(target_chat_id, msg.common.reply_to)
+            .await?;
+        v.has_reply = reply_to.is_some();
+        v.reply_to = reply_to.unwrap_or(0);
 
         match &msg.content.text {
             Some(content) => {
@@ -163,10 +301,189 @@ impl MessagesDB {
             }
         }
 
+        if let Some(ttl_seconds) = msg.ttl_seconds {
+            validate_ttl(ttl_seconds)?;
+        }
+
+        let message = self.insert_message(v, msg.ttl_seconds).await?;
+        if let Some(reply_to) = message.reply_to {
+            let threads_db: ThreadsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+            threads_db
+                .add_reply(target_chat_id, reply_to, message.message_id, message.from_id)
+                .await?;
+        }
+
+        Ok(message)
+    }
+
+    /// Copies content and hashes of the `original` message into the target chat
+    ///
+    /// Keeps the first origin if the `original` is forwarded itself.
+    /// The copy expires no later than the `original`
+    pub async fn add_forwarded_message(
+        &self,
+        original: &Message,
+        forwarded_from: ForwardedFrom,
+        sender_id: &UserId,
+        target_chat_id: ChatId,
+    ) -> PPResult<Message> {
+        let forwarded_from = original.forwarded_from.clone().unwrap_or(forwarded_from);
+
+        let v = DatabaseMessage {
+            from_id: sender_id.as_i32_unchecked(),
+            chat_id: target_chat_id,
+            has_content: original.content.is_some(),
+            content: original.content.clone().unwrap_or_default(),
+            has_hashes: original.sha256_hashes.is_some(),
+            sha256_hashes: original.sha256_hashes.clone().unwrap_or_default(),
+            forwarded_from_user: Some(forwarded_from.user_id),
+            forwarded_from_chat: forwarded_from.chat_id,
+            ..Default::default()
+        };
+
+        let ttl_seconds = match original.expires_at {
+            Some(expires_at) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as i64;
+                let remaining = (expires_at - now).clamp(1, u32::MAX as i64) as u32;
+                let chats_db: ChatsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+                let auto_delete = chats_db.fetch_auto_delete(target_chat_id).await?;
+                Some(auto_delete.map_or(remaining, |ttl_seconds| ttl_seconds.min(remaining)))
+            }
+            None => None,
+        };
+
+        self.insert_message(v, ttl_seconds).await
+    }
+
+    /// Allocates id and date of the message and stores it
+    ///
+    /// Message expires after `ttl_seconds`, or after the default timer of the chat
+    async fn insert_message(
+        &self,
+        mut v: DatabaseMessage,
+        ttl_seconds: Option<u32>,
+    ) -> PPResult<Message> {
+        let insert_query = r#"
+            INSERT INTO ksp.messages
+                (id, is_unread, from_id, chat_id, edited, date, has_reply,
+                reply_to, has_content, content,
+                has_hashes, sha256_hashes,
+                forwarded_from_user, forwarded_from_chat, expires_at, edited_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#;
+
+        v.id = self.next_message_id(v.chat_id).await?;
+        let prepared = self.session.prepare(insert_query).await?;
+
+        v.is_unread = true;
+        v.edited = false;
+        v.date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let ttl_seconds = match ttl_seconds {
+            Some(ttl_seconds) => Some(ttl_seconds),
+            None => {
+                let chats_db: ChatsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+                chats_db.fetch_auto_delete(v.chat_id).await?
+            }
+        };
+        v.expires_at = ttl_seconds.map(|ttl_seconds| v.date + ttl_seconds as i64);
+
+        let (chat_id, message_id, expires_at) = (v.chat_id, v.id, v.expires_at);
+        let content = v.has_content.then(|| v.content.clone());
         self.session.execute_unpaged(&prepared, v).await?;
 
-        let msg = self.fetch_messages(target_chat_id, -1..0).await?;
-        Ok(msg.into_iter().next().unwrap())
+        if let Some(expires_at) = expires_at {
+            let query = "INSERT INTO ksp.expiring_messages_by_minute (minute, chat_id, message_id, expires_at) VALUES (?, ?, ?, ?)";
+            let prepared = self.session.prepare(query).await?;
+            self.session
+                .execute_unpaged(
+                    &prepared,
+                    (expiry_minute(expires_at), chat_id, message_id, expires_at),
+                )
+                .await?;
+        }
+
+        if let Some(content) = content {
+            update_search_index(async {
+                search::message_index()?
+                    .index_messages(vec![(chat_id, message_id, content)])
+                    .await
+            })
+            .await;
+        }
+
+        let msg = self.fetch_messages(chat_id, message_id..0).await?;
+        msg.into_iter()
+            .next()
+            .ok_or(PPError::from("Failed to fetch sent message!"))
+    }
+
+    /// Allocates the next message id in the chat
+    ///
+    /// Concurrent senders race on a compare-and-set of `ksp.message_ids`,
+    /// so each of them gets its own id. Chats created before the sequence
+    /// existed are continued from their latest message.
+    async fn next_message_id(&self, chat_id: ChatId) -> PPResult<MessageId> {
+        let select_query = "SELECT last_id FROM ksp.message_ids WHERE chat_id = ?";
+        let insert_query =
+            "INSERT INTO ksp.message_ids (chat_id, last_id) VALUES (?, ?) IF NOT EXISTS";
+        let update_query =
+            "UPDATE ksp.message_ids SET last_id = ? WHERE chat_id = ? IF last_id = ?";
+
+        let mut current = self
+            .session
+            .execute_iter(self.session.prepare(select_query).await?, (chat_id,))
+            .await?
+            .rows_stream::<(Option<i32>,)>()?
+            .try_next()
+            .await?
+            .and_then(|v| v.0);
+
+        for _ in 0..MAX_ID_ATTEMPTS {
+            let (next, result) = match current {
+                Some(last_id) => {
+                    let prepared = self.session.prepare(update_query).await?;
+                    let result = self
+                        .session
+                        .execute_unpaged(&prepared, (last_id + 1, chat_id, last_id))
+                        .await?;
+                    (last_id + 1, result)
+                }
+                None => {
+                    let next = self.get_latest(chat_id).await?.map_or(0, |id| id + 1);
+                    let prepared = self.session.prepare(insert_query).await?;
+                    let result = self
+                        .session
+                        .execute_unpaged(&prepared, (chat_id, next))
+                        .await?;
+                    (next, result)
+                }
+            };
+
+            let (applied, row) = ids::lwt_result(result)?;
+            if applied {
+                return Ok(next);
+            }
+            // Someone else was faster, the failed transaction returns the actual value
+            current = row.get("last_id").copied().flatten();
+        }
+
+        Err(PPError::from("Chat is too busy, try again later"))
+    }
+
+    /// Forgets the id sequence of the deleted chat
+    pub async fn delete_message_ids(&self, chat_id: ChatId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.message_ids WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        Ok(())
     }
 
     pub async fn get_latest(&self, chat_id: ChatId) -> Result<Option<MessageId>, PPError> {
@@ -184,6 +501,20 @@ impl MessagesDB {
         Ok(res)
     }
 
+    pub async fn get_oldest(&self, chat_id: ChatId) -> PPResult<Option<MessageId>> {
+        let query = "SELECT id FROM ksp.messages WHERE chat_id = ? ORDER BY id ASC LIMIT 1";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(i32,)>()?
+            .try_next()
+            .await?
+            .map(|v| v.0))
+    }
+
     pub async fn message_exists(
         &self,
         chat_id: ChatId,
@@ -199,6 +530,73 @@ impl MessagesDB {
         Ok(res.is_rows())
     }
 
+    /// Checks that the replied message exists in the chat
+    ///
+    /// Clients used to send `reply_to: 0` for no reply, so it means none
+    /// unless the chat has a message with id 0
+    async fn validate_reply(
+        &self,
+        chat_id: ChatId,
+        reply_to: Option<MessageId>,
+    ) -> PPResult<Option<MessageId>> {
+        let Some(reply_to) = reply_to else {
+            return Ok(None);
+        };
+        if !self.message_exists(chat_id, reply_to).await? {
+            if reply_to == 0 {
+                return Ok(None);
+            }
+            return Err(PPError::from(
+                "Message to reply to wasn't found in the chat!",
+            ));
+        }
+
+        Ok(Some(reply_to))
+    }
+
+    /// Fills `reply_preview` of the given messages of the chat, which reply to existing ones
+    pub async fn attach_reply_previews(
+        &self,
+        chat_id: ChatId,
+        messages: &mut [Message],
+    ) -> PPResult<()> {
+        let mut reply_ids: Vec<MessageId> = messages.iter().filter_map(|m| m.reply_to).collect();
+        reply_ids.sort_unstable();
+        reply_ids.dedup();
+        if reply_ids.is_empty() {
+            return Ok(());
+        }
+
+        let hashes_db: HashesDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        let mut previews = BTreeMap::new();
+        for replied in self.fetch_messages_by_ids(chat_id, &reply_ids).await? {
+            let media = match replied.sha256_hashes.as_ref().and_then(|h| h.first()) {
+                Some(hash) => hashes_db
+                    .fetch_hash(hash)
+                    .await?
+                    .map(|info| media_kind(&info)),
+                None => None,
+            };
+            previews.insert(
+                replied.message_id,
+                ReplyPreview {
+                    message_id: replied.message_id,
+                    from_id: replied.from_id,
+                    text: replied
+                        .content
+                        .map(|text| text.chars().take(REPLY_PREVIEW_LENGTH).collect()),
+                    media,
+                },
+            );
+        }
+
+        for message in messages.iter_mut() {
+            message.reply_preview = message.reply_to.and_then(|id| previews.get(&id).cloned());
+        }
+
+        Ok(())
+    }
+
     pub async fn fetch_messages(
         &self,
         chat_id: ChatId,
@@ -217,24 +615,20 @@ impl MessagesDB {
         let (start, end) = validate_range(RangeInclusive::from(range.start..=range.end))?;
 
         let mut iter = if end != 0 {
-            let query = r#"
-                SELECT id, is_unread, from_id, chat_id, edited, date, has_reply,
-                    reply_to, has_content, content, has_hashes, sha256_hashes
-                    FROM ksp.messages
-                    WHERE chat_id = ? AND id >= ? AND id <= ?;
-            "#;
+            let query = format!(
+                "SELECT {} FROM ksp.messages WHERE chat_id = ? AND id >= ? AND id <= ?",
+                MESSAGE_COLUMNS
+            );
             let prepared = self.session.prepare(query).await?;
             self.session
                 .execute_iter(prepared, (chat_id, start, end))
                 .await?
                 .rows_stream::<DatabaseMessage>()?
         } else {
-            let query = r#"
-                SELECT id, is_unread, from_id, chat_id, edited, date, has_reply,
-                    reply_to, has_content, content, has_hashes, sha256_hashes
-                    FROM ksp.messages
-                    WHERE chat_id = ? AND id = ?;
-            "#;
+            let query = format!(
+                "SELECT {} FROM ksp.messages WHERE chat_id = ? AND id = ?",
+                MESSAGE_COLUMNS
+            );
             let prepared = self.session.prepare(query).await?;
             self.session
                 .execute_iter(prepared, (chat_id, start))
@@ -244,59 +638,135 @@ impl MessagesDB {
 
         let mut output: Vec<Message> = vec![];
         while let Some(msg) = iter.try_next().await? {
-            output.push(Message {
-                message_id: msg.id,
-                is_unread: msg.is_unread,
-                from_id: msg.from_id,
-                chat_id: msg.chat_id,
-                is_edited: msg.edited,
-                date: msg.date,
-                reply_to: if msg.has_reply {
-                    Some(msg.reply_to)
-                } else {
-                    None
-                },
-                content: if msg.has_content {
-                    Some(msg.content)
-                } else {
-                    None
-                },
-                sha256_hashes: if msg.has_hashes {
-                    Some(msg.sha256_hashes)
-                } else {
-                    None
-                },
-            });
+            output.push(msg.into());
         }
 
         Ok(output)
     }
 
-    pub async fn mark_as_read(&self, chat_id: ChatId, msg_ids: &[i32]) -> PPResult<()> {
-        if msg_ids.is_empty() {
-            return Ok(());
+    /// Fetches the messages with the given ids, oldest first. Missing ones are skipped
+    pub async fn fetch_messages_by_ids(
+        &self,
+        chat_id: ChatId,
+        message_ids: &[MessageId],
+    ) -> PPResult<Vec<Message>> {
+        if message_ids.is_empty() {
+            return Ok(vec![]);
         }
 
-        for msg_id in msg_ids {
-            let update_query = "
-                UPDATE ksp.messages
-                SET is_unread = false
-                WHERE chat_id = ? AND id = ?";
-            let prepared = self.session.prepare(update_query).await?;
-            self.session
-                .execute_unpaged(&prepared, (chat_id, msg_id))
-                .await?;
+        let query = format!(
+            "SELECT {} FROM ksp.messages WHERE chat_id = ? AND id IN ? ORDER BY id ASC",
+            MESSAGE_COLUMNS
+        );
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id, message_ids))
+            .await?
+            .rows_stream::<DatabaseMessage>()?
+            .map_ok(Message::from)
+            .try_collect()
+            .await?)
+    }
+
+    /// Fetches up to `limit` messages with ids lower than `before`, latest first
+    pub async fn fetch_before(
+        &self,
+        chat_id: ChatId,
+        before: MessageId,
+        limit: u32,
+    ) -> PPResult<Vec<Message>> {
+        let query = format!(
+            "SELECT {} FROM ksp.messages WHERE chat_id = ? AND id < ? ORDER BY id DESC LIMIT ?",
+            MESSAGE_COLUMNS
+        );
+        self.fetch_rows(query, (chat_id, before, limit as i32)).await
+    }
+
+    /// Fetches up to `limit` messages with ids greater than `after`, oldest first
+    pub async fn fetch_after(
+        &self,
+        chat_id: ChatId,
+        after: MessageId,
+        limit: u32,
+    ) -> PPResult<Vec<Message>> {
+        let query = format!(
+            "SELECT {} FROM ksp.messages WHERE chat_id = ? AND id > ? ORDER BY id ASC LIMIT ?",
+            MESSAGE_COLUMNS
+        );
+        self.fetch_rows(query, (chat_id, after, limit as i32)).await
+    }
+
+    async fn fetch_rows(
+        &self,
+        query: String,
+        values: (ChatId, MessageId, i32),
+    ) -> PPResult<Vec<Message>> {
+        let prepared = self.session.prepare(query).await?;
+        let mut iter = self
+            .session
+            .execute_iter(prepared, values)
+            .await?
+            .rows_stream::<DatabaseMessage>()?;
+
+        let mut output: Vec<Message> = vec![];
+        while let Some(msg) = iter.try_next().await? {
+            output.push(msg.into());
         }
 
-        Ok(())
+        Ok(output)
     }
 
+    /// Returns id of the latest message sent at or before the `date`
+    ///
+    /// Ids grow together with dates, so the first match walking
+    /// the partition from the end is the one
+    pub async fn find_by_date(&self, chat_id: ChatId, date: i64) -> PPResult<Option<MessageId>> {
+        let query = "SELECT id FROM ksp.messages WHERE chat_id = ? AND date <= ? ORDER BY id DESC LIMIT 1 ALLOW FILTERING";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id, date))
+            .await?
+            .rows_stream::<(i32,)>()?
+            .try_next()
+            .await?
+            .map(|v| v.0))
+    }
+
+    /// Fetches every message of the chat, latest first
+    pub async fn fetch_all_messages(&self, chat_id: ChatId) -> PPResult<Vec<Message>> {
+        let query = format!(
+            "SELECT {} FROM ksp.messages WHERE chat_id = ?",
+            MESSAGE_COLUMNS
+        );
+        let prepared = self.session.prepare(query).await?;
+        let mut iter = self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<DatabaseMessage>()?;
+
+        let mut output: Vec<Message> = vec![];
+        while let Some(msg) = iter.try_next().await? {
+            output.push(msg.into());
+        }
+
+        Ok(output)
+    }
+
+    /// Overwrites the message, keeping the previous version in [`RevisionsDB`]
+    /// if content, files or reply were changed
+    ///
+    /// Returns the stored message
     pub async fn edit_message(
         &self,
         msg_id: i32,
         chat_id: ChatId,
-        new_message: Message,
-    ) -> PPResult<()> {
+        mut new_message: Message,
+    ) -> PPResult<Message> {
         let update_query = r#"
             UPDATE ksp.messages
             SET is_unread = ?,
@@ -306,10 +776,52 @@ impl MessagesDB {
                 reply_to = ?,
                 has_hashes = ?,
                 sha256_hashes = ?,
-                edited = ?
+                edited = ?,
+                edited_at = ?
             WHERE chat_id = ? AND id = ?
         "#;
 
+        let previous = self
+            .fetch_messages(chat_id, msg_id..0)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or("Message with the given message_id wasn't found!")?;
+        // Validated before anything is written
+        if previous.reply_to != new_message.reply_to {
+            if new_message
+                .reply_to
+                .is_some_and(|reply_to| reply_to >= msg_id)
+            {
+                return Err(PPError::from("Message can only reply to an earlier one!"));
+            }
+            new_message.reply_to = self.validate_reply(chat_id, new_message.reply_to).await?;
+        }
+        if previous.content != new_message.content
+            || previous.sha256_hashes != new_message.sha256_hashes
+            || previous.reply_to != new_message.reply_to
+        {
+            let edited_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64;
+            let revisions_db: RevisionsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+            revisions_db.add_revision(&previous, edited_at).await?;
+            new_message.edited_at = Some(edited_at);
+        }
+        if previous.reply_to != new_message.reply_to {
+            let threads_db: ThreadsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+            if let Some(reply_to) = previous.reply_to {
+                threads_db.remove_reply(chat_id, reply_to, msg_id).await?;
+            }
+            if let Some(reply_to) = new_message.reply_to {
+                threads_db
+                    .add_reply(chat_id, reply_to, msg_id, previous.from_id)
+                    .await?;
+            }
+        }
+        new_message.is_edited = true;
+
         let prepared = self.session.prepare(update_query).await?;
         self.session
             .execute_unpaged(
@@ -317,29 +829,68 @@ impl MessagesDB {
                 (
                     new_message.is_unread,
                     new_message.content.is_some(),
-                    new_message.content.unwrap_or_default().as_str(),
+                    new_message.content.as_deref().unwrap_or_default(),
                     new_message.reply_to.is_some(),
                     new_message.reply_to.unwrap_or(0),
                     new_message.sha256_hashes.is_some(),
-                    new_message.sha256_hashes,
+                    &new_message.sha256_hashes,
                     true,
+                    new_message.edited_at,
                     chat_id,
                     msg_id,
                 ),
             )
             .await?;
 
-        Ok(())
+        let content = new_message.content.clone();
+        update_search_index(async {
+            let index = search::message_index()?;
+            match content {
+                Some(content) => index.index_messages(vec![(chat_id, msg_id, content)]).await,
+                None => index.remove_messages(chat_id, vec![msg_id]).await,
+            }
+        })
+        .await;
+
+        Ok(new_message)
     }
+
     pub async fn delete_messages(&self, chat_id: ChatId, message_ids: &Vec<i32>) -> PPResult<()> {
+        if message_ids.is_empty() {
+            return Ok(());
+        }
         for msg_id in message_ids {
             self.delete_message(chat_id, *msg_id).await?
         }
 
+        let chats_db: ChatsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        if let Some(participants) = chats_db.fetch_participants(chat_id).await? {
+            let hidden_db: HiddenMessagesDB =
+                DatabaseBuilder::from_raw(self.session.clone()).into();
+            hidden_db
+                .forget_messages(chat_id, &participants, message_ids)
+                .await?;
+        }
+
         Ok(())
     }
 
+    /// Deletes the message with everything attached to it: reactions, views, the thread
+    /// and the edit history. Ids aren't reused, so nothing could reach the history anymore
     pub async fn delete_message(&self, chat_id: ChatId, message_id: i32) -> PPResult<()> {
+        let threads_db: ThreadsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        let (reply_to, expires_at) = self
+            .fetch_messages(chat_id, message_id..0)
+            .await?
+            .pop()
+            .map_or((None, None), |message| {
+                (message.reply_to, message.expires_at)
+            });
+        if let Some(reply_to) = reply_to {
+            threads_db.remove_reply(chat_id, reply_to, message_id).await?;
+        }
+        threads_db.delete_thread(chat_id, message_id).await?;
+
         let delete_query = r#"
             DELETE FROM ksp.messages
             WHERE chat_id = ? AND id = ?
@@ -350,33 +901,185 @@ impl MessagesDB {
             .execute_unpaged(&prepared, (chat_id, message_id))
             .await?;
 
+        if let Some(expires_at) = expires_at {
+            self.forget_expiry(expiry_minute(expires_at), chat_id, message_id)
+                .await?;
+        }
+
+        let reactions_db: ReactionsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        reactions_db.delete_reactions(chat_id, message_id).await?;
+        let revisions_db: RevisionsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        revisions_db.delete_revisions(chat_id, message_id).await?;
+        let views_db: ViewsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        views_db.delete_views(chat_id, &[message_id]).await?;
+        let chats_db: ChatsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        chats_db.unpin_message(chat_id, message_id).await?;
+        update_search_index(async {
+            search::message_index()?
+                .remove_messages(chat_id, vec![message_id])
+                .await
+        })
+        .await;
+
         Ok(())
     }
 
+    async fn forget_expiry(
+        &self,
+        minute: i64,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> PPResult<()> {
+        let query = "DELETE FROM ksp.expiring_messages_by_minute WHERE minute = ? AND chat_id = ? AND message_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (minute, chat_id, message_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// The earliest minute, which still has messages to expire
+    ///
+    /// Reads every partition key, so it's meant to be called once on startup
+    pub async fn fetch_oldest_expiry_minute(&self) -> PPResult<Option<i64>> {
+        let query = "SELECT DISTINCT minute FROM ksp.expiring_messages_by_minute";
+        let mut minutes = self
+            .session
+            .query_iter(query, &[])
+            .await?
+            .rows_stream::<(i64,)>()?;
+
+        let mut oldest = None;
+        while let Some((minute,)) = minutes.try_next().await? {
+            oldest = Some(oldest.map_or(minute, |oldest: i64| oldest.min(minute)));
+        }
+
+        Ok(oldest)
+    }
+
+    /// Returns `(chat_id, message_id)` of every message, that has expired at `now`,
+    /// looking at the minutes from `from_minute` up to the current one
+    ///
+    /// Entries of the messages, that were already deleted, are dropped
+    pub async fn fetch_expired(
+        &self,
+        from_minute: i64,
+        now: i64,
+    ) -> PPResult<Vec<(ChatId, MessageId)>> {
+        let query = "SELECT chat_id, message_id, expires_at FROM ksp.expiring_messages_by_minute WHERE minute = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        let mut due: BTreeMap<ChatId, Vec<(i64, MessageId)>> = BTreeMap::new();
+        for minute in from_minute..=expiry_minute(now) {
+            let mut rows = self
+                .session
+                .execute_iter(prepared.clone(), (minute,))
+                .await?
+                .rows_stream::<(i32, i32, i64)>()?;
+            while let Some((chat_id, message_id, expires_at)) = rows.try_next().await? {
+                if expires_at <= now {
+                    due.entry(chat_id).or_default().push((minute, message_id));
+                }
+            }
+        }
+
+        let query = "SELECT id FROM ksp.messages WHERE chat_id = ? AND id IN ?";
+        let prepared = self.session.prepare(query).await?;
+
+        let mut expired = vec![];
+        for (chat_id, entries) in due {
+            let message_ids: Vec<MessageId> = entries.iter().map(|&(_, id)| id).collect();
+            let existing: Vec<MessageId> = self
+                .session
+                .execute_iter(prepared.clone(), (chat_id, &message_ids))
+                .await?
+                .rows_stream::<(i32,)>()?
+                .map_ok(|v| v.0)
+                .try_collect()
+                .await?;
+
+            for (minute, message_id) in entries {
+                if existing.contains(&message_id) {
+                    expired.push((chat_id, message_id));
+                } else {
+                    self.forget_expiry(minute, chat_id, message_id).await?;
+                }
+            }
+        }
+
+        Ok(expired)
+    }
+
+    /// Deletes every message in the chat, that was sent by the given user
+    pub async fn delete_messages_from(&self, chat_id: ChatId, from_id: &UserId) -> PPResult<()> {
+        let query = "SELECT id FROM ksp.messages WHERE chat_id = ? AND from_id = ? ALLOW FILTERING";
+        let prepared = self.session.prepare(query).await?;
+        let message_ids: Vec<i32> = self
+            .session
+            .execute_iter(prepared, (chat_id, from_id.as_i32_unchecked()))
+            .await?
+            .rows_stream::<(i32,)>()?
+            .map_ok(|v| v.0)
+            .try_collect()
+            .await?;
+
+        self.delete_messages(chat_id, &message_ids).await
+    }
+
     /// Deletes all messages associated with a specific chat
     pub async fn delete_all_messages(&self, chat_id: ChatId) -> PPResult<()> {
         let delete_query = "DELETE FROM ksp.messages WHERE chat_id = ?";
         let prepared = self.session.prepare(delete_query).await?;
         self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        // Entries of ksp.expiring_messages_by_minute are dropped by the sweeper, once they're due
+        let reactions_db: ReactionsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        reactions_db.delete_all_reactions(chat_id).await?;
+        let revisions_db: RevisionsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        revisions_db.delete_all_revisions(chat_id).await?;
+        let views_db: ViewsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        views_db.delete_all_views(chat_id).await?;
+        let threads_db: ThreadsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        threads_db.delete_all_threads(chat_id).await?;
+        let chats_db: ChatsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        chats_db.clear_pinned(chat_id).await?;
+        if let Some(participants) = chats_db.fetch_participants(chat_id).await? {
+            let hidden_db: HiddenMessagesDB =
+                DatabaseBuilder::from_raw(self.session.clone()).into();
+            hidden_db.delete_hidden(chat_id, &participants).await?;
+        }
+        update_search_index(async { search::message_index()?.remove_chat(chat_id).await }).await;
         Ok(())
     }
 
-    pub async fn fetch_unread_count(&self, chat_id: ChatId) -> PPResult<Option<u64>> {
+    /// Counts messages after the read cursor of the user, not including the ones sent by them
+    pub async fn fetch_unread_count(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<u64> {
+        let read_cursors_db: ReadCursorsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        let last_read_id = read_cursors_db
+            .fetch_cursor(chat_id, user_id)
+            .await?
+            .unwrap_or(-1);
+
         let query = r#"
-            SELECT COUNT(*)
+            SELECT from_id
             FROM ksp.messages
-            WHERE chat_id = ? AND is_unread = true;
+            WHERE chat_id = ? AND id > ?;
         "#;
 
         let prepared = self.session.prepare(query).await?;
-        let count = self
+        let mut iter = self
             .session
-            .execute_iter(prepared, (chat_id,))
+            .execute_iter(prepared, (chat_id, last_read_id))
             .await?
-            .rows_stream::<(i64,)>()?
-            .try_next()
-            .await?
-            .map(|v| v.0 as u64);
+            .rows_stream::<(i32,)>()?;
+
+        let mut count = 0;
+        while let Some((from_id,)) = iter.try_next().await? {
+            if from_id != user_id.as_i32_unchecked() {
+                count += 1;
+            }
+        }
 
         Ok(count)
     }
@@ -2,3 +2,13 @@ pub mod chats;
 pub mod hashes;
 pub mod messages;
 pub mod drafts;
+pub mod read_cursors;
+pub mod reactions;
+pub mod scheduled;
+pub mod revisions;
+pub mod hidden;
+pub mod invitations;
+pub mod join_requests;
+pub mod subscribers;
+pub mod views;
+pub mod threads;
@@ -0,0 +1,160 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use futures::TryStreamExt;
+
+use crate::{
+    db::{
+        bucket::DatabaseBuilder,
+        init::Database,
+        internal::{
+            error::{PPError, PPResult},
+            validate,
+        },
+    },
+    server::message::types::{
+        chat::ChatId,
+        message::{Message, ReactionCount},
+        request::send::MessageId,
+        user::UserId,
+    },
+};
+
+/// Every user may put a single reaction on a message
+pub struct ReactionsDB {
+    session: Arc<scylla::Session>,
+}
+
+impl From<DatabaseBuilder> for ReactionsDB {
+    fn from(value: DatabaseBuilder) -> Self {
+        ReactionsDB {
+            session: value.bucket.get_connection(),
+        }
+    }
+}
+
+impl Database for ReactionsDB {
+    fn new(session: Arc<scylla::Session>) -> Self {
+        Self {
+            session: Arc::clone(&session),
+        }
+    }
+
+    async fn create_table(&self) -> Result<(), PPError> {
+        let create_table_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.reactions (
+                chat_id int,
+                message_id int,
+                user_id int,
+                reaction TEXT,
+                PRIMARY KEY (chat_id, message_id, user_id)
+            );
+        "#;
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+        Ok(())
+    }
+}
+
+impl ReactionsDB {
+    /// Puts the reaction, replacing the previous one of the user
+    pub async fn set_reaction(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        user_id: &UserId,
+        reaction: &str,
+    ) -> PPResult<()> {
+        validate::validate_reaction(reaction)?;
+
+        let query = "INSERT INTO ksp.reactions (chat_id, message_id, user_id, reaction) VALUES (?, ?, ?, ?)";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(
+                &prepared,
+                (chat_id, message_id, user_id.as_i32_unchecked(), reaction),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_reaction(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        user_id: &UserId,
+    ) -> PPResult<()> {
+        let query =
+            "DELETE FROM ksp.reactions WHERE chat_id = ? AND message_id = ? AND user_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (chat_id, message_id, user_id.as_i32_unchecked()))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Fills `reactions` and `my_reaction` of the given messages of the chat,
+    /// relative to `self_user_id`
+    pub async fn attach_reactions(
+        &self,
+        chat_id: ChatId,
+        self_user_id: &UserId,
+        messages: &mut [Message],
+    ) -> PPResult<()> {
+        let (Some(min), Some(max)) = (
+            messages.iter().map(|m| m.message_id).min(),
+            messages.iter().map(|m| m.message_id).max(),
+        ) else {
+            return Ok(());
+        };
+
+        let query = "SELECT message_id, user_id, reaction FROM ksp.reactions WHERE chat_id = ? AND message_id >= ? AND message_id <= ?";
+        let prepared = self.session.prepare(query).await?;
+        let mut iter = self
+            .session
+            .execute_iter(prepared, (chat_id, min, max))
+            .await?
+            .rows_stream::<(i32, i32, String)>()?;
+
+        let mut reactions: BTreeMap<MessageId, (BTreeMap<String, u32>, Option<String>)> =
+            BTreeMap::new();
+        while let Some((message_id, user_id, reaction)) = iter.try_next().await? {
+            let (counts, mine) = reactions.entry(message_id).or_default();
+            if user_id == self_user_id.as_i32_unchecked() {
+                *mine = Some(reaction.clone());
+            }
+            *counts.entry(reaction).or_default() += 1;
+        }
+
+        for message in messages.iter_mut() {
+            if let Some((counts, mine)) = reactions.remove(&message.message_id) {
+                message.reactions = counts
+                    .into_iter()
+                    .map(|(reaction, count)| ReactionCount { reaction, count })
+                    .collect();
+                message.my_reaction = mine;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete_reactions(&self, chat_id: ChatId, message_id: MessageId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.reactions WHERE chat_id = ? AND message_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (chat_id, message_id))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_all_reactions(&self, chat_id: ChatId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.reactions WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+
+use crate::{
+    db::{
+        bucket::DatabaseBuilder,
+        init::Database,
+        internal::{
+            error::{PPError, PPResult},
+            ids,
+        },
+    },
+    server::message::types::{
+        chat::ChatId, message::Message, request::send::MessageId, user::UserId,
+    },
+};
+
+/// Stores the latest message each participant has read in a chat
+///
+/// Everything up to and including `last_read_id` is considered read by the user
+pub struct ReadCursorsDB {
+    session: Arc<scylla::Session>,
+}
+
+impl From<DatabaseBuilder> for ReadCursorsDB {
+    fn from(value: DatabaseBuilder) -> Self {
+        ReadCursorsDB {
+            session: value.bucket.get_connection(),
+        }
+    }
+}
+
+impl Database for ReadCursorsDB {
+    fn new(session: Arc<scylla::Session>) -> Self {
+        Self {
+            session: Arc::clone(&session),
+        }
+    }
+
+    async fn create_table(&self) -> Result<(), PPError> {
+        let create_table_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.read_cursors (
+                chat_id int,
+                user_id int,
+                last_read_id int,
+                PRIMARY KEY (chat_id, user_id)
+            );
+        "#;
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+        Ok(())
+    }
+}
+
+impl ReadCursorsDB {
+    /// Moves the cursor of the user forward to `message_id`
+    ///
+    /// Cursor never moves backwards, returns whether it was moved
+    pub async fn advance(
+        &self,
+        chat_id: ChatId,
+        user_id: &UserId,
+        message_id: MessageId,
+    ) -> PPResult<bool> {
+        let update_query = "UPDATE ksp.read_cursors SET last_read_id = ? WHERE chat_id = ? AND user_id = ? IF last_read_id < ?";
+        let insert_query = "INSERT INTO ksp.read_cursors (chat_id, user_id, last_read_id) VALUES (?, ?, ?) IF NOT EXISTS";
+        let user_id = user_id.as_i32_unchecked();
+
+        let prepared = self.session.prepare(update_query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (message_id, chat_id, user_id, message_id))
+            .await?;
+        let (applied, row) = ids::lwt_result(result)?;
+        if applied {
+            return Ok(true);
+        }
+        // Cursor is already further
+        if row.get("last_read_id").copied().flatten().is_some() {
+            return Ok(false);
+        }
+
+        let prepared = self.session.prepare(insert_query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (chat_id, user_id, message_id))
+            .await?;
+        if ids::lwt_result(result)?.0 {
+            return Ok(true);
+        }
+
+        // Lost the race to the first insert, try moving the created cursor once more
+        let prepared = self.session.prepare(update_query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (message_id, chat_id, user_id, message_id))
+            .await?;
+
+        Ok(ids::lwt_result(result)?.0)
+    }
+
+    pub async fn fetch_cursor(
+        &self,
+        chat_id: ChatId,
+        user_id: &UserId,
+    ) -> PPResult<Option<MessageId>> {
+        let query = "SELECT last_read_id FROM ksp.read_cursors WHERE chat_id = ? AND user_id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id, user_id.as_i32_unchecked()))
+            .await?
+            .rows_stream::<(i32,)>()?
+            .try_next()
+            .await?
+            .map(|v| v.0))
+    }
+
+    /// Returns `(user_id, last_read_id)` of every participant who has read something in the chat
+    pub async fn fetch_cursors(&self, chat_id: ChatId) -> PPResult<Vec<(i32, MessageId)>> {
+        let query = "SELECT user_id, last_read_id FROM ksp.read_cursors WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(i32, i32)>()?
+            .try_collect()
+            .await?)
+    }
+
+    /// Fills `is_unread` of the given messages of the chat, relative to `self_user_id`
+    ///
+    /// Message of someone else is unread until the cursor of the user passes it,
+    /// own message is unread until any other participant reads it
+    pub async fn attach_unread(
+        &self,
+        chat_id: ChatId,
+        self_user_id: &UserId,
+        messages: &mut [Message],
+    ) -> PPResult<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let self_user_id = self_user_id.as_i32_unchecked();
+        let mut own_last_read = -1;
+        let mut others_last_read = -1;
+        for (user_id, last_read_id) in self.fetch_cursors(chat_id).await? {
+            if user_id == self_user_id {
+                own_last_read = last_read_id;
+            } else {
+                others_last_read = others_last_read.max(last_read_id);
+            }
+        }
+
+        for message in messages.iter_mut() {
+            message.is_unread = if message.from_id == self_user_id {
+                message.message_id > others_last_read
+            } else {
+                message.message_id > own_last_read
+            };
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete_cursor(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.read_cursors WHERE chat_id = ? AND user_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (chat_id, user_id.as_i32_unchecked()))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_cursors(&self, chat_id: ChatId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.read_cursors WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+use scylla::DeserializeRow;
+
+use crate::{
+    db::{
+        bucket::DatabaseBuilder,
+        init::Database,
+        internal::error::{PPError, PPResult},
+    },
+    server::message::types::{
+        chat::ChatId,
+        message::{Message, MessageRevision},
+        request::send::MessageId,
+    },
+};
+
+/// Every previous version of edited messages
+pub struct RevisionsDB {
+    session: Arc<scylla::Session>,
+}
+
+#[derive(DeserializeRow)]
+struct DatabaseRevision {
+    date: i64,
+    edited_at: i64,
+    content: Option<String>,
+    sha256_hashes: Option<Vec<String>>,
+    reply_to: Option<i32>,
+}
+
+impl From<DatabaseRevision> for MessageRevision {
+    fn from(revision: DatabaseRevision) -> Self {
+        MessageRevision {
+            date: revision.date,
+            edited_at: revision.edited_at,
+            content: revision.content,
+            sha256_hashes: revision.sha256_hashes,
+            reply_to: revision.reply_to,
+        }
+    }
+}
+
+impl From<DatabaseBuilder> for RevisionsDB {
+    fn from(value: DatabaseBuilder) -> Self {
+        RevisionsDB {
+            session: value.bucket.get_connection(),
+        }
+    }
+}
+
+impl Database for RevisionsDB {
+    fn new(session: Arc<scylla::Session>) -> Self {
+        Self {
+            session: Arc::clone(&session),
+        }
+    }
+
+    async fn create_table(&self) -> Result<(), PPError> {
+        let create_table_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.message_revisions (
+                chat_id int,
+                message_id int,
+                revision timeuuid,
+                date bigint,
+                edited_at bigint,
+                content TEXT,
+                sha256_hashes LIST<TEXT>,
+                reply_to int,
+                PRIMARY KEY (chat_id, message_id, revision)
+            );
+        "#;
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+        Ok(())
+    }
+}
+
+impl RevisionsDB {
+    /// Stores the version of the message, that is replaced at `edited_at`
+    pub async fn add_revision(&self, previous: &Message, edited_at: i64) -> PPResult<()> {
+        let query = r#"
+            INSERT INTO ksp.message_revisions
+                (chat_id, message_id, revision, date, edited_at, content, sha256_hashes, reply_to)
+                VALUES (?, ?, now(), ?, ?, ?, ?, ?)
+        "#;
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(
+                &prepared,
+                (
+                    previous.chat_id,
+                    previous.message_id,
+                    previous.edited_at.unwrap_or(previous.date),
+                    edited_at,
+                    &previous.content,
+                    &previous.sha256_hashes,
+                    previous.reply_to,
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Previous versions of the message, the oldest first
+    pub async fn fetch_revisions(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> PPResult<Vec<MessageRevision>> {
+        let query = r#"
+            SELECT date, edited_at, content, sha256_hashes, reply_to
+                FROM ksp.message_revisions
+                WHERE chat_id = ? AND message_id = ?
+        "#;
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id, message_id))
+            .await?
+            .rows_stream::<DatabaseRevision>()?
+            .map_ok(Into::into)
+            .try_collect()
+            .await?)
+    }
+
+    pub async fn delete_revisions(&self, chat_id: ChatId, message_id: MessageId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.message_revisions WHERE chat_id = ? AND message_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (chat_id, message_id))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_all_revisions(&self, chat_id: ChatId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.message_revisions WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        Ok(())
+    }
+}
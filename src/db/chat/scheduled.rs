@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+use scylla::DeserializeRow;
+
+use crate::{
+    db::{
+        bucket::DatabaseBuilder,
+        init::Database,
+        internal::{
+            error::{PPError, PPResult},
+            ids,
+        },
+    },
+    server::message::types::{
+        message::ScheduledMessage,
+        request::send::{CommonFields, MessageContent, SendMessageRequest},
+        user::UserId,
+    },
+};
+
+const SCHEDULED_COLUMNS: &str = "from_id, id, to_id, schedule_at, reply_to, content, sha256_hashes";
+
+/// Messages, which are delivered by the scheduler once `schedule_at` comes
+///
+/// They aren't stored in `ksp.messages` until then, so ids are local to the sender
+pub struct ScheduledMessagesDB {
+    session: Arc<scylla::Session>,
+}
+
+#[derive(DeserializeRow)]
+struct DatabaseScheduledMessage {
+    from_id: i32,
+    id: i32,
+    to_id: i32,
+    schedule_at: i64,
+    reply_to: Option<i32>,
+    content: Option<String>,
+    sha256_hashes: Option<Vec<String>>,
+}
+
+impl From<DatabaseScheduledMessage> for (i32, ScheduledMessage) {
+    fn from(msg: DatabaseScheduledMessage) -> Self {
+        (
+            msg.from_id,
+            ScheduledMessage {
+                scheduled_id: msg.id,
+                chat_id: msg.to_id,
+                schedule_at: msg.schedule_at,
+                reply_to: msg.reply_to,
+                content: msg.content,
+                sha256_hashes: msg.sha256_hashes,
+            },
+        )
+    }
+}
+
+impl From<DatabaseBuilder> for ScheduledMessagesDB {
+    fn from(value: DatabaseBuilder) -> Self {
+        ScheduledMessagesDB {
+            session: value.bucket.get_connection(),
+        }
+    }
+}
+
+impl Database for ScheduledMessagesDB {
+    fn new(session: Arc<scylla::Session>) -> Self {
+        Self {
+            session: Arc::clone(&session),
+        }
+    }
+
+    async fn create_table(&self) -> Result<(), PPError> {
+        let create_table_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.scheduled_messages (
+                from_id int,
+                id int,
+                to_id int,
+                schedule_at bigint,
+                reply_to int,
+                content TEXT,
+                sha256_hashes LIST<TEXT>,
+                PRIMARY KEY (from_id, id)
+            );
+        "#;
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+        Ok(())
+    }
+}
+
+impl ScheduledMessage {
+    /// Request, the message is delivered with
+    pub fn to_send_request(&self) -> SendMessageRequest {
+        SendMessageRequest {
+            common: CommonFields {
+                method: "send_message".into(),
+                to: self.chat_id,
+                reply_to: self.reply_to,
+            },
+            content: MessageContent {
+                text: self.content.clone(),
+                sha256_hashes: self.sha256_hashes.clone(),
+            },
+            schedule_at: None,
+            ttl_seconds: None,
+        }
+    }
+}
+
+impl ScheduledMessagesDB {
+    pub async fn schedule(
+        &self,
+        msg: &SendMessageRequest,
+        sender_id: &UserId,
+        schedule_at: i64,
+    ) -> PPResult<ScheduledMessage> {
+        let insert_query = format!(
+            "INSERT INTO ksp.scheduled_messages ({}) VALUES (?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            SCHEDULED_COLUMNS
+        );
+        let prepared = self.session.prepare(insert_query).await?;
+        let from_id = sender_id.as_i32_unchecked();
+
+        let scheduled_id = ids::insert_with_unique_id(1..i32::MAX, |id| {
+            let values = (
+                from_id,
+                id,
+                msg.common.to,
+                schedule_at,
+                msg.common.reply_to,
+                msg.content.text.clone(),
+                msg.content.sha256_hashes.clone(),
+            );
+            let prepared = &prepared;
+            async move {
+                let result = self.session.execute_unpaged(prepared, values).await?;
+                Ok(ids::lwt_result(result)?.0)
+            }
+        })
+        .await?;
+
+        Ok(ScheduledMessage {
+            scheduled_id,
+            chat_id: msg.common.to,
+            schedule_at,
+            reply_to: msg.common.reply_to,
+            content: msg.content.text.clone(),
+            sha256_hashes: msg.content.sha256_hashes.clone(),
+        })
+    }
+
+    /// Scheduled messages of the user, the earliest first
+    ///
+    /// Only the ones targeted to `chat_id` if it's provided
+    pub async fn fetch_scheduled(
+        &self,
+        sender_id: &UserId,
+        chat_id: Option<i32>,
+    ) -> PPResult<Vec<ScheduledMessage>> {
+        let query = format!(
+            "SELECT {} FROM ksp.scheduled_messages WHERE from_id = ?",
+            SCHEDULED_COLUMNS
+        );
+        let prepared = self.session.prepare(query).await?;
+        let mut messages: Vec<ScheduledMessage> = self
+            .session
+            .execute_iter(prepared, (sender_id.as_i32_unchecked(),))
+            .await?
+            .rows_stream::<DatabaseScheduledMessage>()?
+            .map_ok(|msg| <(i32, ScheduledMessage)>::from(msg).1)
+            .try_filter(|msg| std::future::ready(chat_id.map_or(true, |id| msg.chat_id == id)))
+            .try_collect()
+            .await?;
+        messages.sort_by_key(|msg| msg.schedule_at);
+
+        Ok(messages)
+    }
+
+    pub async fn fetch_one(
+        &self,
+        sender_id: &UserId,
+        scheduled_id: i32,
+    ) -> PPResult<Option<ScheduledMessage>> {
+        let query = format!(
+            "SELECT {} FROM ksp.scheduled_messages WHERE from_id = ? AND id = ?",
+            SCHEDULED_COLUMNS
+        );
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (sender_id.as_i32_unchecked(), scheduled_id))
+            .await?
+            .rows_stream::<DatabaseScheduledMessage>()?
+            .try_next()
+            .await?
+            .map(|msg| <(i32, ScheduledMessage)>::from(msg).1))
+    }
+
+    /// Overwrites the editable fields, returns false if the message was already sent or canceled
+    pub async fn update(&self, sender_id: &UserId, msg: &ScheduledMessage) -> PPResult<bool> {
+        let query = "UPDATE ksp.scheduled_messages SET schedule_at = ?, content = ?, sha256_hashes = ? WHERE from_id = ? AND id = ? IF EXISTS";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(
+                &prepared,
+                (
+                    msg.schedule_at,
+                    &msg.content,
+                    &msg.sha256_hashes,
+                    sender_id.as_i32_unchecked(),
+                    msg.scheduled_id,
+                ),
+            )
+            .await?;
+
+        Ok(ids::lwt_result(result)?.0)
+    }
+
+    /// Returns false if the message was already sent or canceled
+    pub async fn cancel(&self, sender_id: &UserId, scheduled_id: i32) -> PPResult<bool> {
+        let query = "DELETE FROM ksp.scheduled_messages WHERE from_id = ? AND id = ? IF EXISTS";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (sender_id.as_i32_unchecked(), scheduled_id))
+            .await?;
+
+        Ok(ids::lwt_result(result)?.0)
+    }
+
+    pub async fn cancel_all(&self, sender_id: &UserId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.scheduled_messages WHERE from_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (sender_id.as_i32_unchecked(),))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns `(sender, message)` of every message due at `now`
+    pub async fn fetch_due(&self, now: i64) -> PPResult<Vec<(i32, ScheduledMessage)>> {
+        let query = format!(
+            "SELECT {} FROM ksp.scheduled_messages WHERE schedule_at <= ? ALLOW FILTERING",
+            SCHEDULED_COLUMNS
+        );
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (now,))
+            .await?
+            .rows_stream::<DatabaseScheduledMessage>()?
+            .map_ok(Into::into)
+            .try_collect()
+            .await?)
+    }
+
+    /// Removes the due message before delivering it
+    ///
+    /// Fails if it was canceled or rescheduled meanwhile, so it's never delivered twice
+    pub async fn claim(&self, from_id: i32, scheduled_id: i32, now: i64) -> PPResult<bool> {
+        let query =
+            "DELETE FROM ksp.scheduled_messages WHERE from_id = ? AND id = ? IF schedule_at <= ?";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (from_id, scheduled_id, now))
+            .await?;
+
+        Ok(ids::lwt_result(result)?.0)
+    }
+}
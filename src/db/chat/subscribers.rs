@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use futures::{Stream, TryStreamExt};
+use scylla::frame::value::Counter;
+
+use crate::{
+    db::{
+        bucket::DatabaseBuilder,
+        init::Database,
+        internal::{
+            error::{PPError, PPResult},
+            ids,
+        },
+    },
+    server::message::types::{chat::ChatId, user::UserId},
+};
+
+/// Subscribers of channels
+///
+/// Channels may have too many of them for the `participants` list of `ksp.chats`,
+/// so they are stored by rows with the count kept in a counter table
+pub struct SubscribersDB {
+    session: Arc<scylla::Session>,
+}
+
+impl From<DatabaseBuilder> for SubscribersDB {
+    fn from(value: DatabaseBuilder) -> Self {
+        SubscribersDB {
+            session: value.bucket.get_connection(),
+        }
+    }
+}
+
+impl Database for SubscribersDB {
+    fn new(session: Arc<scylla::Session>) -> Self {
+        Self {
+            session: Arc::clone(&session),
+        }
+    }
+
+    async fn create_table(&self) -> Result<(), PPError> {
+        let create_table_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.channel_subscribers (
+                chat_id int,
+                user_id int,
+                PRIMARY KEY (chat_id, user_id)
+            );
+        "#;
+        // Counters can't share a table with regular columns
+        let create_counts_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.channel_subscriber_counts (
+                chat_id int PRIMARY KEY,
+                subscribers counter
+            );
+        "#;
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+        self.session.query_unpaged(create_counts_query, &[]).await?;
+        Ok(())
+    }
+}
+
+impl SubscribersDB {
+    async fn change_count(&self, chat_id: ChatId, delta: i64) -> PPResult<()> {
+        let query = "UPDATE ksp.channel_subscriber_counts SET subscribers = subscribers + ? WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (Counter(delta), chat_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns false if the user is already subscribed
+    pub async fn subscribe(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<bool> {
+        let query =
+            "INSERT INTO ksp.channel_subscribers (chat_id, user_id) VALUES (?, ?) IF NOT EXISTS";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (chat_id, user_id.as_i32_unchecked()))
+            .await?;
+
+        let applied = ids::lwt_result(result)?.0;
+        if applied {
+            self.change_count(chat_id, 1).await?;
+        }
+
+        Ok(applied)
+    }
+
+    /// Returns false if the user wasn't subscribed
+    pub async fn unsubscribe(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<bool> {
+        let query =
+            "DELETE FROM ksp.channel_subscribers WHERE chat_id = ? AND user_id = ? IF EXISTS";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (chat_id, user_id.as_i32_unchecked()))
+            .await?;
+
+        let applied = ids::lwt_result(result)?.0;
+        if applied {
+            self.change_count(chat_id, -1).await?;
+        }
+
+        Ok(applied)
+    }
+
+    pub async fn is_subscriber(&self, chat_id: ChatId, user_id: &UserId) -> PPResult<bool> {
+        let query = "SELECT user_id FROM ksp.channel_subscribers WHERE chat_id = ? AND user_id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id, user_id.as_i32_unchecked()))
+            .await?
+            .rows_stream::<(i32,)>()?
+            .try_next()
+            .await?
+            .is_some())
+    }
+
+    pub async fn fetch_count(&self, chat_id: ChatId) -> PPResult<u64> {
+        let query = "SELECT subscribers FROM ksp.channel_subscriber_counts WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(Option<Counter>,)>()?
+            .try_next()
+            .await?
+            .and_then(|v| v.0)
+            .map_or(0, |count| count.0.max(0) as u64))
+    }
+
+    /// Ids of the subscribers, fetched page by page while the stream is consumed
+    pub async fn stream_subscribers(
+        &self,
+        chat_id: ChatId,
+    ) -> PPResult<impl Stream<Item = PPResult<i32>>> {
+        let query = "SELECT user_id FROM ksp.channel_subscribers WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id,))
+            .await?
+            .rows_stream::<(i32,)>()?
+            .map_ok(|(user_id,)| user_id)
+            .map_err(PPError::from))
+    }
+
+    /// Those of `user_ids`, who are subscribed to the channel
+    pub async fn filter_subscribers(
+        &self,
+        chat_id: ChatId,
+        user_ids: &[i32],
+    ) -> PPResult<Vec<i32>> {
+        let query =
+            "SELECT user_id FROM ksp.channel_subscribers WHERE chat_id = ? AND user_id IN ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id, user_ids))
+            .await?
+            .rows_stream::<(i32,)>()?
+            .map_ok(|(user_id,)| user_id)
+            .try_collect()
+            .await?)
+    }
+
+    pub async fn delete_subscribers(&self, chat_id: ChatId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.channel_subscribers WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        let query = "DELETE FROM ksp.channel_subscriber_counts WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use futures::TryStreamExt;
+
+use crate::{
+    db::{
+        bucket::DatabaseBuilder,
+        chat::{messages::MessagesDB, read_cursors::ReadCursorsDB},
+        init::Database,
+        internal::error::{PPError, PPResult},
+    },
+    server::message::types::{
+        chat::ChatId,
+        message::{Message, ThreadInfo},
+        request::send::MessageId,
+        user::UserId,
+    },
+};
+
+/// Replies indexed by the message they reply to
+///
+/// Threads are fetched from here instead of scanning `ksp.messages`.
+/// Only direct replies belong to the thread of a message
+pub struct ThreadsDB {
+    session: Arc<scylla::Session>,
+}
+
+impl From<DatabaseBuilder> for ThreadsDB {
+    fn from(value: DatabaseBuilder) -> Self {
+        ThreadsDB {
+            session: value.bucket.get_connection(),
+        }
+    }
+}
+
+impl Database for ThreadsDB {
+    fn new(session: Arc<scylla::Session>) -> Self {
+        Self {
+            session: Arc::clone(&session),
+        }
+    }
+
+    async fn create_table(&self) -> Result<(), PPError> {
+        let create_table_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.replies (
+                chat_id int,
+                reply_to int,
+                message_id int,
+                from_id int,
+                PRIMARY KEY (chat_id, reply_to, message_id)
+            );
+        "#;
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+        Ok(())
+    }
+}
+
+impl ThreadsDB {
+    pub async fn add_reply(
+        &self,
+        chat_id: ChatId,
+        reply_to: MessageId,
+        message_id: MessageId,
+        from_id: i32,
+    ) -> PPResult<()> {
+        let query =
+            "INSERT INTO ksp.replies (chat_id, reply_to, message_id, from_id) VALUES (?, ?, ?, ?)";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (chat_id, reply_to, message_id, from_id))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_reply(
+        &self,
+        chat_id: ChatId,
+        reply_to: MessageId,
+        message_id: MessageId,
+    ) -> PPResult<()> {
+        let query = "DELETE FROM ksp.replies WHERE chat_id = ? AND reply_to = ? AND message_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (chat_id, reply_to, message_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Forgets replies to the deleted message
+    ///
+    /// Ids of deleted messages may be taken again by new ones, which must start without replies
+    pub async fn delete_thread(&self, chat_id: ChatId, reply_to: MessageId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.replies WHERE chat_id = ? AND reply_to = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (chat_id, reply_to))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_all_threads(&self, chat_id: ChatId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.replies WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        Ok(())
+    }
+
+    /// Ids of up to `limit` replies with ids greater than `after`, oldest first
+    pub async fn fetch_reply_ids(
+        &self,
+        chat_id: ChatId,
+        reply_to: MessageId,
+        after: MessageId,
+        limit: u32,
+    ) -> PPResult<Vec<MessageId>> {
+        let query = "SELECT message_id FROM ksp.replies WHERE chat_id = ? AND reply_to = ? AND message_id > ? LIMIT ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id, reply_to, after, limit as i32))
+            .await?
+            .rows_stream::<(i32,)>()?
+            .map_ok(|v| v.0)
+            .try_collect()
+            .await?)
+    }
+
+    /// Counts replies after `last_read_id`, not including the ones sent by the user
+    pub async fn fetch_unread_count(
+        &self,
+        chat_id: ChatId,
+        reply_to: MessageId,
+        user_id: &UserId,
+        last_read_id: MessageId,
+    ) -> PPResult<u32> {
+        let query =
+            "SELECT from_id FROM ksp.replies WHERE chat_id = ? AND reply_to = ? AND message_id > ?";
+        let prepared = self.session.prepare(query).await?;
+        let mut iter = self
+            .session
+            .execute_iter(prepared, (chat_id, reply_to, last_read_id))
+            .await?
+            .rows_stream::<(i32,)>()?;
+
+        let mut count = 0;
+        while let Some((from_id,)) = iter.try_next().await? {
+            if from_id != user_id.as_i32_unchecked() {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Fills `thread` of the given messages of the chat, which have replies,
+    /// relative to `self_user_id`
+    pub async fn attach_threads(
+        &self,
+        chat_id: ChatId,
+        self_user_id: &UserId,
+        messages: &mut [Message],
+    ) -> PPResult<()> {
+        let (Some(min), Some(max)) = (
+            messages.iter().map(|m| m.message_id).min(),
+            messages.iter().map(|m| m.message_id).max(),
+        ) else {
+            return Ok(());
+        };
+
+        let query = "SELECT reply_to, COUNT(*), MAX(message_id) FROM ksp.replies WHERE chat_id = ? AND reply_to >= ? AND reply_to <= ? GROUP BY reply_to";
+        let prepared = self.session.prepare(query).await?;
+        let threads: BTreeMap<MessageId, (i64, MessageId)> = self
+            .session
+            .execute_iter(prepared, (chat_id, min, max))
+            .await?
+            .rows_stream::<(i32, i64, i32)>()?
+            .map_ok(|(reply_to, count, last_reply_id)| (reply_to, (count, last_reply_id)))
+            .try_collect()
+            .await?;
+        if threads.is_empty() {
+            return Ok(());
+        }
+
+        let messages_db: MessagesDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        let read_cursors_db: ReadCursorsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        let last_read_id = read_cursors_db
+            .fetch_cursor(chat_id, self_user_id)
+            .await?
+            .unwrap_or(-1);
+
+        for message in messages.iter_mut() {
+            let Some(&(reply_count, last_reply_id)) = threads.get(&message.message_id) else {
+                continue;
+            };
+            let Some(last_reply) = messages_db
+                .fetch_messages(chat_id, last_reply_id..0)
+                .await?
+                .pop()
+            else {
+                continue;
+            };
+
+            message.thread = Some(ThreadInfo {
+                reply_count: reply_count as u32,
+                last_reply_id,
+                last_reply_from: last_reply.from_id,
+                last_reply_date: last_reply.date,
+                unread_count: self
+                    .fetch_unread_count(chat_id, message.message_id, self_user_id, last_read_id)
+                    .await?,
+            });
+        }
+
+        Ok(())
+    }
+}
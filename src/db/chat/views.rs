@@ -0,0 +1,104 @@
+use std::{collections::HashMap, sync::Arc};
+
+use futures::TryStreamExt;
+use scylla::frame::value::Counter;
+
+use crate::{
+    db::{
+        bucket::DatabaseBuilder,
+        init::Database,
+        internal::error::{PPError, PPResult},
+    },
+    server::message::types::{chat::ChatId, request::send::MessageId},
+};
+
+/// How many times messages of channels were viewed
+///
+/// Every subscriber views a message at most once, see `ReadCursorsDB`
+pub struct ViewsDB {
+    session: Arc<scylla::Session>,
+}
+
+impl From<DatabaseBuilder> for ViewsDB {
+    fn from(value: DatabaseBuilder) -> Self {
+        ViewsDB {
+            session: value.bucket.get_connection(),
+        }
+    }
+}
+
+impl Database for ViewsDB {
+    fn new(session: Arc<scylla::Session>) -> Self {
+        Self {
+            session: Arc::clone(&session),
+        }
+    }
+
+    async fn create_table(&self) -> Result<(), PPError> {
+        let create_table_query = r#"
+            CREATE TABLE IF NOT EXISTS ksp.message_views (
+                chat_id int,
+                message_id int,
+                views counter,
+                PRIMARY KEY (chat_id, message_id)
+            );
+        "#;
+
+        self.session.query_unpaged(create_table_query, &[]).await?;
+        Ok(())
+    }
+}
+
+impl ViewsDB {
+    pub async fn add_views(&self, chat_id: ChatId, message_ids: &[MessageId]) -> PPResult<()> {
+        let query =
+            "UPDATE ksp.message_views SET views = views + 1 WHERE chat_id = ? AND message_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        for &message_id in message_ids {
+            self.session
+                .execute_unpaged(&prepared, (chat_id, message_id))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Messages, which weren't viewed yet, are missing
+    pub async fn fetch_views(
+        &self,
+        chat_id: ChatId,
+        message_ids: &[MessageId],
+    ) -> PPResult<HashMap<MessageId, u64>> {
+        let query =
+            "SELECT message_id, views FROM ksp.message_views WHERE chat_id = ? AND message_id IN ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (chat_id, message_ids))
+            .await?
+            .rows_stream::<(i32, Counter)>()?
+            .map_ok(|(message_id, views)| (message_id, views.0.max(0) as u64))
+            .try_collect()
+            .await?)
+    }
+
+    /// Ids of deleted messages may be taken again by new ones, which must start from zero
+    pub async fn delete_views(&self, chat_id: ChatId, message_ids: &[MessageId]) -> PPResult<()> {
+        let query = "DELETE FROM ksp.message_views WHERE chat_id = ? AND message_id IN ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (chat_id, message_ids))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_all_views(&self, chat_id: ChatId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.message_views WHERE chat_id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session.execute_unpaged(&prepared, (chat_id,)).await?;
+
+        Ok(())
+    }
+}
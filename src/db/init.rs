@@ -2,7 +2,13 @@ use std::sync::Arc;
 
 use super::{
     bucket::{DatabaseBuilder, DatabasePool},
-    chat::{chats::ChatsDB, drafts::DraftsDB, hashes::HashesDB, messages::MessagesDB},
+    chat::{
+        chats::ChatsDB, drafts::DraftsDB, hashes::HashesDB, hidden::HiddenMessagesDB,
+        invitations::InvitationsDB, join_requests::JoinRequestsDB, messages::MessagesDB,
+        reactions::ReactionsDB, read_cursors::ReadCursorsDB, revisions::RevisionsDB,
+        scheduled::ScheduledMessagesDB, subscribers::SubscribersDB, threads::ThreadsDB,
+        views::ViewsDB,
+    },
     internal::error::PPError,
     user::UsersDB,
 };
@@ -12,6 +18,30 @@ pub trait Database {
     fn create_table(&self) -> impl std::future::Future<Output = Result<(), PPError>> + Send;
 }
 
+/// Tables are created with `IF NOT EXISTS`, so columns that were added later
+/// must be added to already existing tables explicitly
+pub async fn add_column_if_not_exists(
+    session: &scylla::Session,
+    table: &str,
+    column: &str,
+    cql_type: &str,
+) -> Result<(), PPError> {
+    let query = "SELECT column_name FROM system_schema.columns WHERE keyspace_name = 'ksp' AND table_name = ? AND column_name = ?";
+    let exists = session
+        .query_unpaged(query, (table, column))
+        .await?
+        .into_rows_result()
+        .map_or(false, |rows| rows.rows_num() > 0);
+
+    if !exists {
+        session
+            .query_unpaged(format!("ALTER TABLE ksp.{} ADD {} {}", table, column, cql_type), &[])
+            .await?;
+    }
+
+    Ok(())
+}
+
 /// Creates a temporary database pool for creating basic tables
 /// Deallocating pool at the end
 pub async fn create_tables() {
@@ -22,6 +52,16 @@ pub async fn create_tables() {
     let chats_db: ChatsDB = DatabaseBuilder::from(bucket.clone()).into();
     let drafts_db: DraftsDB = DatabaseBuilder::from(bucket.clone()).into();
     let hashes_db: HashesDB = DatabaseBuilder::from(bucket.clone()).into();
+    let read_cursors_db: ReadCursorsDB = DatabaseBuilder::from(bucket.clone()).into();
+    let reactions_db: ReactionsDB = DatabaseBuilder::from(bucket.clone()).into();
+    let scheduled_db: ScheduledMessagesDB = DatabaseBuilder::from(bucket.clone()).into();
+    let revisions_db: RevisionsDB = DatabaseBuilder::from(bucket.clone()).into();
+    let hidden_db: HiddenMessagesDB = DatabaseBuilder::from(bucket.clone()).into();
+    let invitations_db: InvitationsDB = DatabaseBuilder::from(bucket.clone()).into();
+    let join_requests_db: JoinRequestsDB = DatabaseBuilder::from(bucket.clone()).into();
+    let subscribers_db: SubscribersDB = DatabaseBuilder::from(bucket.clone()).into();
+    let views_db: ViewsDB = DatabaseBuilder::from(bucket.clone()).into();
+    let threads_db: ThreadsDB = DatabaseBuilder::from(bucket.clone()).into();
 
     bucket
         .get_connection()
@@ -40,4 +80,16 @@ pub async fn create_tables() {
     users_db.create_table().await.unwrap();
     messages_db.create_table().await.unwrap();
     chats_db.create_table().await.unwrap();
+    read_cursors_db.create_table().await.unwrap();
+    reactions_db.create_table().await.unwrap();
+    scheduled_db.create_table().await.unwrap();
+    revisions_db.create_table().await.unwrap();
+    hidden_db.create_table().await.unwrap();
+    invitations_db.create_table().await.unwrap();
+    join_requests_db.create_table().await.unwrap();
+    subscribers_db.create_table().await.unwrap();
+    views_db.create_table().await.unwrap();
+    threads_db.create_table().await.unwrap();
+
+    messages_db.build_search_index().await.unwrap();
 }
@@ -13,6 +13,7 @@ async fn send_str_as_err<T: Into<Cow<'static, str>>>(
     method: &str,
     what: T,
     req_id: Option<i64>,
+    retry_after: Option<u64>,
     connection: &TCPConnection,
 ) {
     let what: String = what.into().to_string();
@@ -26,6 +27,9 @@ async fn send_str_as_err<T: Into<Cow<'static, str>>>(
     if let Some(req_id) = req_id {
         obj.insert("req_id".into(), req_id.into());
     }
+    if let Some(retry_after) = retry_after {
+        obj.insert("retry_after".into(), retry_after.into());
+    }
 
     let builder = MessageBuilder::build_from_str(serde_json::to_string(&error).unwrap());
 
@@ -38,6 +42,8 @@ async fn send_str_as_err<T: Into<Cow<'static, str>>>(
 pub enum PPError {
     Server(Box<dyn std::error::Error>),
     Client(String),
+    /// Request was throttled. Client may retry after the given amount of seconds
+    RateLimited(u64),
 }
 
 unsafe impl Send for PPError {}
@@ -48,6 +54,9 @@ impl fmt::Display for PPError {
         match *self {
             PPError::Server(ref err) => write!(f, "INTERNAL ERROR: {}", err),
             PPError::Client(ref msg) => write!(f, "{}", msg),
+            PPError::RateLimited(secs) => {
+                write!(f, "Too many attempts. Try again in {} seconds", secs)
+            }
         }
     }
 }
@@ -56,7 +65,7 @@ impl std::error::Error for PPError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match *self {
             PPError::Server(ref err) => Some(err.as_ref()),
-            PPError::Client(_) => None,
+            PPError::Client(_) | PPError::RateLimited(_) => None,
         }
     }
 }
@@ -101,6 +110,8 @@ impl PPError {
     /// if Server error, writes error to console and sends 'Internal error.' to user.
     ///
     /// if Client error, sends error to the client
+    ///
+    /// if RateLimited, sends error to the client along with `retry_after` in seconds
     pub async fn safe_send(
         &self,
         method: &str,
@@ -112,9 +123,13 @@ impl PPError {
                 error!("{}", internal);
                 "Internal error.".into()
             }
-            PPError::Client(_) => self.to_string(),
+            PPError::Client(_) | PPError::RateLimited(_) => self.to_string(),
+        };
+        let retry_after = match self {
+            PPError::RateLimited(secs) => Some(*secs),
+            _ => None,
         };
-        send_str_as_err(method, err, req_id, output_connection).await;
+        send_str_as_err(method, err, req_id, retry_after, output_connection).await;
     }
 }
 pub type PPResult<T> = Result<T, PPError>;
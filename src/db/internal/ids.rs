@@ -0,0 +1,58 @@
+use std::{collections::HashMap, future::Future, ops::Range};
+
+use rand::Rng;
+use scylla::{frame::response::result::Row, QueryResult};
+
+use super::error::{PPError, PPResult};
+
+/// How many times a colliding id is regenerated before giving up
+const MAX_ATTEMPTS: usize = 16;
+
+/// Parses the result of a lightweight transaction(`IF NOT EXISTS`, `IF col = ?`)
+///
+/// Returns whether it was applied, and the current int values of the row by column name,
+/// if it wasn't
+pub fn lwt_result(result: QueryResult) -> PPResult<(bool, HashMap<String, Option<i32>>)> {
+    let rows = result
+        .into_rows_result()
+        .map_err(|err| PPError::Server(Box::new(err)))?;
+    let names: Vec<String> = rows
+        .column_specs()
+        .iter()
+        .map(|spec| spec.name().to_owned())
+        .collect();
+    let row = rows
+        .maybe_first_row::<Row>()
+        .map_err(|err| PPError::Server(Box::new(err)))?
+        .ok_or("Lightweight transaction returned no rows")?;
+
+    let mut applied = false;
+    let mut columns = HashMap::new();
+    for (name, value) in names.into_iter().zip(row.columns) {
+        if name == "[applied]" {
+            applied = value.and_then(|v| v.as_boolean()).unwrap_or(false);
+        } else {
+            columns.insert(name, value.and_then(|v| v.as_int()));
+        }
+    }
+
+    Ok((applied, columns))
+}
+
+/// Picks random ids from `range` until `insert` succeeds
+///
+/// `insert` must be backed by `IF NOT EXISTS`, returning whether the row was inserted
+pub async fn insert_with_unique_id<F, Fut>(range: Range<i32>, mut insert: F) -> PPResult<i32>
+where
+    F: FnMut(i32) -> Fut,
+    Fut: Future<Output = PPResult<bool>>,
+{
+    for _ in 0..MAX_ATTEMPTS {
+        let id = rand::thread_rng().gen_range(range.clone());
+        if insert(id).await? {
+            return Ok(id);
+        }
+    }
+
+    Err(PPError::Server("Failed to generate unique id".into()))
+}
@@ -1,2 +1,4 @@
 pub mod error;
+pub(super) mod ids;
+pub(super) mod totp;
 pub(super) mod validate;
\ No newline at end of file
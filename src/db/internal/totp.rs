@@ -0,0 +1,82 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use super::error::{PPError, PPResult};
+
+const TOTP_ISSUER: &str = "PPgram";
+const TOTP_DIGITS: usize = 6;
+/// Accept one step before and after the current one, to tolerate clock drift
+const TOTP_SKEW: u8 = 1;
+/// Seconds
+const TOTP_STEP: u64 = 30;
+
+const RECOVERY_CODES_COUNT: usize = 8;
+const RECOVERY_CODE_SIZE: usize = 10;
+
+fn totp_from_base32(secret: &str) -> PPResult<TOTP> {
+    let bytes = Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .map_err(|err| PPError::Server(Box::new(err)))?;
+
+    // Skew is applied by `verify_code`, which needs to know the matching step
+    TOTP::new(Algorithm::SHA1, TOTP_DIGITS, 0, TOTP_STEP, bytes)
+        .map_err(|err| PPError::Server(Box::new(err)))
+}
+
+/// Generates new TOTP secret encoded in base32
+pub fn generate_secret() -> String {
+    match Secret::generate_secret().to_encoded() {
+        Secret::Encoded(secret) => secret,
+        Secret::Raw(_) => unreachable!("secret was encoded"),
+    }
+}
+
+/// `otpauth://` uri, which is understood by the authenticator apps(usually shown as QR code)
+pub fn otpauth_uri(secret: &str, username: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&digits={TOTP_DIGITS}&period={TOTP_STEP}",
+        issuer = TOTP_ISSUER,
+        account = username.replace('@', "%40"),
+    )
+}
+
+/// Checks the 6-digit code against the current time
+///
+/// Returns the time-step the code belongs to, so it can be recorded as used
+pub fn verify_code(secret: &str, code: &str) -> PPResult<Option<i32>> {
+    let totp = totp_from_base32(secret)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|err| PPError::Server(Box::new(err)))?
+        .as_secs();
+    let current = now / TOTP_STEP;
+    let skew = TOTP_SKEW as u64;
+
+    Ok((current.saturating_sub(skew)..=current + skew)
+        .find(|step| totp.check(code.trim(), step * TOTP_STEP))
+        .map(|step| step as i32))
+}
+
+/// Recovery codes are random enough, so plain SHA256 is sufficient
+pub fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().as_bytes()))
+}
+
+/// Returns plain recovery codes(to be shown once to the user) and their hashes(to be stored)
+pub fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    let codes: Vec<String> = (0..RECOVERY_CODES_COUNT)
+        .map(|_| {
+            rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(RECOVERY_CODE_SIZE)
+                .map(char::from)
+                .collect()
+        })
+        .collect();
+    let hashes = codes.iter().map(|code| hash_recovery_code(code)).collect();
+
+    (codes, hashes)
+}
@@ -8,6 +8,12 @@ const MIN_USERNAME_SIZE: usize = 3;
 const MAX_NAME_SIZE: usize = 60;
 const MIN_NAME_SIZE: usize = 1;
 
+/// Enough for the longest emoji ZWJ sequences
+const MAX_REACTION_SIZE: usize = 32;
+
+/// 1 year
+const MAX_TTL_SECONDS: u32 = 365 * 24 * 60 * 60;
+
 pub fn validate_username(username: &str) -> Result<(), PPError> {
     let lowercase: Vec<char> = ('a'..='z').collect();
     let uppercase: Vec<char> = ('A'..='Z').collect();
@@ -22,7 +28,10 @@ pub fn validate_username(username: &str) -> Result<(), PPError> {
     allowed_characters.push('_');
     let allowed_set: std::collections::HashSet<_> = allowed_characters.iter().collect();
 
-    let username_body = &username[1..]; // Exclude '@' for character validation
+    // Exclude '@' for character validation
+    let Some(username_body) = username.strip_prefix('@') else {
+        return Err(PPError::from("Username must start with '@' symbol!"));
+    };
 
     if !username_body.chars().all(|c| allowed_set.contains(&c)) {
         return Err(PPError::from(
@@ -30,10 +39,6 @@ pub fn validate_username(username: &str) -> Result<(), PPError> {
         ));
     }
 
-    if !username.starts_with('@') {
-        return Err(PPError::from("Username must start with '@' symbol!"));
-    }
-
     if username.len() > MAX_USERNAME_SIZE {
         return Err(PPError::from("Username too big"));
     }
@@ -57,6 +62,91 @@ pub fn validate_name(name: &str) -> Result<(), PPError> {
     Ok(())
 }
 
+/// Reaction must be a single emoji: one pictograph with optional modifiers,
+/// a ZWJ sequence of those, a flag or a keycap
+pub fn validate_reaction(reaction: &str) -> Result<(), PPError> {
+    if reaction.is_empty() || reaction.len() > MAX_REACTION_SIZE {
+        return Err(PPError::from("Invalid reaction size"));
+    }
+
+    let chars: Vec<char> = reaction.chars().collect();
+    if !(is_flag(&chars) || is_keycap(&chars) || is_zwj_sequence(&chars)) {
+        return Err(PPError::from("Reaction must be an emoji!"));
+    }
+
+    Ok(())
+}
+
+const ZWJ: char = '\u{200D}';
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+const KEYCAP: char = '\u{20E3}';
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1F1E6}'..='\u{1F1FF}')
+}
+
+fn is_skin_tone(c: char) -> bool {
+    matches!(c, '\u{1F3FB}'..='\u{1F3FF}')
+}
+
+/// Tags of subdivision flags, like England
+fn is_tag(c: char) -> bool {
+    matches!(c, '\u{E0020}'..='\u{E007F}')
+}
+
+fn is_pictograph(c: char) -> bool {
+    matches!(c,
+        '\u{00A9}' | '\u{00AE}' | '\u{203C}' | '\u{2049}' | '\u{2122}' | '\u{2139}'
+        | '\u{2194}'..='\u{21AA}'
+        | '\u{231A}'..='\u{23FF}'
+        | '\u{24C2}'
+        | '\u{25AA}'..='\u{25FE}'
+        | '\u{2600}'..='\u{27BF}'
+        | '\u{2934}'..='\u{2935}'
+        | '\u{2B05}'..='\u{2B55}'
+        | '\u{3030}' | '\u{303D}' | '\u{3297}' | '\u{3299}'
+        | '\u{1F000}'..='\u{1F1E5}'
+        | '\u{1F200}'..='\u{1F3FA}'
+        | '\u{1F400}'..='\u{1FAFF}'
+    )
+}
+
+fn is_flag(chars: &[char]) -> bool {
+    matches!(chars, [a, b] if is_regional_indicator(*a) && is_regional_indicator(*b))
+}
+
+fn is_keycap(chars: &[char]) -> bool {
+    match chars {
+        [c, VARIATION_SELECTOR, KEYCAP] | [c, KEYCAP] => matches!(c, '0'..='9' | '#' | '*'),
+        _ => false,
+    }
+}
+
+/// Pictographs, each followed by optional modifiers, joined by ZWJ
+fn is_zwj_sequence(chars: &[char]) -> bool {
+    chars.split(|&c| c == ZWJ).all(|element| match element {
+        [base, modifiers @ ..] => {
+            is_pictograph(*base)
+                && modifiers
+                    .iter()
+                    .all(|&c| c == VARIATION_SELECTOR || is_skin_tone(c) || is_tag(c))
+        }
+        [] => false,
+    })
+}
+
+/// Expiry timer of a message, at most a year
+pub fn validate_ttl(ttl_seconds: u32) -> Result<(), PPError> {
+    if ttl_seconds == 0 || ttl_seconds > MAX_TTL_SECONDS {
+        return Err(PPError::from(format!(
+            "ttl_seconds must be between 1 and {}!",
+            MAX_TTL_SECONDS
+        )));
+    }
+
+    Ok(())
+}
+
 /// Makes range valid.
 pub fn validate_range(range: impl RangeBounds<i32>) -> Result<(i32, i32), PPError> {
     match (range.start_bound(), range.end_bound()) {
@@ -15,11 +15,19 @@ use crate::server::message::types::chat::ChatId;
 use crate::server::message::types::user::User;
 use crate::server::message::types::user::UserId;
 
-use super::init::Database;
+use super::init::{add_column_if_not_exists, Database};
 use super::internal::error::PPError;
 use super::internal::error::PPResult;
+use super::internal::ids;
+use super::internal::totp;
 use super::internal::validate;
-use super::{bucket::DatabaseBuilder, chat::hashes::HashesDB};
+use super::{
+    bucket::DatabaseBuilder,
+    chat::{chats::ChatsDB, hashes::HashesDB},
+};
+
+/// How many times spending a recovery code is retried on concurrent changes of the codes
+const MAX_RECOVERY_CODE_ATTEMPTS: usize = 8;
 
 pub struct UsersDB {
     session: Arc<scylla::Session>,
@@ -33,6 +41,16 @@ impl From<DatabaseBuilder> for UsersDB {
     }
 }
 
+/// Checks the password against the stored argon2 hash
+fn password_matches(password: &str, stored_password_hash: &str) -> PPResult<bool> {
+    let stored_password_hash = PasswordHash::new(stored_password_hash)
+        .map_err(|err| PPError::Server(format!("Failed to parse password hash: {}", err).into()))?;
+
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &stored_password_hash)
+        .is_ok())
+}
+
 impl Database for UsersDB {
     fn new(session: Arc<scylla::Session>) -> UsersDB {
         UsersDB { session }
@@ -49,7 +67,12 @@ impl Database for UsersDB {
                 password_hash TEXT,
                 password_salt TEXT,
                 sessions LIST<TEXT>,
-                chats MAP<int, int>
+                chats MAP<int, int>,
+                totp_secret TEXT,
+                totp_enabled boolean,
+                recovery_codes LIST<TEXT>,
+                totp_last_step int,
+                export_hash TEXT
             )
         "#;
 
@@ -74,6 +97,12 @@ impl Database for UsersDB {
             .query_unpaged(name_custom_index_query, &[])
             .await?;
 
+        add_column_if_not_exists(&self.session, "users", "totp_secret", "TEXT").await?;
+        add_column_if_not_exists(&self.session, "users", "totp_enabled", "boolean").await?;
+        add_column_if_not_exists(&self.session, "users", "recovery_codes", "LIST<TEXT>").await?;
+        add_column_if_not_exists(&self.session, "users", "totp_last_step", "int").await?;
+        add_column_if_not_exists(&self.session, "users", "export_hash", "TEXT").await?;
+
         Ok(())
     }
 }
@@ -118,15 +147,15 @@ impl UsersDB {
         validate::validate_name(name)?;
         validate::validate_username(username)?;
 
-        if self.exists(&username.into()).await? {
+        if self.exists(&username.into()).await? || self.tag_taken_by_group(username).await? {
             return Err(PPError::from("Username already taken"));
         }
 
-        let user_id: i32 = rand::thread_rng().gen_range(1..i32::MAX);
         let profile_color: u32 = rand::thread_rng().gen_range(1..=21);
 
         let query = r#"
             INSERT INTO ksp.users (id, name, username, password_hash, password_salt, sessions, photo, chats, profile_color) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            IF NOT EXISTS
         "#;
         let prepared = self.session.prepare(query).await?;
 
@@ -139,22 +168,25 @@ impl UsersDB {
             .to_string();
         info!("Generated new password hash: {}", password_hash);
 
-        self.session
-            .execute_unpaged(
-                &prepared,
-                (
-                    user_id,
-                    name,
-                    username,
-                    password_hash,
-                    salt.as_str(),
-                    Vec::<String>::new(),
-                    "",
-                    HashMap::<i32, i32>::new(),
-                    profile_color as i32,
-                ),
-            )
-            .await?;
+        let user_id = ids::insert_with_unique_id(1..i32::MAX, |user_id| {
+            let values = (
+                user_id,
+                name,
+                username,
+                password_hash.as_str(),
+                salt.as_str(),
+                Vec::<String>::new(),
+                "",
+                HashMap::<i32, i32>::new(),
+                profile_color as i32,
+            );
+            let prepared = &prepared;
+            async move {
+                let result = self.session.execute_unpaged(prepared, values).await?;
+                Ok(ids::lwt_result(result)?.0)
+            }
+        })
+        .await?;
 
         match self.create_session(user_id).await {
             Ok(session_id) => Ok((user_id, session_id)),
@@ -211,39 +243,243 @@ impl UsersDB {
         Ok(o)
     }
 
+    /// Verifies the password. Returns `user_id` and `session_id` if successfull
+    ///
+    /// If the user has two-factor authentication enabled, no session is created
+    /// and `session_id` is `None`. The session is then created by `login_second_factor`
     pub async fn login(
         &self,
         username: &str,
         password: &str,
-    ) -> PPResult<(i32 /* user_id */, String /* session_id */)> {
-        let query = "SELECT id, password_hash FROM ksp.users WHERE username = ?";
+    ) -> PPResult<(i32 /* user_id */, Option<String> /* session_id */)> {
+        let query = "SELECT id, password_hash, totp_enabled FROM ksp.users WHERE username = ?";
         let mut res = self
             .session
             .query_iter(query, (username,))
             .await?
-            .rows_stream::<(i32, String)>()?;
+            .rows_stream::<(i32, String, Option<bool>)>()?;
 
-        let (user_id, stored_password_hash) = res
+        let (user_id, stored_password_hash, totp_enabled) = res
             .try_next()
             .await?
             .ok_or(PPError::from("User with the given credentials not found!"))?;
 
-        let password_matches = Argon2::default()
-            .verify_password(
-                password.as_bytes(),
-                &PasswordHash::new(&stored_password_hash).expect("Failed to parse password hash!"),
-            )
-            .is_ok();
-        if !password_matches {
+        if !password_matches(password, &stored_password_hash)? {
             return Err(PPError::from("Invalid password!"));
         }
 
+        if totp_enabled.unwrap_or(false) {
+            return Ok((user_id, None));
+        }
+
         match self.create_session(user_id).await {
-            Ok(session_id) => Ok((user_id, session_id)),
+            Ok(session_id) => Ok((user_id, Some(session_id))),
             Err(err) => Err(err),
         }
     }
 
+    /// Second login step for the users with two-factor authentication
+    ///
+    /// Accepts either the TOTP code or one of the recovery codes. Used recovery code is invalidated
+    pub async fn login_second_factor(&self, user_id: i32, code: &str) -> PPResult<String> {
+        self.verify_second_factor(user_id, code).await?;
+        self.create_session(user_id).await
+    }
+
+    async fn fetch_totp_state(
+        &self,
+        user_id: i32,
+    ) -> PPResult<(Option<String>, bool, Vec<String>)> {
+        let query = "SELECT totp_secret, totp_enabled, recovery_codes FROM ksp.users WHERE id = ?";
+        let (secret, enabled, recovery_codes) = self
+            .session
+            .query_iter(query, (user_id,))
+            .await?
+            .rows_stream::<(Option<String>, Option<bool>, Option<Vec<String>>)>()?
+            .try_next()
+            .await?
+            .ok_or(PPError::from("User wasn't found!"))?;
+
+        Ok((
+            secret.filter(|s| !s.is_empty()),
+            enabled.unwrap_or(false),
+            recovery_codes.unwrap_or_default(),
+        ))
+    }
+
+    async fn verify_second_factor(&self, user_id: i32, code: &str) -> PPResult<()> {
+        let (secret, enabled, recovery_codes) = self.fetch_totp_state(user_id).await?;
+        let secret = match secret {
+            Some(secret) if enabled => secret,
+            _ => return Err(PPError::from("Two-factor authentication isn't enabled!")),
+        };
+
+        if let Some(step) = totp::verify_code(&secret, code)? {
+            if !self.use_totp_step(user_id, step).await? {
+                return Err(PPError::from(
+                    "Two-factor authentication code was already used!",
+                ));
+            }
+            return Ok(());
+        }
+
+        self.use_recovery_code(user_id, recovery_codes, code).await
+    }
+
+    /// Records the time-step of an accepted TOTP code, so the code can't be replayed
+    ///
+    /// Returns false if this or a later step was already used
+    async fn use_totp_step(&self, user_id: i32, step: i32) -> PPResult<bool> {
+        let query = "UPDATE ksp.users SET totp_last_step = ? WHERE id = ? IF totp_last_step < ?";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (step, user_id, step))
+            .await?;
+        let (applied, row) = ids::lwt_result(result)?;
+        if applied || row.get("totp_last_step").copied().flatten().is_some() {
+            return Ok(applied);
+        }
+
+        // No code was used since two-factor authentication was enabled
+        let query = "UPDATE ksp.users SET totp_last_step = ? WHERE id = ? IF totp_last_step = null";
+        let prepared = self.session.prepare(query).await?;
+        let result = self
+            .session
+            .execute_unpaged(&prepared, (step, user_id))
+            .await?;
+
+        Ok(ids::lwt_result(result)?.0)
+    }
+
+    /// Invalidates the recovery code
+    ///
+    /// The codes are replaced only if nobody changed them meanwhile,
+    /// so concurrent logins can't spend the same code twice
+    async fn use_recovery_code(
+        &self,
+        user_id: i32,
+        mut recovery_codes: Vec<String>,
+        code: &str,
+    ) -> PPResult<()> {
+        let code_hash = totp::hash_recovery_code(code);
+        let query = "UPDATE ksp.users SET recovery_codes = ? WHERE id = ? IF recovery_codes = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        for _ in 0..MAX_RECOVERY_CODE_ATTEMPTS {
+            let Some(idx) = recovery_codes.iter().position(|h| *h == code_hash) else {
+                return Err(PPError::from("Invalid two-factor authentication code!"));
+            };
+            let mut remaining = recovery_codes.clone();
+            remaining.remove(idx);
+
+            let result = self
+                .session
+                .execute_unpaged(&prepared, (&remaining, user_id, &recovery_codes))
+                .await?;
+            if ids::lwt_result(result)?.0 {
+                return Ok(());
+            }
+            recovery_codes = self.fetch_totp_state(user_id).await?.2;
+        }
+
+        Err(PPError::Server("Failed to use the recovery code".into()))
+    }
+
+    /// Generates new TOTP secret, that must be confirmed with `confirm_totp_enrollment`
+    ///
+    /// Returns the secret in base32 and `otpauth://` uri for the authenticator apps
+    pub async fn begin_totp_enrollment(
+        &self,
+        self_user_id: &UserId,
+    ) -> PPResult<(String /* secret */, String /* uri */)> {
+        let user_id = self_user_id.as_i32_unchecked();
+        self.check_totp_enrollment(self_user_id).await?;
+
+        let username = self
+            .fetch_user(self_user_id)
+            .await?
+            .ok_or(PPError::from("User wasn't found!"))?
+            .username()
+            .to_owned();
+
+        let secret = totp::generate_secret();
+        let query = "UPDATE ksp.users SET totp_secret = ?, totp_enabled = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (secret.as_str(), false, user_id))
+            .await?;
+
+        let uri = totp::otpauth_uri(&secret, &username);
+        Ok((secret, uri))
+    }
+
+    /// Fails if two-factor authentication is already enabled
+    pub async fn check_totp_enrollment(&self, self_user_id: &UserId) -> PPResult<()> {
+        let (_, enabled, _) = self
+            .fetch_totp_state(self_user_id.as_i32_unchecked())
+            .await?;
+        if enabled {
+            return Err(PPError::from("Two-factor authentication is already enabled!"));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the code against the pending secret without enabling anything
+    ///
+    /// Returns the time-step of the code
+    pub async fn verify_totp_enrollment(&self, self_user_id: &UserId, code: &str) -> PPResult<i32> {
+        let (secret, enabled, _) = self
+            .fetch_totp_state(self_user_id.as_i32_unchecked())
+            .await?;
+        if enabled {
+            return Err(PPError::from("Two-factor authentication is already enabled!"));
+        }
+        let secret = secret.ok_or(PPError::from(
+            "Two-factor authentication enrollment wasn't started!",
+        ))?;
+
+        totp::verify_code(&secret, code)?
+            .ok_or(PPError::from("Invalid two-factor authentication code!"))
+    }
+
+    /// Enables two-factor authentication, if the code matches the pending secret
+    ///
+    /// Returns plain recovery codes. Only hashes are stored, so they can be shown only once
+    pub async fn confirm_totp_enrollment(
+        &self,
+        self_user_id: &UserId,
+        code: &str,
+    ) -> PPResult<Vec<String>> {
+        let user_id = self_user_id.as_i32_unchecked();
+        let step = self.verify_totp_enrollment(self_user_id, code).await?;
+
+        let (codes, hashes) = totp::generate_recovery_codes();
+        let query = "UPDATE ksp.users SET totp_enabled = ?, recovery_codes = ?, totp_last_step = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (true, hashes, step, user_id))
+            .await?;
+
+        Ok(codes)
+    }
+
+    /// Disables two-factor authentication. Requires the TOTP or a recovery code
+    pub async fn disable_totp(&self, self_user_id: &UserId, code: &str) -> PPResult<()> {
+        let user_id = self_user_id.as_i32_unchecked();
+        self.verify_second_factor(user_id, code).await?;
+
+        let query =
+            "UPDATE ksp.users SET totp_secret = ?, totp_enabled = ?, recovery_codes = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, ("", false, Vec::<String>::new(), user_id))
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn authenticate(&self, user_id: i32, session_id: &str) -> PPResult<()> {
         let query = "SELECT sessions FROM ksp.users WHERE id = ?";
         let (sessions,) = self
@@ -320,7 +556,14 @@ impl UsersDB {
         Ok(())
     }
 
+    /// Same rules as on `register`
+    pub fn check_name(&self, name: &str) -> PPResult<()> {
+        validate::validate_name(name)
+    }
+
     pub async fn update_name(&self, self_user_id: &UserId, name: &str) -> PPResult<()> {
+        self.check_name(name)?;
+
         let query = "UPDATE ksp.users SET name = ? WHERE id = ?";
         let prepared = self.session.prepare(query).await?;
         self.session
@@ -330,7 +573,31 @@ impl UsersDB {
         Ok(())
     }
 
+    /// Same validation and uniqueness rules as on `register`
+    ///
+    /// Returns false if the user already has this username
+    pub async fn check_username(&self, self_user_id: &UserId, username: &str) -> PPResult<bool> {
+        validate::validate_username(username)?;
+
+        let current = self
+            .fetch_user(self_user_id)
+            .await?
+            .ok_or(PPError::from("User wasn't found!"))?;
+        if current.username() == username {
+            return Ok(false);
+        }
+        if self.exists(&username.into()).await? || self.tag_taken_by_group(username).await? {
+            return Err(PPError::from("Username already taken"));
+        }
+
+        Ok(true)
+    }
+
     pub async fn update_username(&self, self_user_id: &UserId, username: &str) -> PPResult<()> {
+        if !self.check_username(self_user_id, username).await? {
+            return Ok(());
+        }
+
         let query = "UPDATE ksp.users SET username = ? WHERE id = ?";
         let prepared = self.session.prepare(query).await?;
         self.session
@@ -340,6 +607,45 @@ impl UsersDB {
         Ok(())
     }
 
+    /// Usernames share the namespace with tags of groups
+    async fn tag_taken_by_group(&self, username: &str) -> PPResult<bool> {
+        let chats_db: ChatsDB = DatabaseBuilder::from_raw(self.session.clone()).into();
+        chats_db.tag_exists(username).await
+    }
+
+    /// Checks the password of already authenticated user, e.g. before changing credentials
+    pub async fn verify_password(&self, self_user_id: &UserId, password: &str) -> PPResult<()> {
+        let query = "SELECT password_hash FROM ksp.users WHERE id = ?";
+        let (stored_password_hash,) = self
+            .session
+            .query_iter(query, (self_user_id.as_i32_unchecked(),))
+            .await?
+            .rows_stream::<(String,)>()?
+            .try_next()
+            .await?
+            .ok_or(PPError::from("User wasn't found!"))?;
+
+        if !password_matches(password, &stored_password_hash)? {
+            return Err(PPError::from("Invalid password!"));
+        }
+
+        Ok(())
+    }
+
+    /// Drops every session besides the given one
+    pub async fn retain_session(&self, self_user_id: &UserId, session_id: &str) -> PPResult<()> {
+        let query = "UPDATE ksp.users SET sessions = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(
+                &prepared,
+                (vec![session_id.to_owned()], self_user_id.as_i32_unchecked()),
+            )
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn update_password(&self, self_user_id: &UserId, password: &str) -> PPResult<()> {
         // Generate a new salt
         let salt = SaltString::generate(&mut OsRng);
@@ -474,6 +780,48 @@ impl UsersDB {
         Ok(())
     }
 
+    /// SHA256 hash of the latest data export of the user, None if they never exported
+    pub async fn fetch_export_hash(&self, self_user_id: &UserId) -> PPResult<Option<String>> {
+        let query = "SELECT export_hash FROM ksp.users WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+
+        Ok(self
+            .session
+            .execute_iter(prepared, (self_user_id.as_i32_unchecked(),))
+            .await?
+            .rows_stream::<(Option<String>,)>()?
+            .try_next()
+            .await?
+            .and_then(|v| v.0)
+            .filter(|hash| !hash.is_empty()))
+    }
+
+    /// Remembers the latest data export, so it's removed with the account
+    pub async fn update_export_hash(
+        &self,
+        self_user_id: &UserId,
+        sha256_hash: &str,
+    ) -> PPResult<()> {
+        let query = "UPDATE ksp.users SET export_hash = ? WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (sha256_hash, self_user_id.as_i32_unchecked()))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Deletes the user row. Chat associations of the peers must be removed separately
+    pub async fn delete_user(&self, self_user_id: &UserId) -> PPResult<()> {
+        let query = "DELETE FROM ksp.users WHERE id = ?";
+        let prepared = self.session.prepare(query).await?;
+        self.session
+            .execute_unpaged(&prepared, (self_user_id.as_i32_unchecked(),))
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn fetch_user(&self, user_id: &UserId) -> PPResult<Option<User>> {
         Ok(match user_id {
             UserId::UserId(user_id) => {
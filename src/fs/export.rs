@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+
+use log::info;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Serialize;
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    db::{
+        chat::hashes::HashesDB,
+        internal::error::{PPError, PPResult},
+    },
+    server::{
+        message::types::{chat::ChatDetails, message::Message, user::User},
+        server::FILES_MESSAGE_ALLOCATION_SIZE,
+    },
+};
+
+use super::{document::DocumentUploader, FsUploader};
+
+const EXPORT_FILE_NAME: &str = "export.tar";
+
+#[derive(Serialize)]
+pub struct ExportedChat {
+    #[serde(flatten)]
+    pub details: ChatDetails,
+    pub messages: Vec<Message>,
+}
+
+/// Everything that is stored about the user
+#[derive(Serialize)]
+pub struct ExportData {
+    pub profile: User,
+    pub chats: Vec<ExportedChat>,
+}
+
+/// File names are given by the uploaders, so they mustn't leave `files/<sha256_hash>/`
+fn sanitize_file_name(file_name: &str) -> String {
+    let file_name: String = file_name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    match file_name.as_str() {
+        "" | "." | ".." => "file".into(),
+        _ => file_name,
+    }
+}
+
+/// Builds a tar archive with the `export.json` and every file referenced
+/// in the exported messages, placed under `files/<sha256_hash>/<file_name>`
+///
+/// The archive itself is stored as a regular document, so it can be
+/// downloaded through the files server. It must be removed by [`delete_archive`],
+/// once it's replaced by a newer export or the account is deleted.
+///
+/// Returns SHA256 hash of the archive
+pub async fn build_archive(data: &ExportData, hashes_db: &HashesDB) -> PPResult<String> {
+    let mut hashes: Vec<&String> = data
+        .chats
+        .iter()
+        .flat_map(|chat| chat.messages.iter())
+        .filter_map(|message| message.sha256_hashes.as_ref())
+        .flatten()
+        .collect();
+    hashes.sort_unstable();
+    hashes.dedup();
+
+    let mut files: Vec<(String, PathBuf)> = vec![];
+    for sha256_hash in hashes {
+        if let Some(hash_info) = hashes_db.fetch_hash(sha256_hash).await? {
+            files.push((
+                format!(
+                    "files/{}/{}",
+                    sha256_hash,
+                    sanitize_file_name(&hash_info.file_name)
+                ),
+                hash_info.file_path,
+            ));
+        }
+    }
+
+    let json = serde_json::to_vec_pretty(data)?;
+
+    let temp_file: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(15)
+        .map(char::from)
+        .collect();
+    let temp_path = std::env::temp_dir().join(temp_file);
+    info!("Building data export in: {}", temp_path.display());
+
+    // tar is sync, so don't block the runtime
+    let archive_path = temp_path.clone();
+    tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+        let mut builder = tar::Builder::new(std::fs::File::create(&archive_path)?);
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "export.json", json.as_slice())?;
+
+        for (name, path) in files {
+            // File may be missing on the fs, export the rest anyway
+            if path.exists() {
+                builder.append_path_with_name(path, name)?;
+            }
+        }
+
+        builder.finish()
+    })
+    .await
+    .map_err(|err| PPError::Server(Box::new(err)))??;
+
+    let mut uploader = DocumentUploader::new(EXPORT_FILE_NAME).await?;
+    let mut archive = tokio::fs::File::open(&temp_path).await?;
+    let mut buf = vec![0; FILES_MESSAGE_ALLOCATION_SIZE];
+    loop {
+        let read = archive.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        uploader.upload_part(&buf[..read]).await?;
+    }
+    tokio::fs::remove_file(&temp_path).await?;
+
+    uploader.finalize(hashes_db).await
+}
+
+/// Removes the archive from the fs and forgets its hash, so it can't be downloaded anymore
+pub async fn delete_archive(sha256_hash: &str, hashes_db: &HashesDB) -> PPResult<()> {
+    if let Some(hash_info) = hashes_db.fetch_hash(sha256_hash).await? {
+        if let Some(directory) = hash_info.file_path.parent() {
+            if tokio::fs::try_exists(directory).await? {
+                tokio::fs::remove_dir_all(directory).await?;
+            }
+        }
+    }
+
+    hashes_db.delete_hash(sha256_hash).await
+}
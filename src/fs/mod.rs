@@ -6,6 +6,8 @@ pub mod media;
 mod hasher;
 pub(super) mod helpers;
 pub mod document;
+pub mod export;
+pub mod search;
 
 pub trait FsUploader {
     /// Uploads only part of the file to fs
@@ -0,0 +1,254 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex, OnceLock,
+};
+use std::time::Duration;
+
+use log::info;
+use tantivy::{
+    collector::TopDocs,
+    directory::MmapDirectory,
+    query::{BooleanQuery, Occur, QueryParser, TermSetQuery},
+    schema::{Field, Schema, Value, FAST, INDEXED, STORED, STRING, TEXT},
+    Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term,
+};
+
+use crate::{
+    db::internal::error::{PPError, PPResult},
+    server::message::types::{chat::ChatId, request::send::MessageId},
+};
+
+use super::FS_BASE;
+
+const INDEX_DIRECTORY: &str = "search_index";
+/// 50 Mib
+const WRITER_MEMORY_BUDGET: usize = 50 * 1024 * 1024;
+pub const MAX_SEARCH_LIMIT: usize = 100;
+/// Changes are committed at least this often, see [`MessageIndex::commit`]
+pub const COMMIT_INTERVAL: Duration = Duration::from_secs(1);
+/// Changed documents, after which the writer commits without waiting for the interval
+const MAX_UNCOMMITTED: usize = 1000;
+
+static MESSAGE_INDEX: OnceLock<MessageIndex> = OnceLock::new();
+
+struct Fields {
+    /// `{chat_id}:{message_id}`, to delete single messages
+    key: Field,
+    chat_id: Field,
+    message_id: Field,
+    content: Field,
+}
+
+/// Inverted index over the text of messages, stored on the fs
+///
+/// Only ids are stored, messages themselves are fetched from the database.
+/// Changes are committed in batches, the reader picks them up on its own after each commit
+pub struct MessageIndex {
+    fields: Fields,
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    /// Documents changed since the last commit
+    uncommitted: AtomicUsize,
+}
+
+fn tantivy_err(err: impl std::error::Error + 'static) -> PPError {
+    PPError::Server(Box::new(err))
+}
+
+fn message_key(chat_id: ChatId, message_id: MessageId) -> String {
+    format!("{}:{}", chat_id, message_id)
+}
+
+/// Opens the index, creating it if needed. Must be called once on startup
+pub fn init() -> PPResult<()> {
+    let mut schema = Schema::builder();
+    let fields = Fields {
+        key: schema.add_text_field("key", STRING),
+        chat_id: schema.add_i64_field("chat_id", INDEXED | STORED | FAST),
+        message_id: schema.add_i64_field("message_id", STORED),
+        content: schema.add_text_field("content", TEXT),
+    };
+
+    let path = std::path::PathBuf::from(FS_BASE).join(INDEX_DIRECTORY);
+    std::fs::create_dir_all(&path)?;
+    info!("Opening search index in: {}", path.display());
+
+    let index = Index::open_or_create(
+        MmapDirectory::open(&path).map_err(tantivy_err)?,
+        schema.build(),
+    )
+    .map_err(tantivy_err)?;
+    let writer = index.writer(WRITER_MEMORY_BUDGET).map_err(tantivy_err)?;
+    let reader = index
+        .reader_builder()
+        .reload_policy(ReloadPolicy::OnCommitWithDelay)
+        .try_into()
+        .map_err(tantivy_err)?;
+
+    MESSAGE_INDEX
+        .set(MessageIndex {
+            fields,
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            uncommitted: AtomicUsize::new(0),
+        })
+        .map_err(|_| PPError::from("Search index is already initialized"))
+}
+
+pub fn message_index() -> PPResult<&'static MessageIndex> {
+    MESSAGE_INDEX
+        .get()
+        .ok_or(PPError::from("Search index isn't initialized"))
+}
+
+impl MessageIndex {
+    pub fn is_empty(&self) -> bool {
+        self.reader.searcher().num_docs() == 0
+    }
+
+    /// Runs a blocking operation with the writer, changing `changed` documents
+    ///
+    /// Commits only once enough changes piled up, otherwise they wait for [`Self::commit`]
+    async fn write(
+        &'static self,
+        changed: usize,
+        op: impl FnOnce(&Fields, &mut IndexWriter) -> tantivy::Result<()> + Send + 'static,
+    ) -> PPResult<()> {
+        tokio::task::spawn_blocking(move || -> tantivy::Result<()> {
+            let mut writer = self.writer.lock().unwrap_or_else(|err| err.into_inner());
+            op(&self.fields, &mut writer)?;
+            let uncommitted = self.uncommitted.fetch_add(changed, Ordering::Relaxed) + changed;
+            if uncommitted >= MAX_UNCOMMITTED {
+                self.uncommitted.store(0, Ordering::Relaxed);
+                writer.commit()?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(tantivy_err)?
+        .map_err(tantivy_err)
+    }
+
+    /// Commits pending changes, if there are any. Called every [`COMMIT_INTERVAL`]
+    pub async fn commit(&'static self) -> PPResult<()> {
+        if self.uncommitted.load(Ordering::Relaxed) == 0 {
+            return Ok(());
+        }
+
+        tokio::task::spawn_blocking(move || -> tantivy::Result<()> {
+            let mut writer = self.writer.lock().unwrap_or_else(|err| err.into_inner());
+            if self.uncommitted.swap(0, Ordering::Relaxed) > 0 {
+                writer.commit()?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(tantivy_err)?
+        .map_err(tantivy_err)
+    }
+
+    /// Adds the messages, replacing already indexed ones with the same ids
+    pub async fn index_messages(
+        &'static self,
+        messages: Vec<(ChatId, MessageId, String)>,
+    ) -> PPResult<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        self.write(messages.len(), move |fields, writer| {
+            for (chat_id, message_id, content) in messages {
+                let key = message_key(chat_id, message_id);
+                writer.delete_term(Term::from_field_text(fields.key, &key));
+
+                let mut doc = TantivyDocument::default();
+                doc.add_text(fields.key, key);
+                doc.add_i64(fields.chat_id, chat_id.into());
+                doc.add_i64(fields.message_id, message_id.into());
+                doc.add_text(fields.content, content);
+                writer.add_document(doc)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn remove_messages(
+        &'static self,
+        chat_id: ChatId,
+        message_ids: Vec<MessageId>,
+    ) -> PPResult<()> {
+        self.write(message_ids.len(), move |fields, writer| {
+            for message_id in message_ids {
+                writer.delete_term(Term::from_field_text(
+                    fields.key,
+                    &message_key(chat_id, message_id),
+                ));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn remove_chat(&'static self, chat_id: ChatId) -> PPResult<()> {
+        self.write(1, move |fields, writer| {
+            writer.delete_term(Term::from_field_i64(fields.chat_id, chat_id.into()));
+            Ok(())
+        })
+        .await
+    }
+
+    /// Searches the query within the given chats, the most relevant first
+    ///
+    /// Returns `(real chat_id, message_id)` pairs
+    pub async fn search(
+        &'static self,
+        chat_ids: Vec<ChatId>,
+        query: String,
+        offset: usize,
+        limit: usize,
+    ) -> PPResult<Vec<(ChatId, MessageId)>> {
+        if chat_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        tokio::task::spawn_blocking(move || -> tantivy::Result<Vec<(ChatId, MessageId)>> {
+            let fields = &self.fields;
+            let parser = QueryParser::for_index(&self.index, vec![fields.content]);
+            // Users don't know the query syntax, so don't fail on it
+            let (text_query, _) = parser.parse_query_lenient(&query);
+            let chats_query = TermSetQuery::new(
+                chat_ids
+                    .into_iter()
+                    .map(|chat_id| Term::from_field_i64(fields.chat_id, chat_id.into())),
+            );
+            let query = BooleanQuery::new(vec![
+                (Occur::Must, text_query),
+                (Occur::Must, Box::new(chats_query)),
+            ]);
+
+            let searcher = self.reader.searcher();
+            let top_docs = searcher.search(
+                &query,
+                &TopDocs::with_limit(limit.clamp(1, MAX_SEARCH_LIMIT)).and_offset(offset),
+            )?;
+
+            let mut found = Vec::with_capacity(top_docs.len());
+            for (_, address) in top_docs {
+                let doc: TantivyDocument = searcher.doc(address)?;
+                let chat_id = doc.get_first(fields.chat_id).and_then(|v| v.as_i64());
+                let message_id = doc.get_first(fields.message_id).and_then(|v| v.as_i64());
+                if let (Some(chat_id), Some(message_id)) = (chat_id, message_id) {
+                    found.push((chat_id as ChatId, message_id as MessageId));
+                }
+            }
+
+            Ok(found)
+        })
+        .await
+        .map_err(tantivy_err)?
+        .map_err(tantivy_err)
+    }
+}
@@ -50,6 +50,7 @@ fn init_logging() {
 async fn main() {
     init_logging();
 
+    fs::search::init().expect("Failed to open search index");
     create_tables().await;
     let server = Server::new(JSON_MESSAGES_PORT, FILE_MESSAGES_PORT).await;
 
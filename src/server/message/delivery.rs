@@ -0,0 +1,200 @@
+use std::sync::{Arc, Weak};
+
+use futures::future::join_all;
+use log::error;
+use serde::Serialize;
+
+use crate::db::bucket::{DatabaseBucket, DatabaseBuilder};
+use crate::db::chat::subscribers::SubscribersDB;
+use crate::server::message::handlers::json_handler::SessionArcRwLock;
+use crate::server::message::types::chat::ChatId;
+use crate::server::server::{LiveSessions, Sessions};
+
+/// Online users, looked up as subscribers of a channel by a single query
+const SUBSCRIBERS_BATCH: usize = 100;
+
+/// Everything needed to store messages and notify the receivers
+///
+/// Not bound to a connection, so background tasks(e.g. the scheduler)
+/// deliver messages the same way as the JSON handler does
+#[derive(Clone)]
+pub struct Delivery {
+    bucket: DatabaseBucket,
+    sessions: Sessions,
+    live_sessions: LiveSessions,
+    /// Connection on behalf of which events are sent, it never receives them itself
+    origin: Option<SessionArcRwLock>,
+}
+
+impl Delivery {
+    pub fn new(bucket: DatabaseBucket, sessions: Sessions, live_sessions: LiveSessions) -> Self {
+        Self {
+            bucket,
+            sessions,
+            live_sessions,
+            origin: None,
+        }
+    }
+
+    pub fn with_origin(mut self, origin: SessionArcRwLock) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    pub fn get_db<T: From<DatabaseBuilder>>(&self) -> T {
+        DatabaseBuilder::from(self.bucket.clone()).into()
+    }
+
+    /// Sends the event to the user's connection without waiting for it
+    ///
+    /// Events the user sends to themselves(Saved Messages) go to every other
+    /// connection of the user instead.
+    /// If user isn't connected to the server, nothing happens
+    pub fn send_event_to_con_detached(
+        &self,
+        to: i32,
+        msg: impl Serialize + std::fmt::Debug + Clone + Send + 'static,
+    ) {
+        tokio::spawn({
+            let delivery = self.clone();
+            async move {
+                if delivery.is_origin_user(to).await {
+                    delivery.send_to_own_sessions(to, msg).await;
+                    return;
+                }
+
+                let receiver_session = delivery
+                    .sessions
+                    .get(&to)
+                    .map(|session| Arc::clone(session.value()));
+                if let Some(receiver_session) = receiver_session {
+                    let mut target_connection = receiver_session.write().await;
+
+                    target_connection.mpsc_send(msg, 0).await;
+                }
+            }
+        });
+    }
+
+    /// Sends the event to every live connection of the user, besides the origin,
+    /// without waiting for it
+    pub fn send_event_to_own_sessions(
+        &self,
+        user_id: i32,
+        msg: impl Serialize + std::fmt::Debug + Clone + Send + 'static,
+    ) {
+        tokio::spawn({
+            let delivery = self.clone();
+            async move {
+                delivery.send_to_own_sessions(user_id, msg).await;
+            }
+        });
+    }
+
+    async fn is_origin_user(&self, user_id: i32) -> bool {
+        match self.origin.as_ref() {
+            Some(origin) => origin
+                .read()
+                .await
+                .get_credentials()
+                .is_some_and(|(id, _)| id.as_i32_unchecked() == user_id),
+            None => false,
+        }
+    }
+
+    async fn send_to_own_sessions(
+        &self,
+        user_id: i32,
+        msg: impl Serialize + std::fmt::Debug + Clone,
+    ) {
+        let live: Vec<SessionArcRwLock> = self
+            .live_sessions
+            .get(&user_id)
+            .map(|live| live.iter().filter_map(Weak::upgrade).collect())
+            .unwrap_or_default();
+
+        for session in live {
+            if self
+                .origin
+                .as_ref()
+                .is_some_and(|origin| Arc::ptr_eq(origin, &session))
+            {
+                continue;
+            }
+            let mut session = session.write().await;
+            // The connection may have been authenticated as someone else since then
+            if session
+                .get_credentials()
+                .is_some_and(|(id, _)| id.as_i32_unchecked() == user_id)
+            {
+                session.mpsc_send(msg.clone(), 0).await;
+            }
+        }
+    }
+
+    /// same as `send_event_to_con_detached`, but multiple
+    pub fn send_events_to_connections<I, M>(&self, recv_msgs: I)
+    where
+        I: IntoIterator<Item = (i32, M)> + Send + 'static,
+        M: Serialize + std::fmt::Debug + Send + 'static,
+        <I as std::iter::IntoIterator>::IntoIter: std::marker::Send,
+    {
+        tokio::spawn({
+            let connections = Arc::clone(&self.sessions);
+            async move {
+                for (to, msg) in recv_msgs {
+                    if let Some(receiver_session) = connections.get(&to) {
+                        let mut target_connection = receiver_session.write().await;
+
+                        target_connection.mpsc_send(msg, 0).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Sends the event to every connected subscriber of the channel without waiting for it
+    ///
+    /// Subscribers are streamed page by page, so they are never loaded in memory at once
+    /// Only subscribers, who are online, are looked up, the events are sent in parallel
+    pub fn send_event_to_subscribers<M>(&self, chat_id: ChatId, except: Option<i32>, msg: M)
+    where
+        M: Serialize + std::fmt::Debug + Clone + Send + Sync + 'static,
+    {
+        let online: Vec<i32> = self
+            .sessions
+            .iter()
+            .map(|session| *session.key())
+            .filter(|&user_id| except != Some(user_id))
+            .collect();
+
+        tokio::spawn({
+            let delivery = self.clone();
+            async move {
+                let subscribers_db: SubscribersDB = delivery.get_db();
+                for batch in online.chunks(SUBSCRIBERS_BATCH) {
+                    let subscribers = match subscribers_db.filter_subscribers(chat_id, batch).await
+                    {
+                        Ok(subscribers) => subscribers,
+                        Err(err) => {
+                            error!("Error while notifying subscribers of {}: {}", chat_id, err);
+                            return;
+                        }
+                    };
+
+                    // The map mustn't stay locked while the events are being written
+                    let receivers: Vec<SessionArcRwLock> = subscribers
+                        .iter()
+                        .filter_map(|user_id| delivery.sessions.get(user_id))
+                        .map(|session| Arc::clone(session.value()))
+                        .collect();
+                    join_all(receivers.iter().map(|session| {
+                        let msg = msg.clone();
+                        async move { session.write().await.mpsc_send(msg, 0).await }
+                    }))
+                    .await;
+                }
+            }
+        });
+    }
+}
@@ -1,4 +1,5 @@
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::sync::{Arc, Weak};
 
 use log::{debug, info};
 use serde::Serialize;
@@ -10,11 +11,15 @@ use crate::db::bucket::{DatabaseBucket, DatabaseBuilder};
 use crate::db::internal::error::PPError;
 use crate::server::connection::TCPConnection;
 use crate::server::message::builder::MessageBuilder;
-use crate::server::message::methods::{auth, bind, check, edit, fetch, join, new, send};
+use crate::server::message::delivery::Delivery;
+use crate::server::message::methods::{
+    auth, bind, check, edit, export, fetch, forward, group, join, new, send,
+};
 use crate::server::message::types::response::events::IsTypingEvent;
 use crate::server::message::types::user::UserId;
 use crate::server::message::Handler;
-use crate::server::server::Sessions;
+use crate::server::rate_limiter::RateLimiter;
+use crate::server::server::{LiveSessions, Sessions};
 use crate::server::session::Session;
 
 use super::reqid_tracker;
@@ -38,12 +43,18 @@ pub struct JsonHandler {
     is_message_first: bool,
     pub session: SessionArcRwLock,
     pub sessions: Sessions,
+    /// Every authenticated connection of each user
+    pub live_sessions: LiveSessions,
     /// Output TCP connection on which all the responses/messages are sent
     pub output_connection: Arc<TCPConnection>,
     bucket: DatabaseBucket,
     /// Mpsc Sender on receiver task for is_typing event
     /// TODO: Maybe better 'is_typing' event sending?
     is_typing_tx: mpsc::Sender<TypingEventMsg>,
+    /// Remote address of the connection
+    pub addr: SocketAddr,
+    /// Shared throttling of auth attempts
+    pub rate_limiter: Arc<RateLimiter>,
 
     last_req_id: Option<i64>,
 }
@@ -191,7 +202,10 @@ impl JsonHandler {
     pub async fn new(
         session: Arc<RwLock<Session>>,
         sessions: Sessions,
+        live_sessions: LiveSessions,
         bucket: DatabaseBucket,
+        addr: SocketAddr,
+        rate_limiter: Arc<RateLimiter>,
     ) -> Self {
         let output_connection = {
             let session_locked = session.read().await;
@@ -212,10 +226,13 @@ impl JsonHandler {
             builder: None,
             session: Arc::clone(&session),
             sessions,
+            live_sessions,
             output_connection,
             is_message_first: true,
             bucket,
             is_typing_tx: tx,
+            addr,
+            rate_limiter,
             last_req_id: None,
         }
     }
@@ -275,18 +292,9 @@ impl JsonHandler {
     pub fn send_event_to_con_detached(
         &self,
         to: i32,
-        msg: impl Serialize + std::fmt::Debug + Send + 'static,
+        msg: impl Serialize + std::fmt::Debug + Clone + Send + 'static,
     ) {
-        tokio::spawn({
-            let connections = Arc::clone(&self.sessions);
-            async move {
-                if let Some(receiver_session) = connections.get(&to) {
-                    let mut target_connection = receiver_session.write().await;
-
-                    target_connection.mpsc_send(msg, 0).await;
-                }
-            }
-        });
+        self.delivery().send_event_to_con_detached(to, msg);
     }
 
     /// same as `send_event_to_con_detached`, but multiple
@@ -296,18 +304,49 @@ impl JsonHandler {
         M: Serialize + std::fmt::Debug + Send + 'static,
         <I as std::iter::IntoIterator>::IntoIter: std::marker::Send,
     {
-        tokio::spawn({
-            let connections = Arc::clone(&self.sessions);
-            async move {
-                for (to, msg) in recv_msgs {
-                    if let Some(receiver_session) = connections.get(&to) {
-                        let mut target_connection = receiver_session.write().await;
+        self.delivery().send_events_to_connections(recv_msgs);
+    }
 
-                        target_connection.mpsc_send(msg, 0).await;
-                    }
-                }
+    /// Remembers the connection as one of the live sessions of the user
+    pub fn add_live_session(&self, user_id: i32) {
+        let mut live = self.live_sessions.entry(user_id).or_default();
+        live.retain(|session| session.strong_count() > 0);
+        live.push(Arc::downgrade(&self.session));
+    }
+
+    /// Deauthenticates every other connection of the user, besides the ones
+    /// authenticated with `keep_session_id`
+    pub async fn deauthenticate_live_sessions(&self, user_id: i32, keep_session_id: Option<&str>) {
+        let live: Vec<SessionArcRwLock> = self
+            .live_sessions
+            .get(&user_id)
+            .map(|live| live.iter().filter_map(Weak::upgrade).collect())
+            .unwrap_or_default();
+
+        for session in live {
+            if Arc::ptr_eq(&session, &self.session) {
+                continue;
             }
-        });
+            let mut session = session.write().await;
+            // The connection may have been authenticated as someone else since then
+            let is_invalidated = session
+                .get_credentials()
+                .is_some_and(|(id, session_id)| {
+                    id.as_i32_unchecked() == user_id && Some(session_id.as_str()) != keep_session_id
+                });
+            if is_invalidated {
+                session.deauthenticate();
+            }
+        }
+    }
+
+    pub fn delivery(&self) -> Delivery {
+        Delivery::new(
+            self.bucket.clone(),
+            Arc::clone(&self.sessions),
+            Arc::clone(&self.live_sessions),
+        )
+        .with_origin(Arc::clone(&self.session))
     }
 
     // Function to get any database by just passing the type
@@ -337,7 +376,7 @@ impl JsonHandler {
 
                 match value.get("method").and_then(Value::as_str) {
                     Some(method) => match method {
-                        "login" | "auth" | "register" => auth::handle(self, method).await,
+                        "login" | "login_2fa" | "auth" | "register" => auth::handle(self, method).await,
                         "send_message" => send::handle(self, method).await,
                         "edit" | "delete" => edit::handle(self, method).await,
                         "fetch" => fetch::handle(self, method).await,
@@ -345,6 +384,11 @@ impl JsonHandler {
                         "bind" => bind::handle(self, method).await,
                         "new" => new::handle(self, method).await,
                         "join" => join::handle(self, method).await,
+                        "export" => export::handle(self, method).await,
+                        "forward" => forward::handle(self, method).await,
+                        "add" | "leave" | "kick" | "ban" | "unban" | "approve" | "decline" => {
+                            group::handle(self, method).await
+                        }
                         _ => {
                             self.send_error(method, "Unknown method given!".into())
                                 .await
@@ -4,34 +4,109 @@ use crate::{
     db::{internal::error::{PPError, PPResult}, user::UsersDB},
     server::{
         message::{
-            handlers::json_handler::JsonHandler, types::{request::auth::*, response::auth::{AuthResponse, RegisterResponse}}
+            handlers::json_handler::JsonHandler, types::{request::auth::*, response::auth::{AuthResponse, NeedTwoFactorResponse, RegisterResponse}}
         },
-        session::{AuthComponent, Session},
+        session::{AuthComponent, LoginStep, Session},
     },
 };
 
 
+/// Why an auth attempt failed
+enum AuthError {
+    /// Wrong password, session or second factor. Counted towards the lockout
+    Credentials(PPError),
+    /// e.g. malformed request or taken username
+    Other(PPError),
+}
+
+impl From<PPError> for AuthError {
+    fn from(err: PPError) -> Self {
+        AuthError::Other(err)
+    }
+}
+
+impl From<serde_json::Error> for AuthError {
+    fn from(err: serde_json::Error) -> Self {
+        AuthError::Other(err.into())
+    }
+}
+
+/// Client errors of a credentials check mean the credentials are wrong, server errors don't
+fn credentials_error(err: PPError) -> AuthError {
+    match err {
+        PPError::Client(_) => AuthError::Credentials(err),
+        _ => AuthError::Other(err),
+    }
+}
+
 async fn handle_auth_message<'a, T, F, Fut>(
     buffer: &str,
     session: &'a mut Session,
     users_db: UsersDB,
     from_func: F,
-) -> Result<(), PPError>
+) -> Result<(), AuthError>
 where
     T: serde::de::DeserializeOwned,
     F: FnOnce(UsersDB, T) -> Fut + Send + 'a,
     Fut: Future<Output = PPResult<AuthComponent>> + Send,
 {
-    match serde_json::from_str::<T>(buffer) {
-        Ok(auth_message) => {
-            Session::authenticate(session, from_func(users_db, auth_message).await?);
-        },
-        Err(err) => return Err(err.into()),
+    let auth_message = serde_json::from_str::<T>(buffer)?;
+    let auth_component = from_func(users_db, auth_message)
+        .await
+        .map_err(credentials_error)?;
+    Session::authenticate(session, auth_component);
+
+    Ok(())
+}
+
+/// First login step. May leave the session waiting for the second factor
+async fn handle_login_message(
+    buffer: &str,
+    session: &mut Session,
+    users_db: UsersDB,
+) -> Result<(), AuthError> {
+    let req = serde_json::from_str::<LoginRequest>(buffer)?;
+    match AuthComponent::from_login(users_db, req).await.map_err(credentials_error)? {
+        LoginStep::Done(auth_component) => Session::authenticate(session, auth_component),
+        LoginStep::NeedTwoFactor(user_id) => session.set_pending_two_factor(user_id),
     }
 
     Ok(())
 }
 
+async fn handle_two_factor_message(
+    buffer: &str,
+    session: &mut Session,
+    users_db: UsersDB,
+) -> Result<(), AuthError> {
+    let user_id = session
+        .pending_two_factor()
+        .ok_or(PPError::from("No login is waiting for the two-factor authentication code!"))?;
+    let req = serde_json::from_str::<TwoFactorRequest>(buffer)?;
+    let auth_component = AuthComponent::from_two_factor(users_db, user_id, req)
+        .await
+        .map_err(credentials_error)?;
+    Session::authenticate(session, auth_component);
+
+    Ok(())
+}
+
+/// The account, auth attempts are throttled on: username for `login`/`register`, user_id for `auth`
+fn extract_account_key(buffer: &str) -> Option<String> {
+    let value = serde_json::from_str::<serde_json::Value>(buffer).ok()?;
+    if let Some(username) = value.get("username").and_then(|v| v.as_str()) {
+        return Some(normalize_username(username));
+    }
+
+    value.get("user_id").and_then(|v| v.as_i64()).map(|v| v.to_string())
+}
+
+/// Spellings of a username, differing in the case or the leading '@', share the limits
+fn normalize_username(username: &str) -> String {
+    let username = username.trim();
+    format!("@{}", username.strip_prefix('@').unwrap_or(username)).to_lowercase()
+}
+
 pub async fn handle(handler: &mut JsonHandler, method: &str) {
     let buffer = handler.utf8_content_unchecked().clone();
 
@@ -43,19 +118,23 @@ pub async fn handle(handler: &mut JsonHandler, method: &str) {
         }
     }
 
+    let ip = handler.addr.ip();
+    let account = match method {
+        "login_2fa" => handler.session.read().await.pending_two_factor().map(|id| id.to_string()),
+        _ => extract_account_key(&buffer),
+    };
+    if let Err(err) = handler.rate_limiter.check(ip, account.as_deref()) {
+        handler.send_error(method, err).await;
+        return;
+    }
+
     let res = {
         let mut session = handler.session.write().await;
         let users_db: UsersDB = handler.get_db();
 
         match method {
-            "login" =>
-                handle_auth_message::<LoginRequest, _, _>(
-                    buffer.as_str(),
-                    &mut session,
-                    users_db,
-                    AuthComponent::from_login,
-                )
-                .await,
+            "login" => handle_login_message(buffer.as_str(), &mut session, users_db).await,
+            "login_2fa" => handle_two_factor_message(buffer.as_str(), &mut session, users_db).await,
             "auth" =>  handle_auth_message::<AuthRequest, _, _>(
                     buffer.as_str(),
                     &mut session,
@@ -70,19 +149,30 @@ pub async fn handle(handler: &mut JsonHandler, method: &str) {
                     AuthComponent::from_register,
                 )
                 .await,
-            _ => Err("Invalid method provided!".into()),
+            _ => Err(PPError::from("Invalid method provided!").into()),
         }
     };
 
     if let Err(err) = res {
+        let err = match err {
+            // Failed register, e.g. taken username, isn't a wrong credential
+            AuthError::Credentials(err) if method != "register" => {
+                handler.rate_limiter.register_failure(ip, account.as_deref());
+                err
+            }
+            AuthError::Credentials(err) | AuthError::Other(err) => err,
+        };
         handler.send_error(method, err).await;
         return;
     }
 
     if let Some((user_id, session_id)) = handler.session.read().await.get_credentials() {
+        // Only a complete login resets the failures, not the password step before the second factor
+        handler.rate_limiter.register_success(account.as_deref());
         let user_id = user_id.as_i32().unwrap();
         {
             handler.sessions.insert(user_id, Arc::clone(&handler.session));
+            handler.add_live_session(user_id);
         }
 
 
@@ -91,5 +181,7 @@ pub async fn handle(handler: &mut JsonHandler, method: &str) {
             _ => serde_json::to_value(RegisterResponse{ ok: true, method: method.into(), user_id, session_id}).unwrap(),
         };
         handler.send_message(&data).await;
-    };
+    } else if method == "login" {
+        handler.send_message(&NeedTwoFactorResponse{ ok: true, method: method.into(), need_2fa: true }).await;
+    }
 }
@@ -1,27 +1,58 @@
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::TryStreamExt;
 use log::debug;
 
 use crate::{
     db::{
-        chat::{chats::ChatsDB, drafts::DraftsDB, hashes::HashesDB, messages::MessagesDB},
+        chat::{
+            chats::ChatsDB, drafts::DraftsDB, hashes::HashesDB, hidden::HiddenMessagesDB,
+            invitations::InvitationsDB, join_requests::JoinRequestsDB, messages::MessagesDB,
+            reactions::ReactionsDB, read_cursors::ReadCursorsDB, scheduled::ScheduledMessagesDB,
+            subscribers::SubscribersDB, views::ViewsDB,
+        },
         internal::error::PPResult,
         user::UsersDB,
     },
+    fs::export::delete_archive,
     server::message::{
         handlers::json_handler::JsonHandler,
         types::{
             edit::EditedMessageBuilder,
             request::{
-                delete::{DeleteAllMessagesRequest, DeleteChatRequest, DeleteMessagesRequest},
-                edit::{EditDraftRequest, EditMessageRequest, EditSelfRequest, MarkAsReadRequest},
+                delete::{
+                    DeleteAllMessagesRequest, DeleteChatRequest, DeleteInvitationLinkRequest,
+                    DeleteMessagesRequest, DeleteScheduledRequest, DeleteSelfRequest,
+                },
+                edit::{
+                    EditAutoDeleteRequest, EditDraftRequest, EditGroupRequest, EditMessageRequest,
+                    EditPermissionsRequest, EditReactionRequest, EditRoleRequest,
+                    EditScheduledRequest, EditSelfRequest, MarkAsReadRequest, PinMessageRequest,
+                },
                 extract_what_field,
             },
             response::{
-                delete::{DeleteAllMessagesResponse, DeleteChatResponse, DeleteMessagesResponse},
-                edit::{EditDraftResponse, EditMessageResponse, MarkAsReadResponse},
+                delete::{
+                    DeleteAllMessagesResponse, DeleteChatResponse, DeleteInvitationLinkResponse,
+                    DeleteMessagesResponse, DeleteScheduledResponse, DeleteSelfResponse,
+                },
+                edit::{
+                    EditAutoDeleteResponse, EditDraftResponse, EditGroupResponse,
+                    EditMessageResponse,
+                    EditPermissionsResponse, EditReactionResponse, EditRoleResponse,
+                    EditScheduledResponse, EditSelfResponse, MarkAsReadResponse,
+                    PinMessageResponse,
+                },
                 events::{
-                    DeleteMessagesEvent, EditMessageEvent, EditSelfEvent, IsTypingEvent, MarkAsReadEvent
+                    AccountDeletedEvent, AutoDeleteEvent, DeleteMessagesEvent, EditMessageEvent,
+                    EditSelfEvent, GroupUpdatedEvent, IsTypingEvent, MarkAsReadEvent, PermissionsEvent,
+                    PinMessageEvent, ReactionEvent, RoleEvent,
                 },
             },
+            chat::{ChatDetails, ChatId, Permission, Permissions, Role},
+            message::ScheduledMessage,
             user::{User, UserId},
         },
     },
@@ -63,6 +94,12 @@ async fn handle_edit_message(handler: &mut JsonHandler, msg: EditMessageRequest)
                 return Err(format!("Provided SHA256 Hash: {} doesn't exist!", hash).into());
             }
         }
+        if real_chat_id.is_negative() && !hashes.is_empty() {
+            handler
+                .get_db::<ChatsDB>()
+                .check_permission(real_chat_id, &self_user_id, Permission::SendMedia)
+                .await?;
+        }
     }
 
     let builder = EditedMessageBuilder::from(msg);
@@ -75,9 +112,15 @@ async fn handle_edit_message(handler: &mut JsonHandler, msg: EditMessageRequest)
     }
     debug!("Existing Message: {:?}", existing_message);
 
-    let edited_msg = builder.get_edited_message(existing_message);
+    let mut edited_msg = messages_db
+        .edit_message(
+            msg_id,
+            real_chat_id,
+            builder.get_edited_message(existing_message),
+        )
+        .await?;
     messages_db
-        .edit_message(msg_id, real_chat_id, edited_msg.clone())
+        .attach_reply_previews(real_chat_id, std::slice::from_mut(&mut edited_msg))
         .await?;
     debug!("Edited Message: {:?}", edited_msg);
 
@@ -106,6 +149,14 @@ async fn handle_edit_message(handler: &mut JsonHandler, msg: EditMessageRequest)
             event: "edit_message".into(),
             new_message: edited_msg,
         };
+        if chat.is_channel() {
+            handler.delivery().send_event_to_subscribers(
+                real_chat_id,
+                Some(self_user_id.as_i32_unchecked()),
+                ev,
+            );
+            return Ok(());
+        }
 
         let receivers: Vec<_> = chat
             .participants()
@@ -119,6 +170,9 @@ async fn handle_edit_message(handler: &mut JsonHandler, msg: EditMessageRequest)
     Ok(())
 }
 
+/// Most messages of a channel, that are viewed at once
+const MAX_VIEWED_AT_ONCE: usize = 100;
+
 async fn handle_mark_as_read(handler: &mut JsonHandler, msg: &MarkAsReadRequest) -> PPResult<()> {
     let self_user_id = {
         let session = handler.session.read().await;
@@ -135,12 +189,57 @@ async fn handle_mark_as_read(handler: &mut JsonHandler, msg: &MarkAsReadRequest)
         .await?
         .ok_or("Given ChatId doesn't exist!")?;
 
-    messages_db.mark_as_read(chat_id, &msg.message_ids).await?;
+    let read_cursors_db: ReadCursorsDB = handler.get_db();
+
+    let is_channel = chat_id.is_negative() && chats_db.is_channel(chat_id).await?;
+    // Cursor can't pass the latest message of the chat
+    let latest_id = messages_db.get_latest(chat_id).await?;
+    let last_read_id = msg
+        .message_ids
+        .iter()
+        .max()
+        .zip(latest_id)
+        .map(|(&last_read_id, latest_id)| last_read_id.min(latest_id));
+    if let Some(last_read_id) = last_read_id {
+        let previous = read_cursors_db.fetch_cursor(chat_id, &self_user_id).await?;
+        let moved = read_cursors_db
+            .advance(chat_id, &self_user_id, last_read_id)
+            .await?;
+
+        // Every subscriber views a message once, when the cursor passes it
+        if is_channel && moved {
+            let mut viewed: Vec<_> = msg
+                .message_ids
+                .iter()
+                .copied()
+                .filter(|&id| id <= last_read_id && previous.is_none_or(|previous| id > previous))
+                .collect();
+            viewed.sort_unstable();
+            viewed.dedup();
+            viewed.truncate(MAX_VIEWED_AT_ONCE);
+            // Only existing messages are viewed
+            let viewed: Vec<_> = messages_db
+                .fetch_messages_by_ids(chat_id, &viewed)
+                .await?
+                .into_iter()
+                .map(|message| message.message_id)
+                .collect();
+            handler
+                .get_db::<ViewsDB>()
+                .add_views(chat_id, &viewed)
+                .await?;
+        }
+    }
+    // Subscribers of channels don't notify anyone, views are counted instead
+    if is_channel {
+        return Ok(());
+    }
 
     let ev = MarkAsReadEvent {
         event: "mark_as_read".into(),
         chat_id: self_user_id.as_i32_unchecked(),
         message_ids: msg.message_ids.clone(),
+        user_id: self_user_id.as_i32_unchecked(),
     };
 
     if msg.chat_id.is_positive() {
@@ -166,174 +265,135 @@ async fn handle_mark_as_read(handler: &mut JsonHandler, msg: &MarkAsReadRequest)
     Ok(())
 }
 
-async fn handle_edit_draft(handler: &mut JsonHandler, msg: &EditDraftRequest) -> PPResult<()> {
+async fn handle_edit_reaction(
+    handler: &mut JsonHandler,
+    msg: &EditReactionRequest,
+) -> PPResult<()> {
     let self_user_id = {
         let session = handler.session.read().await;
         let (user_id, _) = session.get_credentials_unchecked();
         user_id
     };
 
-    let drafts_db: DraftsDB = handler.get_db();
     let users_db: UsersDB = handler.get_db();
+    let messages_db: MessagesDB = handler.get_db();
     let chats_db: ChatsDB = handler.get_db();
+    let reactions_db: ReactionsDB = handler.get_db();
 
     let real_chat_id = users_db
         .get_associated_chat_id(&self_user_id, msg.chat_id)
         .await?
         .ok_or("Chat with the given chat_id doesn't exist!")?;
 
-    let event_chat_id = if msg.chat_id.is_positive() {
-        self_user_id.as_i32_unchecked()
-    } else {
-        real_chat_id
+    if !messages_db
+        .message_exists(real_chat_id, msg.message_id)
+        .await?
+    {
+        return Err("Message with the given message_id wasn't found!".into());
+    }
+
+    match msg.reaction.as_ref() {
+        Some(reaction) => {
+            reactions_db
+                .set_reaction(real_chat_id, msg.message_id, &self_user_id, reaction)
+                .await?
+        }
+        None => {
+            reactions_db
+                .remove_reaction(real_chat_id, msg.message_id, &self_user_id)
+                .await?
+        }
+    }
+
+    let ev = ReactionEvent {
+        event: "reaction".into(),
+        chat_id: self_user_id.as_i32_unchecked(),
+        message_id: msg.message_id,
+        user_id: self_user_id.as_i32_unchecked(),
+        reaction: msg.reaction.clone(),
     };
 
-    let users = if msg.chat_id.is_positive() {
-        vec![UserId::UserId(msg.chat_id)]
+    if msg.chat_id.is_positive() {
+        handler.send_event_to_con_detached(msg.chat_id, ev);
     } else {
+        let mut ev = ev;
         let (group, _) = chats_db
             .fetch_chat(&self_user_id, real_chat_id)
             .await?
-            .expect("chat to exist");
-        group
+            .ok_or("Group wasn't found!")?;
+        ev.chat_id = msg.chat_id;
+        if group.is_channel() {
+            handler.delivery().send_event_to_subscribers(
+                real_chat_id,
+                Some(self_user_id.as_i32_unchecked()),
+                ev,
+            );
+            return Ok(());
+        }
+
+        let receivers: Vec<_> = group
             .participants()
             .iter()
-            .filter(|&u| u.user_id() != self_user_id.as_i32_unchecked())
-            .map(|user| UserId::UserId(user.user_id()))
-            .collect()
-    };
-
-    let ev = IsTypingEvent {
-        event: "is_typing".into(),
-        is_typing: true,
-        chat_id: event_chat_id,
-        user_id: self_user_id.as_i32_unchecked(),
-    };
-
-    handler.send_is_typing(ev, users).await;
-
-    drafts_db
-        .update_draft(&self_user_id, real_chat_id, msg.draft.as_str())
-        .await?;
+            .filter(|u| u.user_id() != self_user_id.as_i32_unchecked())
+            .map(|u| (u.user_id(), ev.clone()))
+            .collect();
+        handler.send_events_to_connections(receivers);
+    }
 
     Ok(())
 }
 
-/// Edits self user profile
-async fn handle_edit_self(handler: &mut JsonHandler, msg: &EditSelfRequest) -> PPResult<()> {
+async fn handle_edit_scheduled(
+    handler: &JsonHandler,
+    msg: &EditScheduledRequest,
+) -> PPResult<ScheduledMessage> {
     let self_user_id = {
         let session = handler.session.read().await;
-        let (user_id, _) = session.get_credentials_unchecked();
-        user_id
+        session.get_credentials_unchecked().0.to_owned()
     };
 
-    let users_db: UsersDB = handler.get_db();
-
-    if let Some(name) = msg.name.as_ref() {
-        users_db.update_name(&self_user_id, name).await?;
-    }
-
-    if let Some(&profile_color) = msg.profile_color.as_ref() {
-        users_db
-            .update_profile_color(&self_user_id, profile_color)
-            .await?;
-    }
-
-    if let Some(username) = msg.username.as_ref() {
-        users_db.update_username(&self_user_id, username).await?;
+    let scheduled_db: ScheduledMessagesDB = handler.get_db();
+    let mut scheduled = scheduled_db
+        .fetch_one(&self_user_id, msg.scheduled_id)
+        .await?
+        .ok_or("Scheduled message with the given scheduled_id wasn't found!")?;
+
+    if let Some(schedule_at) = msg.schedule_at {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        if schedule_at <= now {
+            return Err("schedule_at must be in the future!".into());
+        }
+        scheduled.schedule_at = schedule_at;
     }
-
-    if let Some(hash) = msg.photo.as_ref() {
+    if let Some(hashes) = msg.sha256_hashes.as_ref() {
         let hashes_db: HashesDB = handler.get_db();
-        if !hashes_db.hash_exists(hash).await? {
-            return Err(format!("Provided SHA256 Hash: {} doesn't exist!", hash).into());
+        for hash in hashes.iter() {
+            if !hashes_db.hash_exists(hash).await? {
+                return Err(format!("Provided SHA256 Hash: {} doesn't exist!", hash).into());
+            }
         }
-        users_db.update_photo(&self_user_id, hash).await?;
+        scheduled.sha256_hashes = Some(hashes.clone());
     }
-
-    if let Some(password) = msg.password.as_ref() {
-        users_db.update_password(&self_user_id, password).await?;
+    if let Some(content) = msg.content.as_ref() {
+        scheduled.content = Some(content.clone());
     }
 
-    let chats = users_db.fetch_chats(&self_user_id).await?;
-    let self_profile = users_db.fetch_user(&self_user_id).await?.unwrap();
-
-    for pub_chat_id in chats.keys() {
-        let is_group = pub_chat_id.is_negative();
-
-        if is_group {
-            // if chat is group, we do nothing because of the performance cost of traversing every
-            // participant
-            continue;
-        } else {
-            handler.send_event_to_con_detached(
-                *pub_chat_id,
-                EditSelfEvent {
-                    event: "edit_self".into(),
-                    new_profile: User::construct(
-                        self_profile.name().to_string(),
-                        self_profile.user_id(),
-                        self_profile.profile_color(),
-                        self_profile.username().to_string(),
-                        self_profile.photo_cloned(),
-                    ),
-                },
-            );
-        }
+    if !scheduled_db.update(&self_user_id, &scheduled).await? {
+        return Err("Scheduled message was already sent!".into());
     }
 
-    Ok(())
-}
-
-async fn handle_edit(handler: &mut JsonHandler, content: &str) -> PPResult<serde_json::Value> {
-    let what_field = extract_what_field(content)?;
-
-    match what_field.as_str() {
-        "message" => {
-            let msg: EditMessageRequest = serde_json::from_str(content)?;
-            handle_edit_message(handler, msg).await?;
-            Ok(serde_json::to_value(EditMessageResponse {
-                ok: true,
-                method: "edit_message".into(),
-            })
-            .unwrap())
-        }
-        "self" => {
-            let msg: EditSelfRequest = serde_json::from_str(content)?;
-            handle_edit_self(handler, &msg).await?;
-            Ok(serde_json::to_value(EditMessageResponse {
-                ok: true,
-                method: "edit_self".into(),
-            })
-            .unwrap())
-        }
-        "draft" => {
-            let msg: EditDraftRequest = serde_json::from_str(content)?;
-            handle_edit_draft(handler, &msg).await?;
-            Ok(serde_json::to_value(EditDraftResponse {
-                ok: true,
-                method: "edit_draft".into(),
-            })
-            .unwrap())
-        }
-        "is_unread" => {
-            let msg: MarkAsReadRequest = serde_json::from_str(content)?;
-            handle_mark_as_read(handler, &msg).await?;
-            Ok(serde_json::to_value(MarkAsReadResponse {
-                ok: true,
-                method: "edit_is_unread".into(),
-                chat_id: msg.chat_id,
-            })
-            .unwrap())
-        }
-        _ => Err("Unknown what field! Known what fields for edit: 'message', 'self', 'draft', 'is_unread'".into()),
-    }
+    Ok(scheduled)
 }
 
-async fn on_delete_msgs(
+/// Pins or unpins the message. In groups only admins are allowed to
+async fn handle_pin_message(
     handler: &mut JsonHandler,
-    msg: &DeleteMessagesRequest,
-) -> PPResult<DeleteMessagesResponse> {
+    msg: &PinMessageRequest,
+    is_pinned: bool,
+) -> PPResult<()> {
     let self_user_id = {
         let session = handler.session.read().await;
         let (user_id, _) = session.get_credentials_unchecked();
@@ -349,81 +409,74 @@ async fn on_delete_msgs(
         .await?
         .ok_or("Chat with the given chat_id doesn't exist!")?;
 
-    for &msg_id in msg.message_ids.iter() {
-        if !messages_db.message_exists(real_chat_id, msg_id).await? {
-            return Err("Message with the given message_id wasn't found!".into());
-        }
+    if msg.chat_id.is_negative() {
+        chats_db
+            .check_permission(real_chat_id, &self_user_id, Permission::Pin)
+            .await?;
     }
 
-    let is_group = real_chat_id.is_negative();
-    if is_group {
-        for &msg_id in msg.message_ids.iter() {
-            let message_info = messages_db.fetch_messages(real_chat_id, msg_id..0).await?;
-            if message_info[0].from_id != self_user_id.as_i32_unchecked() {
-                return Err("You aren't authorized to delete not yours message!".into());
-            }
+    if is_pinned {
+        if !messages_db
+            .message_exists(real_chat_id, msg.message_id)
+            .await?
+        {
+            return Err("Message with the given message_id wasn't found!".into());
+        }
+        if !chats_db.pin_message(real_chat_id, msg.message_id).await? {
+            return Err("Message is already pinned!".into());
         }
+    } else {
+        chats_db.unpin_message(real_chat_id, msg.message_id).await?;
     }
 
-    messages_db
-        .delete_messages(real_chat_id, &msg.message_ids)
-        .await?;
-    if !is_group {
-        handler.send_event_to_con_detached(
-            msg.chat_id,
-            DeleteMessagesEvent {
-                event: "delete_message".into(),
-                chat_id: self_user_id.as_i32_unchecked(),
-                message_ids: msg.message_ids.clone(),
-            },
-        );
+    let ev = PinMessageEvent {
+        event: "pin_message".into(),
+        chat_id: self_user_id.as_i32_unchecked(),
+        message_id: msg.message_id,
+        is_pinned,
+    };
+
+    if msg.chat_id.is_positive() {
+        handler.send_event_to_con_detached(msg.chat_id, ev);
     } else {
-        let (chat, _) = chats_db
+        let mut ev = ev;
+        let (group, _) = chats_db
             .fetch_chat(&self_user_id, real_chat_id)
             .await?
-            .unwrap();
-
-        #[cfg(debug_assertions)]
-        assert!(chat.is_group());
+            .ok_or("Group wasn't found!")?;
+        ev.chat_id = msg.chat_id;
+        if group.is_channel() {
+            handler.delivery().send_event_to_subscribers(
+                real_chat_id,
+                Some(self_user_id.as_i32_unchecked()),
+                ev,
+            );
+            return Ok(());
+        }
 
-        // filter self
-        for participant in chat
+        let receivers: Vec<_> = group
             .participants()
             .iter()
-            .filter(|el| el.user_id() != self_user_id.as_i32_unchecked())
-        {
-            // send real chat id for everyone
-            handler.send_event_to_con_detached(
-                participant.user_id(),
-                DeleteMessagesEvent{
-                    event: "delete_message".into(),
-                    chat_id: msg.chat_id,
-                    message_ids: msg.message_ids.clone(),
-                },
-            );
-        }
+            .filter(|u| u.user_id() != self_user_id.as_i32_unchecked())
+            .map(|u| (u.user_id(), ev.clone()))
+            .collect();
+        handler.send_events_to_connections(receivers);
     }
 
-    Ok(DeleteMessagesResponse {
-        ok: true,
-        method: "delete_message".into(),
-        chat_id: msg.chat_id,
-        message_ids: msg.message_ids.clone(),
-    })
+    Ok(())
 }
 
-async fn on_delete_all_messages(
-    handler: &mut JsonHandler,
-    msg: &DeleteAllMessagesRequest,
-) -> PPResult<DeleteAllMessagesResponse> {
+/// Sets the default expiry timer of the chat. In groups it requires the `edit_info` permission
+async fn handle_edit_auto_delete(
+    handler: &JsonHandler,
+    msg: &EditAutoDeleteRequest,
+) -> PPResult<()> {
     let self_user_id = {
         let session = handler.session.read().await;
-        let (user_id, _) = session.get_credentials_unchecked();
-        user_id
+        session.get_credentials_unchecked().0.to_owned()
     };
 
     let users_db: UsersDB = handler.get_db();
-    let messages_db: MessagesDB = handler.get_db();
     let chats_db: ChatsDB = handler.get_db();
 
     let real_chat_id = users_db
@@ -431,43 +484,859 @@ async fn on_delete_all_messages(
         .await?
         .ok_or("Chat with the given chat_id doesn't exist!")?;
 
-    messages_db.delete_all_messages(real_chat_id).await?;
+    if msg.chat_id.is_negative() {
+        chats_db
+            .check_permission(real_chat_id, &self_user_id, Permission::EditInfo)
+            .await?;
+    }
+    chats_db.set_auto_delete(real_chat_id, msg.ttl_seconds).await?;
 
-    let event = DeleteAllMessagesEvent {
-        event: "delete_all_messages".into(),
+    let ev = AutoDeleteEvent {
+        event: "auto_delete".into(),
         chat_id: self_user_id.as_i32_unchecked(),
+        ttl_seconds: msg.ttl_seconds,
     };
 
     if msg.chat_id.is_positive() {
-        handler.send_event_to_con_detached(msg.chat_id, event);
+        handler.send_event_to_con_detached(msg.chat_id, ev);
     } else {
-        let mut event = event;
+        let mut ev = ev;
         let (group, _) = chats_db
             .fetch_chat(&self_user_id, real_chat_id)
             .await?
-            .ok_or("Group wasn't found. WTF?")?;
-        event.chat_id = msg.chat_id;
+            .ok_or("Group wasn't found!")?;
+        ev.chat_id = msg.chat_id;
+        if group.is_channel() {
+            handler.delivery().send_event_to_subscribers(
+                real_chat_id,
+                Some(self_user_id.as_i32_unchecked()),
+                ev,
+            );
+            return Ok(());
+        }
 
         let receivers: Vec<_> = group
             .participants()
             .iter()
             .filter(|u| u.user_id() != self_user_id.as_i32_unchecked())
-            .map(|u| (u.user_id(), event.clone()))
+            .map(|u| (u.user_id(), ev.clone()))
             .collect();
         handler.send_events_to_connections(receivers);
     }
 
-    Ok(DeleteAllMessagesResponse {
-        ok: true,
-        method: "delete_all_messages".into(),
-        chat_id: msg.chat_id,
-    })
+    Ok(())
 }
 
-async fn on_delete_chat(
-    handler: &mut JsonHandler,
-    msg: &DeleteChatRequest,
-) -> PPResult<DeleteChatResponse> {
+async fn handle_edit_draft(handler: &mut JsonHandler, msg: &EditDraftRequest) -> PPResult<()> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        let (user_id, _) = session.get_credentials_unchecked();
+        user_id
+    };
+
+    let drafts_db: DraftsDB = handler.get_db();
+    let users_db: UsersDB = handler.get_db();
+    let chats_db: ChatsDB = handler.get_db();
+
+    let real_chat_id = users_db
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Chat with the given chat_id doesn't exist!")?;
+
+    let event_chat_id = if msg.chat_id.is_positive() {
+        self_user_id.as_i32_unchecked()
+    } else {
+        real_chat_id
+    };
+
+    let users = if msg.chat_id.is_positive() {
+        vec![UserId::UserId(msg.chat_id)]
+    } else {
+        let (group, _) = chats_db
+            .fetch_chat(&self_user_id, real_chat_id)
+            .await?
+            .expect("chat to exist");
+        group
+            .participants()
+            .iter()
+            .filter(|&u| u.user_id() != self_user_id.as_i32_unchecked())
+            .map(|user| UserId::UserId(user.user_id()))
+            .collect()
+    };
+
+    let ev = IsTypingEvent {
+        event: "is_typing".into(),
+        is_typing: true,
+        chat_id: event_chat_id,
+        user_id: self_user_id.as_i32_unchecked(),
+    };
+
+    handler.send_is_typing(ev, users).await;
+
+    drafts_db
+        .update_draft(&self_user_id, real_chat_id, msg.draft.as_str())
+        .await?;
+
+    Ok(())
+}
+
+/// Edits self user profile
+async fn handle_edit_self(
+    handler: &mut JsonHandler,
+    msg: &EditSelfRequest,
+) -> PPResult<EditSelfResponse> {
+    let (self_user_id, self_session_id) = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked()
+    };
+
+    let users_db: UsersDB = handler.get_db();
+
+    // Every field is checked before anything is written
+    // Credentials can be changed only knowing the current password
+    if msg.password.is_some() || msg.username.is_some() {
+        let current_password = msg
+            .current_password
+            .as_ref()
+            .ok_or("'current_password' is required to change password or username!")?;
+        users_db
+            .verify_password(&self_user_id, current_password)
+            .await?;
+    }
+
+    if let Some(username) = msg.username.as_ref() {
+        users_db.check_username(&self_user_id, username).await?;
+    }
+
+    if let Some(name) = msg.name.as_ref() {
+        users_db.check_name(name)?;
+    }
+
+    if let Some(hash) = msg.photo.as_ref() {
+        let hashes_db: HashesDB = handler.get_db();
+        if !hashes_db.hash_exists(hash).await? {
+            return Err(format!("Provided SHA256 Hash: {} doesn't exist!", hash).into());
+        }
+    }
+
+    let enable_2fa = msg.enable_2fa == Some(true);
+    let totp_changes = [
+        enable_2fa,
+        msg.confirm_2fa.is_some(),
+        msg.disable_2fa.is_some(),
+    ];
+    if totp_changes.into_iter().filter(|&given| given).count() > 1 {
+        return Err(
+            "Only one of 'enable_2fa', 'confirm_2fa' and 'disable_2fa' may be given!".into(),
+        );
+    }
+    if enable_2fa {
+        users_db.check_totp_enrollment(&self_user_id).await?;
+    }
+    if let Some(code) = msg.confirm_2fa.as_ref() {
+        users_db.verify_totp_enrollment(&self_user_id, code).await?;
+    }
+    // Spends the code, so it goes after the other checks
+    if let Some(code) = msg.disable_2fa.as_ref() {
+        users_db.disable_totp(&self_user_id, code).await?;
+    }
+
+    if let Some(username) = msg.username.as_ref() {
+        users_db.update_username(&self_user_id, username).await?;
+    }
+
+    if let Some(name) = msg.name.as_ref() {
+        users_db.update_name(&self_user_id, name).await?;
+    }
+
+    if let Some(&profile_color) = msg.profile_color.as_ref() {
+        users_db
+            .update_profile_color(&self_user_id, profile_color)
+            .await?;
+    }
+
+    if let Some(hash) = msg.photo.as_ref() {
+        users_db.update_photo(&self_user_id, hash).await?;
+    }
+
+    if let Some(password) = msg.password.as_ref() {
+        users_db.update_password(&self_user_id, password).await?;
+
+        // Invalidate every other session, including the connected ones
+        users_db
+            .retain_session(&self_user_id, &self_session_id)
+            .await?;
+        let user_id = self_user_id.as_i32_unchecked();
+        handler
+            .deauthenticate_live_sessions(user_id, Some(&self_session_id))
+            .await;
+        // Events go to the connection, which is still authenticated
+        handler
+            .sessions
+            .insert(user_id, Arc::clone(&handler.session));
+    }
+
+    let mut response = EditSelfResponse {
+        ok: true,
+        method: "edit_self".into(),
+        totp_secret: None,
+        totp_uri: None,
+        recovery_codes: None,
+    };
+
+    if enable_2fa {
+        let (secret, uri) = users_db.begin_totp_enrollment(&self_user_id).await?;
+        response.totp_secret = Some(secret);
+        response.totp_uri = Some(uri);
+    }
+
+    if let Some(code) = msg.confirm_2fa.as_ref() {
+        let recovery_codes = users_db
+            .confirm_totp_enrollment(&self_user_id, code)
+            .await?;
+        response.recovery_codes = Some(recovery_codes);
+    }
+
+    let chats = users_db.fetch_chats(&self_user_id).await?;
+    let self_profile = users_db.fetch_user(&self_user_id).await?.unwrap();
+
+    for pub_chat_id in chats.keys() {
+        let is_group = pub_chat_id.is_negative();
+
+        if is_group {
+            // if chat is group, we do nothing because of the performance cost of traversing every
+            // participant
+            continue;
+        } else {
+            handler.send_event_to_con_detached(
+                *pub_chat_id,
+                EditSelfEvent {
+                    event: "edit_self".into(),
+                    new_profile: User::construct(
+                        self_profile.name().to_string(),
+                        self_profile.user_id(),
+                        self_profile.profile_color(),
+                        self_profile.username().to_string(),
+                        self_profile.photo_cloned(),
+                    ),
+                },
+            );
+        }
+    }
+
+    Ok(response)
+}
+
+/// Promotes or demotes a participant of the group, only the owner is allowed to
+///
+/// Returns the new role and permissions of the participant
+async fn handle_edit_role(
+    handler: &JsonHandler,
+    msg: &EditRoleRequest,
+) -> PPResult<(Role, Permissions)> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let users_db: UsersDB = handler.get_db();
+    let chats_db: ChatsDB = handler.get_db();
+
+    let real_chat_id = users_db
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Chat with the given chat_id doesn't exist!")?;
+    if !real_chat_id.is_negative() {
+        return Err("Roles exist only in groups!".into());
+    }
+
+    if !matches!(
+        chats_db.fetch_role(real_chat_id, &self_user_id).await?,
+        Some((Role::Owner, _))
+    ) {
+        return Err("Only the owner can change roles!".into());
+    }
+    if msg.user_id == self_user_id.as_i32_unchecked() {
+        return Err("Role of the owner can't be changed!".into());
+    }
+
+    let target_user_id: UserId = msg.user_id.into();
+    if chats_db
+        .fetch_role(real_chat_id, &target_user_id)
+        .await?
+        .is_none()
+    {
+        return Err("User isn't a participant of the group!".into());
+    }
+
+    match msg.role {
+        Role::Admin => {
+            chats_db
+                .set_admin(
+                    real_chat_id,
+                    &target_user_id,
+                    msg.permissions.unwrap_or(Permissions::ALL),
+                )
+                .await?
+        }
+        Role::Member => chats_db.remove_admin(real_chat_id, &target_user_id).await?,
+        Role::Owner => return Err("Ownership can't be transferred!".into()),
+    }
+
+    let (role, permissions) = chats_db
+        .fetch_role(real_chat_id, &target_user_id)
+        .await?
+        .ok_or("User isn't a participant of the group!")?;
+
+    let mut receivers = chats_db
+        .fetch_participants(real_chat_id)
+        .await?
+        .unwrap_or_default();
+    // Demoted admins of channels are only subscribers
+    if !receivers.contains(&msg.user_id) {
+        receivers.push(msg.user_id);
+    }
+    let receivers: Vec<_> = receivers
+        .into_iter()
+        .filter(|&user_id| user_id != self_user_id.as_i32_unchecked())
+        .map(|user_id| {
+            (
+                user_id,
+                RoleEvent {
+                    event: "role".into(),
+                    chat_id: msg.chat_id,
+                    user_id: msg.user_id,
+                    role,
+                    permissions,
+                },
+            )
+        })
+        .collect();
+    handler.send_events_to_connections(receivers);
+
+    Ok((role, permissions))
+}
+
+/// Changes permissions of the group members, requires the `edit_info` permission
+async fn handle_edit_permissions(
+    handler: &JsonHandler,
+    msg: &EditPermissionsRequest,
+) -> PPResult<()> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let users_db: UsersDB = handler.get_db();
+    let chats_db: ChatsDB = handler.get_db();
+
+    let real_chat_id = users_db
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Chat with the given chat_id doesn't exist!")?;
+    if !real_chat_id.is_negative() {
+        return Err("Permissions exist only in groups!".into());
+    }
+
+    chats_db
+        .check_permission(real_chat_id, &self_user_id, Permission::EditInfo)
+        .await?;
+    chats_db
+        .set_member_permissions(real_chat_id, msg.permissions)
+        .await?;
+
+    let receivers: Vec<_> = chats_db
+        .fetch_participants(real_chat_id)
+        .await?
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|&user_id| user_id != self_user_id.as_i32_unchecked())
+        .map(|user_id| {
+            (
+                user_id,
+                PermissionsEvent {
+                    event: "permissions".into(),
+                    chat_id: msg.chat_id,
+                    permissions: msg.permissions,
+                },
+            )
+        })
+        .collect();
+    handler.send_events_to_connections(receivers);
+
+    Ok(())
+}
+
+/// Changes name, avatar or tag of the group, requires the `edit_info` permission
+async fn handle_edit_group(handler: &JsonHandler, msg: &EditGroupRequest) -> PPResult<ChatDetails> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let users_db: UsersDB = handler.get_db();
+    let chats_db: ChatsDB = handler.get_db();
+
+    let real_chat_id = users_db
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Chat with the given chat_id doesn't exist!")?;
+    if !real_chat_id.is_negative() {
+        return Err("The id of provided chat must be a group!".into());
+    }
+    chats_db
+        .check_permission(real_chat_id, &self_user_id, Permission::EditInfo)
+        .await?;
+
+    if let Some(hash) = msg.avatar_hash.as_ref().filter(|hash| !hash.is_empty()) {
+        let hashes_db: HashesDB = handler.get_db();
+        if !hashes_db.hash_exists(hash).await? {
+            return Err(format!("Provided SHA256 Hash: {} doesn't exist!", hash).into());
+        }
+    }
+
+    if let Some(name) = msg.name.as_ref() {
+        chats_db.update_name(real_chat_id, name).await?;
+    }
+    // The tag may be removed only after the group became private
+    // and must be set before it becomes public
+    if msg.is_public == Some(false) {
+        chats_db.set_public(real_chat_id, false).await?;
+    }
+    if let Some(tag) = msg.tag.as_ref() {
+        chats_db.update_tag(real_chat_id, tag).await?;
+    }
+    if msg.is_public == Some(true) {
+        chats_db.set_public(real_chat_id, true).await?;
+    }
+    if let Some(hash) = msg.avatar_hash.as_ref() {
+        chats_db.update_avatar(real_chat_id, hash).await?;
+    }
+
+    let (group, details) = chats_db
+        .fetch_chat(&self_user_id, real_chat_id)
+        .await?
+        .ok_or("Group wasn't found!")?;
+
+    if group.is_channel() {
+        handler.delivery().send_event_to_subscribers(
+            real_chat_id,
+            Some(self_user_id.as_i32_unchecked()),
+            GroupUpdatedEvent {
+                event: "group_updated".into(),
+                chat: details.clone(),
+            },
+        );
+        return Ok(details);
+    }
+
+    let receivers: Vec<_> = group
+        .participants()
+        .iter()
+        .filter(|u| u.user_id() != self_user_id.as_i32_unchecked())
+        .map(|u| {
+            (
+                u.user_id(),
+                GroupUpdatedEvent {
+                    event: "group_updated".into(),
+                    chat: details.clone(),
+                },
+            )
+        })
+        .collect();
+    handler.send_events_to_connections(receivers);
+
+    Ok(details)
+}
+
+async fn handle_edit(handler: &mut JsonHandler, content: &str) -> PPResult<serde_json::Value> {
+    let what_field = extract_what_field(content)?;
+
+    match what_field.as_str() {
+        "message" => {
+            let msg: EditMessageRequest = serde_json::from_str(content)?;
+            handle_edit_message(handler, msg).await?;
+            Ok(serde_json::to_value(EditMessageResponse {
+                ok: true,
+                method: "edit_message".into(),
+            })
+            .unwrap())
+        }
+        "self" => {
+            let msg: EditSelfRequest = serde_json::from_str(content)?;
+            let response = handle_edit_self(handler, &msg).await?;
+            Ok(serde_json::to_value(response).unwrap())
+        }
+        "draft" => {
+            let msg: EditDraftRequest = serde_json::from_str(content)?;
+            handle_edit_draft(handler, &msg).await?;
+            Ok(serde_json::to_value(EditDraftResponse {
+                ok: true,
+                method: "edit_draft".into(),
+            })
+            .unwrap())
+        }
+        "is_unread" => {
+            let msg: MarkAsReadRequest = serde_json::from_str(content)?;
+            handle_mark_as_read(handler, &msg).await?;
+            Ok(serde_json::to_value(MarkAsReadResponse {
+                ok: true,
+                method: "edit_is_unread".into(),
+                chat_id: msg.chat_id,
+            })
+            .unwrap())
+        }
+        "reaction" => {
+            let msg: EditReactionRequest = serde_json::from_str(content)?;
+            handle_edit_reaction(handler, &msg).await?;
+            Ok(serde_json::to_value(EditReactionResponse {
+                ok: true,
+                method: "edit_reaction".into(),
+                chat_id: msg.chat_id,
+                message_id: msg.message_id,
+            })
+            .unwrap())
+        }
+        "pin" | "unpin" => {
+            let msg: PinMessageRequest = serde_json::from_str(content)?;
+            handle_pin_message(handler, &msg, what_field == "pin").await?;
+            Ok(serde_json::to_value(PinMessageResponse {
+                ok: true,
+                method: format!("edit_{}", what_field),
+                chat_id: msg.chat_id,
+                message_id: msg.message_id,
+            })
+            .unwrap())
+        }
+        "scheduled" => {
+            let msg: EditScheduledRequest = serde_json::from_str(content)?;
+            let scheduled_message = handle_edit_scheduled(handler, &msg).await?;
+            Ok(serde_json::to_value(EditScheduledResponse {
+                ok: true,
+                method: "edit_scheduled".into(),
+                scheduled_message,
+            })
+            .unwrap())
+        }
+        "auto_delete" => {
+            let msg: EditAutoDeleteRequest = serde_json::from_str(content)?;
+            handle_edit_auto_delete(handler, &msg).await?;
+            Ok(serde_json::to_value(EditAutoDeleteResponse {
+                ok: true,
+                method: "edit_auto_delete".into(),
+                chat_id: msg.chat_id,
+                ttl_seconds: msg.ttl_seconds,
+            })
+            .unwrap())
+        }
+        "role" => {
+            let msg: EditRoleRequest = serde_json::from_str(content)?;
+            let (role, permissions) = handle_edit_role(handler, &msg).await?;
+            Ok(serde_json::to_value(EditRoleResponse {
+                ok: true,
+                method: "edit_role".into(),
+                chat_id: msg.chat_id,
+                user_id: msg.user_id,
+                role,
+                permissions,
+            })
+            .unwrap())
+        }
+        "permissions" => {
+            let msg: EditPermissionsRequest = serde_json::from_str(content)?;
+            handle_edit_permissions(handler, &msg).await?;
+            Ok(serde_json::to_value(EditPermissionsResponse {
+                ok: true,
+                method: "edit_permissions".into(),
+                chat_id: msg.chat_id,
+                permissions: msg.permissions,
+            })
+            .unwrap())
+        }
+        "group" => {
+            let msg: EditGroupRequest = serde_json::from_str(content)?;
+            let chat = handle_edit_group(handler, &msg).await?;
+            Ok(serde_json::to_value(EditGroupResponse {
+                ok: true,
+                method: "edit_group".into(),
+                chat,
+            })
+            .unwrap())
+        }
+        _ => Err("Unknown what field! Known what fields for edit: 'message', 'self', 'draft', 'is_unread', 'reaction', 'pin', 'unpin', 'scheduled', 'auto_delete', 'role', 'permissions', 'group'".into()),
+    }
+}
+
+/// Messages may be deleted for everyone only within this time after sending
+///
+/// Participants of a group with the `delete_messages` permission may delete
+/// any message at any time
+const DELETE_FOR_EVERYONE_WINDOW: i64 = 48 * 60 * 60;
+
+/// Sends `delete_message` event to everyone who sees the messages: other participants,
+/// subscribers of a channel and other connections of the user
+async fn notify_deleted_messages(
+    handler: &JsonHandler,
+    self_user_id: &UserId,
+    real_chat_id: ChatId,
+    chat_id: ChatId,
+    message_ids: &[i32],
+) -> PPResult<()> {
+    let ev = DeleteMessagesEvent {
+        event: "delete_message".into(),
+        chat_id,
+        message_ids: message_ids.to_vec(),
+    };
+    handler
+        .delivery()
+        .send_event_to_own_sessions(self_user_id.as_i32_unchecked(), ev.clone());
+
+    let is_group = real_chat_id.is_negative();
+    let chats_db: ChatsDB = handler.get_db();
+    if is_group && chats_db.is_channel(real_chat_id).await? {
+        handler.delivery().send_event_to_subscribers(
+            real_chat_id,
+            Some(self_user_id.as_i32_unchecked()),
+            ev,
+        );
+        return Ok(());
+    }
+
+    // Private chats are seen by the peer under the id of the user
+    let ev = DeleteMessagesEvent {
+        chat_id: if is_group {
+            chat_id
+        } else {
+            self_user_id.as_i32_unchecked()
+        },
+        ..ev
+    };
+    let receivers: Vec<_> = chats_db
+        .fetch_participants(real_chat_id)
+        .await?
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|&user_id| user_id != self_user_id.as_i32_unchecked())
+        .map(|user_id| (user_id, ev.clone()))
+        .collect();
+    handler.send_events_to_connections(receivers);
+
+    Ok(())
+}
+
+/// Whether the user may delete messages of others for everyone
+async fn can_delete_others(
+    handler: &JsonHandler,
+    self_user_id: &UserId,
+    real_chat_id: ChatId,
+) -> PPResult<bool> {
+    // Both sides of a private chat may delete only their own messages
+    if !real_chat_id.is_negative() {
+        return Ok(false);
+    }
+
+    Ok(handler
+        .get_db::<ChatsDB>()
+        .fetch_role(real_chat_id, self_user_id)
+        .await?
+        .is_some_and(|(_, permissions)| permissions.allows(Permission::DeleteMessages)))
+}
+
+async fn on_delete_msgs(
+    handler: &mut JsonHandler,
+    msg: &DeleteMessagesRequest,
+) -> PPResult<DeleteMessagesResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        let (user_id, _) = session.get_credentials_unchecked();
+        user_id
+    };
+
+    let users_db: UsersDB = handler.get_db();
+    let messages_db: MessagesDB = handler.get_db();
+
+    let real_chat_id = users_db
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Chat with the given chat_id doesn't exist!")?;
+
+    let mut messages = vec![];
+    for &msg_id in msg.message_ids.iter() {
+        let message = messages_db
+            .fetch_messages(real_chat_id, msg_id..0)
+            .await?
+            .pop()
+            .ok_or("Message with the given message_id wasn't found!")?;
+        messages.push(message);
+    }
+
+    let for_everyone = msg.for_everyone.unwrap_or(false);
+    if !for_everyone {
+        handler
+            .get_db::<HiddenMessagesDB>()
+            .hide_messages(real_chat_id, &self_user_id, &msg.message_ids)
+            .await?;
+        handler.delivery().send_event_to_own_sessions(
+            self_user_id.as_i32_unchecked(),
+            DeleteMessagesEvent {
+                event: "delete_message".into(),
+                chat_id: msg.chat_id,
+                message_ids: msg.message_ids.clone(),
+            },
+        );
+
+        return Ok(DeleteMessagesResponse {
+            ok: true,
+            method: "delete_message".into(),
+            chat_id: msg.chat_id,
+            message_ids: msg.message_ids.clone(),
+            for_everyone,
+        });
+    }
+
+    if !can_delete_others(handler, &self_user_id, real_chat_id).await? {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        for message in messages.iter() {
+            if message.from_id != self_user_id.as_i32_unchecked() {
+                return Err(
+                    "You aren't authorized to delete not yours message for everyone!".into(),
+                );
+            }
+            if now - message.date > DELETE_FOR_EVERYONE_WINDOW {
+                return Err("Message is too old to be deleted for everyone!".into());
+            }
+        }
+    }
+
+    messages_db
+        .delete_messages(real_chat_id, &msg.message_ids)
+        .await?;
+    notify_deleted_messages(
+        handler,
+        &self_user_id,
+        real_chat_id,
+        msg.chat_id,
+        &msg.message_ids,
+    )
+    .await?;
+
+    Ok(DeleteMessagesResponse {
+        ok: true,
+        method: "delete_message".into(),
+        chat_id: msg.chat_id,
+        message_ids: msg.message_ids.clone(),
+        for_everyone,
+    })
+}
+
+async fn on_delete_all_messages(
+    handler: &mut JsonHandler,
+    msg: &DeleteAllMessagesRequest,
+) -> PPResult<DeleteAllMessagesResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        let (user_id, _) = session.get_credentials_unchecked();
+        user_id
+    };
+
+    let users_db: UsersDB = handler.get_db();
+    let messages_db: MessagesDB = handler.get_db();
+    let chats_db: ChatsDB = handler.get_db();
+
+    let real_chat_id = users_db
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Chat with the given chat_id doesn't exist!")?;
+
+    let for_everyone = msg.for_everyone.unwrap_or(false);
+    let response = DeleteAllMessagesResponse {
+        ok: true,
+        method: "delete_all_messages".into(),
+        chat_id: msg.chat_id,
+        for_everyone,
+    };
+    let cleared_ev = DeleteAllMessagesEvent {
+        event: "delete_all_messages".into(),
+        chat_id: msg.chat_id,
+    };
+
+    if !for_everyone || !can_delete_others(handler, &self_user_id, real_chat_id).await? {
+        // Own recent messages are deleted for everyone, like `delete messages` does
+        if for_everyone {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64;
+            let own_ids: Vec<i32> = messages_db
+                .fetch_all_messages(real_chat_id)
+                .await?
+                .into_iter()
+                .filter(|message| {
+                    message.from_id == self_user_id.as_i32_unchecked()
+                        && now - message.date <= DELETE_FOR_EVERYONE_WINDOW
+                })
+                .map(|message| message.message_id)
+                .collect();
+            if !own_ids.is_empty() {
+                messages_db.delete_messages(real_chat_id, &own_ids).await?;
+                notify_deleted_messages(
+                    handler,
+                    &self_user_id,
+                    real_chat_id,
+                    msg.chat_id,
+                    &own_ids,
+                )
+                .await?;
+            }
+        }
+
+        // The rest is hidden only for the user
+        if let Some(latest) = messages_db.get_latest(real_chat_id).await? {
+            handler
+                .get_db::<HiddenMessagesDB>()
+                .clear_history(real_chat_id, &self_user_id, latest)
+                .await?;
+        }
+        handler
+            .delivery()
+            .send_event_to_own_sessions(self_user_id.as_i32_unchecked(), cleared_ev);
+
+        return Ok(response);
+    }
+
+    messages_db.delete_all_messages(real_chat_id).await?;
+
+    handler
+        .delivery()
+        .send_event_to_own_sessions(self_user_id.as_i32_unchecked(), cleared_ev.clone());
+    if chats_db.is_channel(real_chat_id).await? {
+        handler.delivery().send_event_to_subscribers(
+            real_chat_id,
+            Some(self_user_id.as_i32_unchecked()),
+            cleared_ev,
+        );
+    } else {
+        let receivers: Vec<_> = chats_db
+            .fetch_participants(real_chat_id)
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|&user_id| user_id != self_user_id.as_i32_unchecked())
+            .map(|user_id| (user_id, cleared_ev.clone()))
+            .collect();
+        handler.send_events_to_connections(receivers);
+    }
+
+    Ok(response)
+}
+
+async fn on_delete_chat(
+    handler: &mut JsonHandler,
+    msg: &DeleteChatRequest,
+) -> PPResult<DeleteChatResponse> {
     let self_user_id = {
         let session = handler.session.read().await;
         let (user_id, _) = session.get_credentials_unchecked();
@@ -476,24 +1345,273 @@ async fn on_delete_chat(
 
     let users_db: UsersDB = handler.get_db();
     let chats_db: ChatsDB = handler.get_db();
+    let read_cursors_db: ReadCursorsDB = handler.get_db();
 
     let real_chat_id = users_db
         .get_associated_chat_id(&self_user_id, msg.chat_id)
         .await?
         .ok_or("Chat with the given chat_id doesn't exist!")?;
 
+    if real_chat_id.is_negative()
+        && !matches!(
+            chats_db.fetch_role(real_chat_id, &self_user_id).await?,
+            Some((Role::Owner, _))
+        )
+    {
+        return Err("Only the owner can delete the group!".into());
+    }
+
+    let participants = chats_db
+        .fetch_participants(real_chat_id)
+        .await?
+        .unwrap_or_default();
+    let is_channel = real_chat_id.is_negative() && chats_db.is_channel(real_chat_id).await?;
     chats_db.delete_chat(real_chat_id).await?;
+    read_cursors_db.delete_cursors(real_chat_id).await?;
+
+    if real_chat_id.is_negative() {
+        handler
+            .get_db::<InvitationsDB>()
+            .delete_invitations(real_chat_id)
+            .await?;
+        handler
+            .get_db::<JoinRequestsDB>()
+            .delete_requests(real_chat_id)
+            .await?;
+        // Groups are associated under their own id by every participant
+        for participant in participants {
+            users_db
+                .remove_associated_chat(&participant.into(), real_chat_id)
+                .await?;
+        }
+        if is_channel {
+            let subscribers_db: SubscribersDB = handler.get_db();
+            let mut subscribers = pin!(subscribers_db.stream_subscribers(real_chat_id).await?);
+            while let Some(subscriber) = subscribers.try_next().await? {
+                users_db
+                    .remove_associated_chat(&subscriber.into(), real_chat_id)
+                    .await?;
+            }
+            subscribers_db.delete_subscribers(real_chat_id).await?;
+            handler
+                .get_db::<ViewsDB>()
+                .delete_all_views(real_chat_id)
+                .await?;
+        }
+    } else {
+        users_db
+            .remove_associated_chat(&self_user_id, msg.chat_id)
+            .await?;
+        users_db
+            .remove_associated_chat(&msg.chat_id.into(), self_user_id.as_i32_unchecked())
+            .await?;
+    }
+
+    Ok(DeleteChatResponse {
+        ok: true,
+        method: "delete_chat".into(),
+        chat_id: msg.chat_id,
+    })
+}
+
+/// Deletes the account of the user
+///
+/// Private chats are deleted for both sides, groups are left.
+/// Groups owned by the user are handed over to the oldest admin or participant.
+/// Messages sent in groups are deleted only if `delete_messages` is true
+async fn on_delete_self(
+    handler: &mut JsonHandler,
+    msg: &DeleteSelfRequest,
+) -> PPResult<DeleteSelfResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        let (user_id, _) = session.get_credentials_unchecked();
+        user_id
+    };
+
+    let users_db: UsersDB = handler.get_db();
+    let chats_db: ChatsDB = handler.get_db();
+    let messages_db: MessagesDB = handler.get_db();
+    let drafts_db: DraftsDB = handler.get_db();
+    let read_cursors_db: ReadCursorsDB = handler.get_db();
+
     users_db
-        .remove_associated_chat(&self_user_id, msg.chat_id)
+        .verify_password(&self_user_id, &msg.password)
         .await?;
-    users_db
-        .remove_associated_chat(&msg.chat_id.into(), self_user_id.as_i32_unchecked())
+
+    let chats = users_db.fetch_chats(&self_user_id).await?;
+    let mut receivers = vec![];
+    let mut role_receivers = vec![];
+
+    for (pub_chat_id, real_chat_id) in chats {
+        let is_group = pub_chat_id.is_negative();
+
+        if is_group {
+            // Subscribers of channels learn only about the staff
+            let is_staff = chats_db
+                .fetch_participants(real_chat_id)
+                .await?
+                .unwrap_or_default()
+                .contains(&self_user_id.as_i32_unchecked());
+            let new_owner = chats_db
+                .hand_over_ownership(real_chat_id, &self_user_id)
+                .await?;
+            chats_db
+                .remove_participant(real_chat_id, &self_user_id)
+                .await?;
+            read_cursors_db
+                .delete_cursor(real_chat_id, &self_user_id)
+                .await?;
+            if msg.delete_messages.unwrap_or(false) {
+                messages_db
+                    .delete_messages_from(real_chat_id, &self_user_id)
+                    .await?;
+            }
+
+            if let Some((group, _)) = chats_db.fetch_chat(&self_user_id, real_chat_id).await? {
+                let ev = AccountDeletedEvent {
+                    event: "account_deleted".into(),
+                    chat_id: pub_chat_id,
+                    user_id: self_user_id.as_i32_unchecked(),
+                };
+                if group.is_channel() && is_staff {
+                    handler
+                        .delivery()
+                        .send_event_to_subscribers(real_chat_id, None, ev);
+                } else {
+                    receivers.extend(
+                        group
+                            .participants()
+                            .iter()
+                            .map(|u| (u.user_id(), ev.clone())),
+                    );
+                }
+                if let Some(new_owner) = new_owner {
+                    role_receivers.extend(group.participants().iter().map(|u| {
+                        (
+                            u.user_id(),
+                            RoleEvent {
+                                event: "role".into(),
+                                chat_id: pub_chat_id,
+                                user_id: new_owner,
+                                role: Role::Owner,
+                                permissions: Permissions::ALL,
+                            },
+                        )
+                    }));
+                }
+            }
+        } else {
+            messages_db.delete_all_messages(real_chat_id).await?;
+            chats_db.delete_chat(real_chat_id).await?;
+            read_cursors_db.delete_cursors(real_chat_id).await?;
+            users_db
+                .remove_associated_chat(&pub_chat_id.into(), self_user_id.as_i32_unchecked())
+                .await?;
+
+            receivers.push((
+                pub_chat_id,
+                AccountDeletedEvent {
+                    event: "account_deleted".into(),
+                    chat_id: self_user_id.as_i32_unchecked(),
+                    user_id: self_user_id.as_i32_unchecked(),
+                },
+            ));
+        }
+    }
+
+    drafts_db.delete_drafts(&self_user_id).await?;
+    handler
+        .get_db::<ScheduledMessagesDB>()
+        .cancel_all(&self_user_id)
         .await?;
+    if let Some(export_hash) = users_db.fetch_export_hash(&self_user_id).await? {
+        delete_archive(&export_hash, &handler.get_db::<HashesDB>()).await?;
+    }
+    users_db.delete_user(&self_user_id).await?;
 
-    Ok(DeleteChatResponse {
+    handler.send_events_to_connections(receivers);
+    handler.send_events_to_connections(role_receivers);
+
+    let user_id = self_user_id.as_i32_unchecked();
+    handler.deauthenticate_live_sessions(user_id, None).await;
+    {
+        let mut session = handler.session.write().await;
+        session.deauthenticate();
+    }
+    handler.sessions.remove(&user_id);
+    handler.live_sessions.remove(&user_id);
+
+    Ok(DeleteSelfResponse {
         ok: true,
-        method: "delete_chat".into(),
+        method: "delete_self".into(),
+    })
+}
+
+async fn on_delete_scheduled(
+    handler: &JsonHandler,
+    msg: &DeleteScheduledRequest,
+) -> PPResult<DeleteScheduledResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    if !handler
+        .get_db::<ScheduledMessagesDB>()
+        .cancel(&self_user_id, msg.scheduled_id)
+        .await?
+    {
+        return Err("Scheduled message with the given scheduled_id wasn't found!".into());
+    }
+
+    Ok(DeleteScheduledResponse {
+        ok: true,
+        method: "delete_scheduled".into(),
+        scheduled_id: msg.scheduled_id,
+    })
+}
+
+/// Revokes the invitation link, only admins may revoke links created by others
+async fn on_delete_invitation_link(
+    handler: &JsonHandler,
+    msg: &DeleteInvitationLinkRequest,
+) -> PPResult<DeleteInvitationLinkResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let real_chat_id = handler
+        .get_db::<UsersDB>()
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Chat with the given chat_id doesn't exist!")?;
+    let chats_db: ChatsDB = handler.get_db();
+    chats_db
+        .check_permission(real_chat_id, &self_user_id, Permission::Invite)
+        .await?;
+
+    let invitations_db: InvitationsDB = handler.get_db();
+    let invitation = invitations_db
+        .fetch_invitation(&msg.link)
+        .await?
+        .filter(|invitation| invitation.chat_id == real_chat_id)
+        .ok_or("Invitation link wasn't found!")?;
+    if invitation.creator_id != self_user_id.as_i32_unchecked()
+        && !chats_db.is_admin(real_chat_id, &self_user_id).await?
+    {
+        return Err("Only admins can revoke invitation links of others!".into());
+    }
+    invitations_db
+        .revoke_invitation(real_chat_id, &msg.link)
+        .await?;
+
+    Ok(DeleteInvitationLinkResponse {
+        ok: true,
+        method: "delete_invitation_link".into(),
         chat_id: msg.chat_id,
+        link: msg.link.clone(),
     })
 }
 
@@ -513,6 +1631,18 @@ async fn handle_delete(handler: &mut JsonHandler, content: &str) -> PPResult<ser
             on_delete_msgs(handler, &serde_json::from_str(content)?).await?,
         )
         .unwrap()),
+        "self" => Ok(serde_json::to_value(
+            on_delete_self(handler, &serde_json::from_str(content)?).await?,
+        )
+        .unwrap()),
+        "scheduled" => Ok(serde_json::to_value(
+            on_delete_scheduled(handler, &serde_json::from_str(content)?).await?,
+        )
+        .unwrap()),
+        "invitation_link" => Ok(serde_json::to_value(
+            on_delete_invitation_link(handler, &serde_json::from_str(content)?).await?,
+        )
+        .unwrap()),
         _ => Err("Unknown what field provided!".into()),
     }
 }
@@ -0,0 +1,74 @@
+use crate::{
+    db::{
+        chat::{chats::ChatsDB, hashes::HashesDB, messages::MessagesDB},
+        internal::error::PPResult,
+        user::UsersDB,
+    },
+    fs::export::{build_archive, delete_archive, ExportData, ExportedChat},
+    server::message::{
+        handlers::json_handler::JsonHandler, methods::macros,
+        types::response::export::ExportResponse,
+    },
+};
+
+/// Collects the profile, the chats and all messages of the user into a downloadable archive
+async fn handle_export(handler: &JsonHandler) -> PPResult<ExportResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let users_db: UsersDB = handler.get_db();
+    let chats_db: ChatsDB = handler.get_db();
+    let messages_db: MessagesDB = handler.get_db();
+    let hashes_db: HashesDB = handler.get_db();
+
+    let profile = users_db
+        .fetch_user(&self_user_id)
+        .await?
+        .ok_or("Failed to fetch self!")?;
+
+    let mut chats = vec![];
+    for (chat_id, associated_chat_id) in users_db.fetch_chats(&self_user_id).await? {
+        if let Some((chat, mut details)) = chats_db
+            .fetch_chat(&self_user_id, associated_chat_id)
+            .await?
+        {
+            let mut messages = messages_db.fetch_all_messages(associated_chat_id).await?;
+            if !chat.is_group() {
+                // Fake the chat id with the user id
+                details.chat_id = chat_id;
+                messages.iter_mut().for_each(|msg| msg.chat_id = chat_id);
+            }
+            chats.push(ExportedChat { details, messages });
+        }
+    }
+
+    let sha256_hash = build_archive(&ExportData { profile, chats }, &hashes_db).await?;
+    // Only the latest export stays downloadable
+    if let Some(previous) = users_db.fetch_export_hash(&self_user_id).await? {
+        if previous != sha256_hash {
+            delete_archive(&previous, &hashes_db).await?;
+        }
+    }
+    users_db
+        .update_export_hash(&self_user_id, &sha256_hash)
+        .await?;
+
+    Ok(ExportResponse {
+        ok: true,
+        method: "export".into(),
+        sha256_hash,
+    })
+}
+
+pub async fn handle(handler: &mut JsonHandler, method: &str) {
+    macros::require_auth!(handler, method);
+
+    match handle_export(handler).await {
+        Ok(response) => handler.send_message(&response).await,
+        Err(err) => {
+            handler.send_error(method, err).await;
+        }
+    };
+}
@@ -1,25 +1,47 @@
+use std::collections::HashMap;
+
 use serde_json::Value;
 
 use crate::db::chat::chats::ChatsDB;
 use crate::db::chat::drafts::DraftsDB;
 use crate::db::chat::hashes::HashesDB;
+use crate::db::chat::hidden::HiddenMessagesDB;
+use crate::db::chat::invitations::InvitationsDB;
+use crate::db::chat::join_requests::JoinRequestsDB;
 use crate::db::chat::messages::MessagesDB;
+use crate::db::chat::reactions::ReactionsDB;
+use crate::db::chat::read_cursors::ReadCursorsDB;
+use crate::db::chat::revisions::RevisionsDB;
+use crate::db::chat::scheduled::ScheduledMessagesDB;
+use crate::db::chat::subscribers::SubscribersDB;
+use crate::db::chat::threads::ThreadsDB;
+use crate::db::chat::views::ViewsDB;
 use crate::db::internal::error::{PPError, PPResult};
 use crate::db::user::UsersDB;
 use crate::fs::media::MediaType;
+use crate::fs::search::{self, MAX_SEARCH_LIMIT};
 use crate::server::message::methods::macros;
-use crate::server::message::types::chat::ChatDetailsResponse;
+use crate::server::message::types::chat::{ChatDetailsResponse, ChatId, Permission};
 use crate::server::message::types::message::Message;
+use crate::server::message::types::request::send::MessageId;
 use crate::server::message::types::request::{extract_what_field, fetch::*};
 use crate::server::message::types::response::fetch::{
-    FetchChatInfoResponse, FetchChatsResponse, FetchMessagesResponse, FetchSelfResponse,
-    FetchUserResponse, FetchUsersResponse,
+    FetchChatInfoResponse, FetchChatsResponse, FetchGroupsResponse, FetchInvitationLinksResponse,
+    FetchJoinRequestsResponse, FetchMessageHistoryResponse, FetchMessagesResponse,
+    FetchPinnedResponse, FetchReadReceiptsResponse, FetchRolesResponse, FetchScheduledResponse,
+    FetchSearchResponse, FetchSelfResponse, FetchThreadResponse, FetchUserResponse,
+    FetchUsersResponse, FetchViewsResponse, MessagesCursor,
 };
 use crate::server::message::{
     handlers::json_handler::JsonHandler,
     types::user::{User, UserId},
 };
 
+const DEFAULT_PAGE_LIMIT: u32 = 50;
+const MAX_PAGE_LIMIT: u32 = 100;
+/// Most messages, which views may be fetched at once
+const MAX_VIEWS_FETCHED: usize = 100;
+
 async fn handle_fetch_chats(handler: &JsonHandler) -> PPResult<Vec<ChatDetailsResponse>> {
     let self_user_id = {
         let session = handler.session.read().await;
@@ -48,13 +70,14 @@ async fn handle_fetch_chats(handler: &JsonHandler) -> PPResult<Vec<ChatDetailsRe
             chats_details.push(ChatDetailsResponse {
                 details,
                 unread_count: messages_db
-                    .fetch_unread_count(associated_chat_id)
-                    .await?
-                    .ok_or(PPError::from("Failed to fetch chat!"))?,
+                    .fetch_unread_count(associated_chat_id, &self_user_id)
+                    .await?,
                 draft: drafts_db
                     .fetch_draft(&self_user_id, associated_chat_id)
                     .await?
                     .unwrap_or("".into()),
+                pinned_message: chats_db.fetch_pinned(associated_chat_id).await?.pop(),
+                auto_delete: chats_db.fetch_auto_delete(associated_chat_id).await?,
             });
         }
     }
@@ -84,6 +107,22 @@ async fn on_users(handler: &mut JsonHandler) -> PPResult<FetchUsersResponse> {
     })
 }
 
+async fn on_groups(handler: &mut JsonHandler) -> PPResult<FetchGroupsResponse> {
+    let content = handler.utf8_content_unchecked();
+    let msg = serde_json::from_str::<FetchGroupsRequest>(content)?;
+
+    let chats_db: ChatsDB = handler.get_db();
+    let groups = chats_db
+        .fetch_public_groups_by_search_query(&msg.query)
+        .await?;
+
+    Ok(FetchGroupsResponse {
+        ok: true,
+        method: "fetch_groups".into(),
+        groups,
+    })
+}
+
 async fn fetch_user(identifier: &UserId, db: UsersDB) -> PPResult<User> {
     db.fetch_user(identifier).await?.ok_or("User wasn't found!".into())
 }
@@ -94,37 +133,184 @@ async fn handle_fetch_self(handler: &JsonHandler) -> PPResult<User> {
     fetch_user(&user_id, handler.get_db()).await
 }
 
+/// Fetches a page of messages next to the anchor, latest first
+async fn fetch_messages_page(
+    messages_db: &MessagesDB,
+    chat_id: ChatId,
+    msg: &FetchMessagesRequest,
+) -> PPResult<(Vec<Message>, MessagesCursor, bool)> {
+    let limit = msg.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let direction = msg.direction.unwrap_or_default();
+    // Messages sent exactly at the anchor date count as sent before it
+    let date_anchor = match (msg.message_id, msg.date) {
+        (None, Some(date)) => Some(messages_db.find_by_date(chat_id, date).await?.unwrap_or(-1)),
+        _ => None,
+    };
+
+    // Fetching one more than needed tells whether there is more
+    let (messages, has_older, has_newer) = match direction {
+        PageDirection::Before => {
+            let before = msg
+                .message_id
+                .or(date_anchor.map(|id| id + 1))
+                .unwrap_or(MessageId::MAX);
+            let mut messages = messages_db.fetch_before(chat_id, before, limit + 1).await?;
+            let has_older = messages.len() > limit as usize;
+            messages.truncate(limit as usize);
+            (messages, Some(has_older), None)
+        }
+        PageDirection::After => {
+            let after = msg.message_id.or(date_anchor).unwrap_or(-1);
+            let mut messages = messages_db.fetch_after(chat_id, after, limit + 1).await?;
+            let has_newer = messages.len() > limit as usize;
+            messages.truncate(limit as usize);
+            messages.reverse();
+            (messages, None, Some(has_newer))
+        }
+        PageDirection::Around => {
+            let center = match msg.message_id.or(date_anchor) {
+                Some(id) => id,
+                None => messages_db.get_latest(chat_id).await?.unwrap_or(-1),
+            };
+            let newer_limit = limit / 2;
+            let older_limit = limit - newer_limit;
+
+            // Includes the anchor itself
+            let mut older = messages_db
+                .fetch_before(chat_id, center.saturating_add(1), older_limit + 1)
+                .await?;
+            let has_older = older.len() > older_limit as usize;
+            older.truncate(older_limit as usize);
+
+            let mut messages = messages_db
+                .fetch_after(chat_id, center, newer_limit + 1)
+                .await?;
+            let has_newer = messages.len() > newer_limit as usize;
+            messages.truncate(newer_limit as usize);
+            messages.reverse();
+            messages.extend(older);
+            (messages, Some(has_older), Some(has_newer))
+        }
+    };
+
+    let (Some(newest_id), Some(oldest_id)) = (
+        messages.first().map(|m| m.message_id),
+        messages.last().map(|m| m.message_id),
+    ) else {
+        let cursor = MessagesCursor {
+            before: None,
+            after: None,
+        };
+        return Ok((messages, cursor, false));
+    };
+
+    // The side opposite to the direction wasn't fetched, check its bounds
+    let has_older = match has_older {
+        Some(has_older) => has_older,
+        None => messages_db
+            .get_oldest(chat_id)
+            .await?
+            .is_some_and(|id| id < oldest_id),
+    };
+    let has_newer = match has_newer {
+        Some(has_newer) => has_newer,
+        None => messages_db
+            .get_latest(chat_id)
+            .await?
+            .is_some_and(|id| id > newest_id),
+    };
+
+    let has_more = match direction {
+        PageDirection::Before => has_older,
+        PageDirection::After => has_newer,
+        PageDirection::Around => has_older || has_newer,
+    };
+    let cursor = MessagesCursor {
+        before: has_older.then_some(oldest_id),
+        after: has_newer.then_some(newest_id),
+    };
+
+    Ok((messages, cursor, has_more))
+}
+
 async fn handle_fetch_messages(
     handler: &JsonHandler,
     msg: FetchMessagesRequest,
-) -> PPResult<Vec<Message>> {
+) -> PPResult<FetchMessagesResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
     // Groups have negative id
     let maybe_chat_id = if msg.chat_id.is_positive() {
-        let session = handler.session.read().await;
-        let (user_id, _) = session.get_credentials_unchecked();
         handler
             .get_db::<UsersDB>()
-            .get_associated_chat_id(&user_id, msg.chat_id)
+            .get_associated_chat_id(&self_user_id, msg.chat_id)
             .await
     } else if handler.get_db::<ChatsDB>().chat_exists(msg.chat_id).await? {
-        Ok(Some(msg.chat_id))
+        // Only participants and subscribers read the group
+        match handler
+            .get_db::<ChatsDB>()
+            .fetch_role(msg.chat_id, &self_user_id)
+            .await?
+        {
+            Some(_) => Ok(Some(msg.chat_id)),
+            None => Err("You aren't a participant of the chat!".into()),
+        }
     } else {
         Err("No group found by the given chat id!".into())
     }?;
 
-    match maybe_chat_id {
-        Some(target_chat_id) => {
-            let mut msgs = handler
-                .get_db::<MessagesDB>()
-                .fetch_messages(target_chat_id, msg.range[0]..msg.range[1])
-                .await?;
+    let Some(target_chat_id) = maybe_chat_id else {
+        return Err("failed to retrieve chat_id!".into());
+    };
 
-            msgs.iter_mut()
-                .for_each(|message| message.chat_id = msg.chat_id);
-            Ok(msgs)
+    let messages_db: MessagesDB = handler.get_db();
+    let (mut msgs, cursor, has_more) = match msg.range {
+        Some(range) => (
+            messages_db
+                .fetch_messages(target_chat_id, range[0]..range[1])
+                .await?,
+            None,
+            None,
+        ),
+        None => {
+            let (msgs, cursor, has_more) =
+                fetch_messages_page(&messages_db, target_chat_id, &msg).await?;
+            (msgs, Some(cursor), Some(has_more))
         }
-        None => Err("failed to retrieve chat_id!".into()),
-    }
+    };
+    handler
+        .get_db::<HiddenMessagesDB>()
+        .retain_visible(target_chat_id, &self_user_id, &mut msgs)
+        .await?;
+    handler
+        .get_db::<ReactionsDB>()
+        .attach_reactions(target_chat_id, &self_user_id, &mut msgs)
+        .await?;
+    handler
+        .get_db::<ThreadsDB>()
+        .attach_threads(target_chat_id, &self_user_id, &mut msgs)
+        .await?;
+    handler
+        .get_db::<ReadCursorsDB>()
+        .attach_unread(target_chat_id, &self_user_id, &mut msgs)
+        .await?;
+    messages_db
+        .attach_reply_previews(target_chat_id, &mut msgs)
+        .await?;
+
+    msgs.iter_mut()
+        .for_each(|message| message.chat_id = msg.chat_id);
+
+    Ok(FetchMessagesResponse {
+        ok: true,
+        method: "fetch_messages".into(),
+        messages: msgs,
+        cursor,
+        has_more,
+    })
 }
 
 async fn on_chats(handler: &JsonHandler) -> PPResult<FetchChatsResponse> {
@@ -249,12 +435,455 @@ async fn on_chat_info(handler: &mut JsonHandler) -> PPResult<FetchChatInfoRespon
 async fn on_messages(handler: &mut JsonHandler) -> PPResult<FetchMessagesResponse> {
     let content = handler.utf8_content_unchecked();
     let msg = serde_json::from_str::<FetchMessagesRequest>(content)?;
-    let fetched_msgs = handle_fetch_messages(handler, msg).await?;
+    handle_fetch_messages(handler, msg).await
+}
 
-    Ok(FetchMessagesResponse {
+async fn on_search(handler: &mut JsonHandler) -> PPResult<FetchSearchResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let content = handler.utf8_content_unchecked();
+    let msg: FetchSearchRequest = serde_json::from_str(content)?;
+    if msg.query.trim().is_empty() {
+        return Err("Search query cannot be empty!".into());
+    }
+
+    let users_db: UsersDB = handler.get_db();
+    let messages_db: MessagesDB = handler.get_db();
+
+    // real chat id -> public chat id
+    let chats: HashMap<i32, i32> = users_db
+        .fetch_chats(&self_user_id)
+        .await?
+        .into_iter()
+        .map(|(public_id, real_id)| (real_id, public_id))
+        .collect();
+
+    let chat_ids = match msg.chat_id {
+        Some(chat_id) => vec![chats
+            .iter()
+            .find(|(_, &public_id)| public_id == chat_id)
+            .map(|(&real_id, _)| real_id)
+            .ok_or("Provided chat_id wasn't found!")?],
+        None => chats.keys().copied().collect(),
+    };
+
+    let found = search::message_index()?
+        .search(
+            chat_ids,
+            msg.query,
+            msg.offset.unwrap_or(0),
+            msg.limit.unwrap_or(MAX_SEARCH_LIMIT),
+        )
+        .await?;
+
+    let hidden_db: HiddenMessagesDB = handler.get_db();
+    let read_cursors_db: ReadCursorsDB = handler.get_db();
+    let mut hidden = HashMap::new();
+    let mut messages = Vec::with_capacity(found.len());
+    for (real_chat_id, message_id) in found {
+        if !hidden.contains_key(&real_chat_id) {
+            let chat_hidden = hidden_db.fetch_hidden(real_chat_id, &self_user_id).await?;
+            hidden.insert(real_chat_id, chat_hidden);
+        }
+        if hidden[&real_chat_id].is_hidden(message_id) {
+            continue;
+        }
+
+        let mut message = messages_db
+            .fetch_messages(real_chat_id, message_id..0)
+            .await?;
+        read_cursors_db
+            .attach_unread(real_chat_id, &self_user_id, &mut message)
+            .await?;
+        messages_db
+            .attach_reply_previews(real_chat_id, &mut message)
+            .await?;
+        if let Some(mut message) = message.pop() {
+            message.chat_id = chats[&real_chat_id];
+            messages.push(message);
+        }
+    }
+
+    Ok(FetchSearchResponse {
         ok: true,
-        method: "fetch_messages".into(),
-        messages: fetched_msgs,
+        method: "fetch_search".into(),
+        messages,
+    })
+}
+
+async fn on_pinned(handler: &mut JsonHandler) -> PPResult<FetchPinnedResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let content = handler.utf8_content_unchecked();
+    let msg: FetchPinnedRequest = serde_json::from_str(content)?;
+
+    let users_db: UsersDB = handler.get_db();
+    let chats_db: ChatsDB = handler.get_db();
+    let messages_db: MessagesDB = handler.get_db();
+
+    let real_chat_id = users_db
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Provided chat_id wasn't found!")?;
+
+    let mut messages = vec![];
+    for message_id in chats_db.fetch_pinned(real_chat_id).await?.into_iter().rev() {
+        messages.extend(messages_db.fetch_messages(real_chat_id, message_id..0).await?);
+    }
+    handler
+        .get_db::<HiddenMessagesDB>()
+        .retain_visible(real_chat_id, &self_user_id, &mut messages)
+        .await?;
+    handler
+        .get_db::<ReactionsDB>()
+        .attach_reactions(real_chat_id, &self_user_id, &mut messages)
+        .await?;
+    handler
+        .get_db::<ThreadsDB>()
+        .attach_threads(real_chat_id, &self_user_id, &mut messages)
+        .await?;
+    handler
+        .get_db::<ReadCursorsDB>()
+        .attach_unread(real_chat_id, &self_user_id, &mut messages)
+        .await?;
+    messages_db
+        .attach_reply_previews(real_chat_id, &mut messages)
+        .await?;
+    messages
+        .iter_mut()
+        .for_each(|message| message.chat_id = msg.chat_id);
+
+    Ok(FetchPinnedResponse {
+        ok: true,
+        method: "fetch_pinned".into(),
+        messages,
+    })
+}
+
+async fn on_message_history(handler: &mut JsonHandler) -> PPResult<FetchMessageHistoryResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let content = handler.utf8_content_unchecked();
+    let msg: FetchMessageHistoryRequest = serde_json::from_str(content)?;
+
+    let real_chat_id = handler
+        .get_db::<UsersDB>()
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Provided chat_id wasn't found!")?;
+    if !handler
+        .get_db::<MessagesDB>()
+        .message_exists(real_chat_id, msg.message_id)
+        .await?
+    {
+        return Err("Message with the given message_id doesn't exist!".into());
+    }
+
+    let revisions = handler
+        .get_db::<RevisionsDB>()
+        .fetch_revisions(real_chat_id, msg.message_id)
+        .await?;
+
+    Ok(FetchMessageHistoryResponse {
+        ok: true,
+        method: "fetch_message_history".into(),
+        chat_id: msg.chat_id,
+        message_id: msg.message_id,
+        revisions,
+    })
+}
+
+async fn on_roles(handler: &mut JsonHandler) -> PPResult<FetchRolesResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let content = handler.utf8_content_unchecked();
+    let msg: FetchRolesRequest = serde_json::from_str(content)?;
+
+    let real_chat_id = handler
+        .get_db::<UsersDB>()
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Provided chat_id wasn't found!")?;
+    if !real_chat_id.is_negative() {
+        return Err("Roles exist only in groups!".into());
+    }
+
+    let chats_db: ChatsDB = handler.get_db();
+    let (owner_id, admins, member_permissions) = chats_db.fetch_roles(real_chat_id).await?;
+
+    Ok(FetchRolesResponse {
+        ok: true,
+        method: "fetch_roles".into(),
+        chat_id: msg.chat_id,
+        owner_id,
+        admins,
+        member_permissions,
+        banned: chats_db.fetch_banned(real_chat_id).await?,
+    })
+}
+
+/// Real id of the group, in which the user may invite others
+async fn fetch_inviting_group_id(
+    handler: &JsonHandler,
+    self_user_id: &UserId,
+    chat_id: ChatId,
+) -> PPResult<ChatId> {
+    let real_chat_id = handler
+        .get_db::<UsersDB>()
+        .get_associated_chat_id(self_user_id, chat_id)
+        .await?
+        .ok_or("Provided chat_id wasn't found!")?;
+    if !real_chat_id.is_negative() {
+        return Err("The id of provided chat must be a group!".into());
+    }
+    handler
+        .get_db::<ChatsDB>()
+        .check_permission(real_chat_id, self_user_id, Permission::Invite)
+        .await?;
+
+    Ok(real_chat_id)
+}
+
+async fn on_invitation_links(handler: &mut JsonHandler) -> PPResult<FetchInvitationLinksResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let content = handler.utf8_content_unchecked();
+    let msg: FetchGroupInvitesRequest = serde_json::from_str(content)?;
+    let real_chat_id = fetch_inviting_group_id(handler, &self_user_id, msg.chat_id).await?;
+
+    Ok(FetchInvitationLinksResponse {
+        ok: true,
+        method: "fetch_invitation_links".into(),
+        chat_id: msg.chat_id,
+        invitations: handler
+            .get_db::<InvitationsDB>()
+            .fetch_invitations(real_chat_id)
+            .await?,
+    })
+}
+
+async fn on_join_requests(handler: &mut JsonHandler) -> PPResult<FetchJoinRequestsResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let content = handler.utf8_content_unchecked();
+    let msg: FetchGroupInvitesRequest = serde_json::from_str(content)?;
+    let real_chat_id = fetch_inviting_group_id(handler, &self_user_id, msg.chat_id).await?;
+
+    Ok(FetchJoinRequestsResponse {
+        ok: true,
+        method: "fetch_join_requests".into(),
+        chat_id: msg.chat_id,
+        requests: handler
+            .get_db::<JoinRequestsDB>()
+            .fetch_requests(real_chat_id)
+            .await?,
+    })
+}
+
+async fn on_scheduled(handler: &mut JsonHandler) -> PPResult<FetchScheduledResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let content = handler.utf8_content_unchecked();
+    let msg: FetchScheduledRequest = serde_json::from_str(content)?;
+
+    let messages = handler
+        .get_db::<ScheduledMessagesDB>()
+        .fetch_scheduled(&self_user_id, msg.chat_id)
+        .await?;
+
+    Ok(FetchScheduledResponse {
+        ok: true,
+        method: "fetch_scheduled".into(),
+        messages,
+    })
+}
+
+async fn on_read_receipts(handler: &mut JsonHandler) -> PPResult<FetchReadReceiptsResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let content = handler.utf8_content_unchecked();
+    let msg: FetchReadReceiptsRequest = serde_json::from_str(content)?;
+
+    let users_db: UsersDB = handler.get_db();
+    let messages_db: MessagesDB = handler.get_db();
+    let read_cursors_db: ReadCursorsDB = handler.get_db();
+
+    let real_chat_id = users_db
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Provided chat_id wasn't found!")?;
+    let message = messages_db
+        .fetch_messages(real_chat_id, msg.message_id..0)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or("Message with the given message_id doesn't exist!")?;
+
+    let read_by = read_cursors_db
+        .fetch_cursors(real_chat_id)
+        .await?
+        .into_iter()
+        .filter(|&(user_id, last_read_id)| {
+            user_id != message.from_id && last_read_id >= msg.message_id
+        })
+        .map(|(user_id, _)| user_id)
+        .collect();
+
+    Ok(FetchReadReceiptsResponse {
+        ok: true,
+        method: "fetch_read_receipts".into(),
+        chat_id: msg.chat_id,
+        message_id: msg.message_id,
+        read_by,
+    })
+}
+
+/// Fetches the message with a page of its replies, oldest first
+async fn on_thread(handler: &mut JsonHandler) -> PPResult<FetchThreadResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let content = handler.utf8_content_unchecked();
+    let msg: FetchThreadRequest = serde_json::from_str(content)?;
+
+    let messages_db: MessagesDB = handler.get_db();
+    let threads_db: ThreadsDB = handler.get_db();
+    let hidden_db: HiddenMessagesDB = handler.get_db();
+    let reactions_db: ReactionsDB = handler.get_db();
+    let read_cursors_db: ReadCursorsDB = handler.get_db();
+
+    let real_chat_id = handler
+        .get_db::<UsersDB>()
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Provided chat_id wasn't found!")?;
+
+    let mut root = messages_db
+        .fetch_messages(real_chat_id, msg.message_id..0)
+        .await?;
+    hidden_db
+        .retain_visible(real_chat_id, &self_user_id, &mut root)
+        .await?;
+    if root.is_empty() {
+        return Err("Message with the given message_id wasn't found!".into());
+    }
+
+    // Fetching one more than needed tells whether there is more
+    let limit = msg.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let mut reply_ids = threads_db
+        .fetch_reply_ids(
+            real_chat_id,
+            msg.message_id,
+            msg.after.unwrap_or(-1),
+            limit + 1,
+        )
+        .await?;
+    let has_more = reply_ids.len() > limit as usize;
+    reply_ids.truncate(limit as usize);
+
+    let mut replies = messages_db
+        .fetch_messages_by_ids(real_chat_id, &reply_ids)
+        .await?;
+    hidden_db
+        .retain_visible(real_chat_id, &self_user_id, &mut replies)
+        .await?;
+
+    for messages in [&mut root, &mut replies] {
+        reactions_db
+            .attach_reactions(real_chat_id, &self_user_id, messages)
+            .await?;
+        threads_db
+            .attach_threads(real_chat_id, &self_user_id, messages)
+            .await?;
+        read_cursors_db
+            .attach_unread(real_chat_id, &self_user_id, messages)
+            .await?;
+        messages_db
+            .attach_reply_previews(real_chat_id, messages)
+            .await?;
+        messages
+            .iter_mut()
+            .for_each(|message| message.chat_id = msg.chat_id);
+    }
+
+    Ok(FetchThreadResponse {
+        ok: true,
+        method: "fetch_thread".into(),
+        chat_id: msg.chat_id,
+        message: root.remove(0),
+        replies,
+        has_more,
+    })
+}
+
+async fn on_views(handler: &mut JsonHandler) -> PPResult<FetchViewsResponse> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let content = handler.utf8_content_unchecked();
+    let msg: FetchViewsRequest = serde_json::from_str(content)?;
+    if msg.message_ids.len() > MAX_VIEWS_FETCHED {
+        return Err(format!(
+            "Views of at most {} messages can be fetched at once!",
+            MAX_VIEWS_FETCHED
+        )
+        .into());
+    }
+
+    let real_chat_id = handler
+        .get_db::<UsersDB>()
+        .get_associated_chat_id(&self_user_id, msg.chat_id)
+        .await?
+        .ok_or("Provided chat_id wasn't found!")?;
+    if !real_chat_id.is_negative()
+        || !handler
+            .get_db::<ChatsDB>()
+            .is_channel(real_chat_id)
+            .await?
+    {
+        return Err("The id of provided chat must be a channel!".into());
+    }
+
+    Ok(FetchViewsResponse {
+        ok: true,
+        method: "fetch_views".into(),
+        chat_id: msg.chat_id,
+        views: handler
+            .get_db::<ViewsDB>()
+            .fetch_views(real_chat_id, &msg.message_ids)
+            .await?,
+        subscribers: handler
+            .get_db::<SubscribersDB>()
+            .fetch_count(real_chat_id)
+            .await?,
     })
 }
 
@@ -278,9 +907,42 @@ async fn handle_json_message(handler: &mut JsonHandler) -> PPResult<Value> {
         "users" => on_users(handler)
             .await
             .map(|v| serde_json::to_value(v).unwrap()),
+        "groups" => on_groups(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
         "chat_info" => on_chat_info(handler)
             .await
             .map(|v| serde_json::to_value(v).unwrap()),
+        "search" => on_search(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
+        "pinned" => on_pinned(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
+        "read_receipts" => on_read_receipts(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
+        "scheduled" => on_scheduled(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
+        "message_history" => on_message_history(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
+        "roles" => on_roles(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
+        "invitation_links" => on_invitation_links(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
+        "join_requests" => on_join_requests(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
+        "thread" => on_thread(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
+        "views" => on_views(handler)
+            .await
+            .map(|v| serde_json::to_value(v).unwrap()),
         _ => Err(PPError::from("Unknown 'what' field provided!")),
     }
 }
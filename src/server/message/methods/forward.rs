@@ -0,0 +1,138 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    db::{
+        chat::{hidden::HiddenMessagesDB, messages::MessagesDB, read_cursors::ReadCursorsDB},
+        internal::error::PPResult,
+        user::UsersDB,
+    },
+    server::message::{
+        handlers::json_handler::JsonHandler,
+        methods::macros,
+        types::{
+            message::ForwardedFrom, request::send::ForwardMessagesRequest,
+            response::send::ForwardMessagesResponse,
+        },
+    },
+};
+
+use super::send::{check_send_permissions, get_or_create_chat, notify_new_message};
+
+const MAX_FORWARDED_MESSAGES: usize = 100;
+
+async fn handle_forward(handler: &JsonHandler, msg: &ForwardMessagesRequest) -> PPResult<Vec<i32>> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    if msg.message_ids.is_empty() || msg.message_ids.len() > MAX_FORWARDED_MESSAGES {
+        return Err(format!(
+            "From 1 to {} messages can be forwarded at once!",
+            MAX_FORWARDED_MESSAGES
+        )
+        .into());
+    }
+
+    let users_db: UsersDB = handler.get_db();
+    let messages_db: MessagesDB = handler.get_db();
+
+    let source_chat_id = users_db
+        .get_associated_chat_id(&self_user_id, msg.from_chat_id)
+        .await?
+        .ok_or("Chat with the given from_chat_id doesn't exist!")?;
+
+    // Messages hidden by the user are missing for them
+    let hidden = handler
+        .get_db::<HiddenMessagesDB>()
+        .fetch_hidden(source_chat_id, &self_user_id)
+        .await?;
+
+    // Fetch everything first, so nothing is forwarded if some message is missing
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let mut originals = Vec::with_capacity(msg.message_ids.len());
+    for &message_id in msg.message_ids.iter() {
+        if hidden.is_hidden(message_id) {
+            return Err(format!("Message {} wasn't found!", message_id).into());
+        }
+        // Expired messages, which weren't swept yet, are already gone for the users
+        let original = messages_db
+            .fetch_messages(source_chat_id, message_id..0)
+            .await?
+            .into_iter()
+            .next()
+            .filter(|original| {
+                original
+                    .expires_at
+                    .is_none_or(|expires_at| now < expires_at)
+            })
+            .ok_or(format!("Message {} wasn't found!", message_id))?;
+        originals.push(original);
+    }
+
+    let delivery = handler.delivery();
+    let target_chat = get_or_create_chat(&delivery, &self_user_id, msg.to).await?;
+    let has_media = originals
+        .iter()
+        .any(|original| original.sha256_hashes.as_ref().is_some_and(|h| !h.is_empty()));
+    check_send_permissions(&delivery, &self_user_id, target_chat.chat_id(), has_media).await?;
+    let read_cursors_db: ReadCursorsDB = handler.get_db();
+
+    let mut message_ids = Vec::with_capacity(originals.len());
+    for original in originals.iter() {
+        let forwarded_from = ForwardedFrom {
+            user_id: original.from_id,
+            chat_id: msg.from_chat_id.is_negative().then_some(msg.from_chat_id),
+        };
+
+        let mut db_message = messages_db
+            .add_forwarded_message(
+                original,
+                forwarded_from,
+                &self_user_id,
+                target_chat.chat_id(),
+            )
+            .await?;
+        if !target_chat.is_group() {
+            db_message.chat_id = self_user_id.as_i32_unchecked();
+        }
+        message_ids.push(db_message.message_id);
+
+        read_cursors_db
+            .advance(target_chat.chat_id(), &self_user_id, db_message.message_id)
+            .await?;
+        notify_new_message(&delivery, &target_chat, &self_user_id, msg.to, db_message);
+    }
+
+    Ok(message_ids)
+}
+
+pub async fn handle(handler: &mut JsonHandler, method: &str) {
+    macros::require_auth!(handler, method);
+
+    let content = handler.utf8_content_unchecked();
+    let msg = match serde_json::from_str::<ForwardMessagesRequest>(content) {
+        Ok(msg) => msg,
+        Err(err) => {
+            handler.send_error(method, err.to_string().into()).await;
+            return;
+        }
+    };
+
+    match handle_forward(handler, &msg).await {
+        Ok(message_ids) => {
+            handler
+                .send_message(&ForwardMessagesResponse {
+                    ok: true,
+                    method: "forward".into(),
+                    chat_id: msg.to,
+                    message_ids,
+                })
+                .await
+        }
+        Err(err) => handler.send_error(method, err).await,
+    }
+}
@@ -0,0 +1,497 @@
+use crate::{
+    db::{
+        chat::{
+            chats::ChatsDB, hidden::HiddenMessagesDB, join_requests::JoinRequestsDB,
+            read_cursors::ReadCursorsDB, subscribers::SubscribersDB,
+        },
+        internal::error::PPResult,
+        user::UsersDB,
+    },
+    server::message::{
+        delivery::Delivery,
+        handlers::json_handler::JsonHandler,
+        methods::macros,
+        types::{
+            chat::{ChatDetailsResponse, ChatId, Permission, Role},
+            request::{
+                extract_what_field,
+                group::{AddParticipantsRequest, LeaveGroupRequest, ParticipantRequest},
+            },
+            response::{
+                events::{
+                    JoinRequestDeclinedEvent, NewChatEvent, NewParticipantEvent,
+                    ParticipantLeftEvent, ParticipantRemovedEvent,
+                },
+                group::{AddParticipantsResponse, LeaveGroupResponse, ParticipantResponse},
+            },
+            user::UserId,
+        },
+    },
+};
+
+/// Most users, that may be added to a group at once
+const MAX_ADDED_PARTICIPANTS: usize = 200;
+
+/// Checks users before adding them to the group, dropping duplicates
+///
+/// Users, that are already participants, are skipped
+pub(crate) async fn validate_new_participants(
+    delivery: &Delivery,
+    chat_id: Option<ChatId>,
+    self_user_id: &UserId,
+    user_ids: &[i32],
+) -> PPResult<Vec<i32>> {
+    if user_ids.len() > MAX_ADDED_PARTICIPANTS {
+        return Err(format!(
+            "At most {} participants can be added at once!",
+            MAX_ADDED_PARTICIPANTS
+        )
+        .into());
+    }
+
+    let users_db: UsersDB = delivery.get_db();
+    let chats_db: ChatsDB = delivery.get_db();
+    let participants = match chat_id {
+        Some(chat_id) => chats_db
+            .fetch_participants(chat_id)
+            .await?
+            .unwrap_or_default(),
+        None => vec![],
+    };
+    // Subscribers of channels aren't listed in the participants
+    let is_channel = match chat_id {
+        Some(chat_id) => chats_db.is_channel(chat_id).await?,
+        None => false,
+    };
+    let subscribers_db: SubscribersDB = delivery.get_db();
+
+    let mut new_participants = Vec::with_capacity(user_ids.len());
+    for &user_id in user_ids {
+        if user_id == self_user_id.as_i32_unchecked()
+            || participants.contains(&user_id)
+            || new_participants.contains(&user_id)
+        {
+            continue;
+        }
+        if !users_db.exists(&user_id.into()).await? {
+            return Err(format!("User {} doesn't exist!", user_id).into());
+        }
+        if let Some(chat_id) = chat_id {
+            if is_channel && subscribers_db.is_subscriber(chat_id, &user_id.into()).await? {
+                continue;
+            }
+            if chats_db.is_banned(chat_id, &user_id.into()).await? {
+                return Err(format!("User {} is banned from the group!", user_id).into());
+            }
+        }
+        new_participants.push(user_id);
+    }
+
+    Ok(new_participants)
+}
+
+/// Adds the users to the participants of the group and to their chats
+///
+/// New participants receive `new_chat` event. Channels get them as subscribers
+pub(crate) async fn add_to_group(
+    delivery: &Delivery,
+    chat_id: ChatId,
+    user_ids: &[i32],
+) -> PPResult<()> {
+    let users_db: UsersDB = delivery.get_db();
+    let chats_db: ChatsDB = delivery.get_db();
+
+    for &user_id in user_ids {
+        let user_id: UserId = user_id.into();
+        chats_db.add_member(chat_id, &user_id).await?;
+        users_db
+            .add_associated_chat(&user_id, chat_id, chat_id)
+            .await?;
+    }
+
+    let Some(&first) = user_ids.first() else {
+        return Ok(());
+    };
+    // Details of a group are the same for everyone
+    let (_, details) = chats_db
+        .fetch_chat(&first.into(), chat_id)
+        .await?
+        .ok_or("Group wasn't found!")?;
+    let new_chat = ChatDetailsResponse {
+        details,
+        unread_count: 0,
+        draft: "".into(),
+        pinned_message: chats_db.fetch_pinned(chat_id).await?.pop(),
+        auto_delete: chats_db.fetch_auto_delete(chat_id).await?,
+    };
+
+    let receivers: Vec<_> = user_ids
+        .iter()
+        .map(|&user_id| {
+            (
+                user_id,
+                NewChatEvent {
+                    event: "new_chat".into(),
+                    new_chat: new_chat.clone(),
+                },
+            )
+        })
+        .collect();
+    delivery.send_events_to_connections(receivers);
+
+    Ok(())
+}
+
+/// Removes the user from the participants of the group and from the chats of the user
+pub(crate) async fn remove_from_group(
+    delivery: &Delivery,
+    chat_id: ChatId,
+    user_id: &UserId,
+) -> PPResult<()> {
+    delivery
+        .get_db::<ChatsDB>()
+        .remove_participant(chat_id, user_id)
+        .await?;
+    delivery
+        .get_db::<UsersDB>()
+        .remove_associated_chat(user_id, chat_id)
+        .await?;
+    delivery
+        .get_db::<ReadCursorsDB>()
+        .delete_cursor(chat_id, user_id)
+        .await?;
+    delivery
+        .get_db::<HiddenMessagesDB>()
+        .delete_hidden(chat_id, &[user_id.as_i32_unchecked()])
+        .await?;
+
+    Ok(())
+}
+
+/// Real id of the group, the user is in
+async fn fetch_group_id(
+    handler: &JsonHandler,
+    self_user_id: &UserId,
+    chat_id: ChatId,
+) -> PPResult<ChatId> {
+    let real_chat_id = handler
+        .get_db::<UsersDB>()
+        .get_associated_chat_id(self_user_id, chat_id)
+        .await?
+        .ok_or("Chat with the given chat_id doesn't exist!")?;
+    if !real_chat_id.is_negative() {
+        return Err("The id of provided chat must be a group!".into());
+    }
+
+    Ok(real_chat_id)
+}
+
+async fn handle_leave(handler: &JsonHandler, msg: &LeaveGroupRequest) -> PPResult<()> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let real_chat_id = fetch_group_id(handler, &self_user_id, msg.chat_id).await?;
+    let chats_db: ChatsDB = handler.get_db();
+    let (owner_id, _, _) = chats_db.fetch_roles(real_chat_id).await?;
+    if owner_id == Some(self_user_id.as_i32_unchecked()) {
+        return Err("The owner can't leave the group, delete it instead!".into());
+    }
+
+    let delivery = handler.delivery();
+    remove_from_group(&delivery, real_chat_id, &self_user_id).await?;
+    // Channels only count their subscribers
+    if chats_db.is_channel(real_chat_id).await? {
+        return Ok(());
+    }
+
+    let receivers: Vec<_> = chats_db
+        .fetch_participants(real_chat_id)
+        .await?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|user_id| {
+            (
+                user_id,
+                ParticipantLeftEvent {
+                    event: "participant_left".into(),
+                    chat_id: msg.chat_id,
+                    user_id: self_user_id.as_i32_unchecked(),
+                },
+            )
+        })
+        .collect();
+    delivery.send_events_to_connections(receivers);
+
+    Ok(())
+}
+
+/// Kicks the participant, banning also keeps them from joining again
+///
+/// Only the owner may remove admins, nobody may remove the owner
+async fn handle_remove(handler: &JsonHandler, msg: &ParticipantRequest, ban: bool) -> PPResult<()> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let real_chat_id = fetch_group_id(handler, &self_user_id, msg.chat_id).await?;
+    let chats_db: ChatsDB = handler.get_db();
+
+    let self_role = chats_db.fetch_role(real_chat_id, &self_user_id).await?;
+    let is_owner = match self_role {
+        Some((Role::Owner, _)) => true,
+        Some((Role::Admin, _)) => false,
+        _ => return Err("Only admins can remove participants!".into()),
+    };
+    if msg.user_id == self_user_id.as_i32_unchecked() {
+        return Err("You can't remove yourself, leave the group instead!".into());
+    }
+
+    let target_user_id: UserId = msg.user_id.into();
+    let target_role = chats_db.fetch_role(real_chat_id, &target_user_id).await?;
+    match target_role {
+        Some((Role::Owner, _)) => return Err("The owner can't be removed!".into()),
+        Some((Role::Admin, _)) if !is_owner => {
+            return Err("Only the owner can remove admins!".into())
+        }
+        // Users may be banned before they join
+        None if !ban => return Err("User isn't a participant of the group!".into()),
+        _ => {}
+    }
+
+    if ban {
+        chats_db.ban(real_chat_id, &target_user_id).await?;
+    }
+    if target_role.is_none() {
+        return Ok(());
+    }
+
+    let delivery = handler.delivery();
+    remove_from_group(&delivery, real_chat_id, &target_user_id).await?;
+
+    let mut receivers = chats_db
+        .fetch_participants(real_chat_id)
+        .await?
+        .unwrap_or_default();
+    receivers.retain(|&user_id| user_id != self_user_id.as_i32_unchecked());
+    receivers.push(msg.user_id);
+    let receivers: Vec<_> = receivers
+        .into_iter()
+        .map(|user_id| {
+            (
+                user_id,
+                ParticipantRemovedEvent {
+                    event: "participant_removed".into(),
+                    chat_id: msg.chat_id,
+                    user_id: msg.user_id,
+                    banned: ban,
+                },
+            )
+        })
+        .collect();
+    delivery.send_events_to_connections(receivers);
+
+    Ok(())
+}
+
+async fn handle_unban(handler: &JsonHandler, msg: &ParticipantRequest) -> PPResult<()> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let real_chat_id = fetch_group_id(handler, &self_user_id, msg.chat_id).await?;
+    let chats_db: ChatsDB = handler.get_db();
+    if !chats_db.is_admin(real_chat_id, &self_user_id).await? {
+        return Err("Only admins can unban users!".into());
+    }
+
+    chats_db.unban(real_chat_id, &msg.user_id.into()).await
+}
+
+/// Adds users to the group directly, requires the `invite` permission
+///
+/// Returns ids of the added users
+async fn handle_add_participants(
+    handler: &JsonHandler,
+    msg: &AddParticipantsRequest,
+) -> PPResult<Vec<i32>> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let real_chat_id = fetch_group_id(handler, &self_user_id, msg.chat_id).await?;
+    let chats_db: ChatsDB = handler.get_db();
+    chats_db
+        .check_permission(real_chat_id, &self_user_id, Permission::Invite)
+        .await?;
+
+    let delivery = handler.delivery();
+    let existing = chats_db
+        .fetch_participants(real_chat_id)
+        .await?
+        .unwrap_or_default();
+    let added =
+        validate_new_participants(&delivery, Some(real_chat_id), &self_user_id, &msg.user_ids)
+            .await?;
+    add_to_group(&delivery, real_chat_id, &added).await?;
+
+    let users_db: UsersDB = handler.get_db();
+    let mut receivers = vec![];
+    for &user_id in added.iter() {
+        let Some(new_user) = users_db.fetch_user(&user_id.into()).await? else {
+            continue;
+        };
+        receivers.extend(
+            existing
+                .iter()
+                .filter(|&&other| other != self_user_id.as_i32_unchecked())
+                .map(|&other| {
+                    (
+                        other,
+                        NewParticipantEvent {
+                            event: "new_participant".into(),
+                            chat_id: msg.chat_id,
+                            new_user: new_user.clone(),
+                        },
+                    )
+                }),
+        );
+    }
+    delivery.send_events_to_connections(receivers);
+
+    Ok(added)
+}
+
+/// Lets the user in or declines the join request, requires the `invite` permission
+async fn handle_join_request(
+    handler: &JsonHandler,
+    msg: &ParticipantRequest,
+    approve: bool,
+) -> PPResult<()> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    let real_chat_id = fetch_group_id(handler, &self_user_id, msg.chat_id).await?;
+    let chats_db: ChatsDB = handler.get_db();
+    chats_db
+        .check_permission(real_chat_id, &self_user_id, Permission::Invite)
+        .await?;
+
+    let user_id: UserId = msg.user_id.into();
+    if !handler
+        .get_db::<JoinRequestsDB>()
+        .delete_request(real_chat_id, &user_id)
+        .await?
+    {
+        return Err("The user hasn't requested to join the group!".into());
+    }
+
+    let delivery = handler.delivery();
+    if !approve {
+        delivery.send_events_to_connections(vec![(
+            msg.user_id,
+            JoinRequestDeclinedEvent {
+                event: "join_request_declined".into(),
+                chat_id: msg.chat_id,
+            },
+        )]);
+        return Ok(());
+    }
+
+    let existing = chats_db
+        .fetch_participants(real_chat_id)
+        .await?
+        .unwrap_or_default();
+    let added =
+        validate_new_participants(&delivery, Some(real_chat_id), &self_user_id, &[msg.user_id])
+            .await?;
+    add_to_group(&delivery, real_chat_id, &added).await?;
+
+    let Some(new_user) = handler.get_db::<UsersDB>().fetch_user(&user_id).await? else {
+        return Ok(());
+    };
+    let receivers: Vec<_> = existing
+        .into_iter()
+        .filter(|&other| other != self_user_id.as_i32_unchecked())
+        .map(|other| {
+            (
+                other,
+                NewParticipantEvent {
+                    event: "new_participant".into(),
+                    chat_id: msg.chat_id,
+                    new_user: new_user.clone(),
+                },
+            )
+        })
+        .collect();
+    delivery.send_events_to_connections(receivers);
+
+    Ok(())
+}
+
+async fn on_group(
+    handler: &JsonHandler,
+    method: &str,
+    content: &str,
+) -> PPResult<serde_json::Value> {
+    match method {
+        "leave" => {
+            let msg: LeaveGroupRequest = serde_json::from_str(content)?;
+            handle_leave(handler, &msg).await?;
+            Ok(serde_json::to_value(LeaveGroupResponse {
+                ok: true,
+                method: "leave".into(),
+                chat_id: msg.chat_id,
+            })
+            .unwrap())
+        }
+        "add" => match extract_what_field(content)?.as_str() {
+            "participants" => {
+                let msg: AddParticipantsRequest = serde_json::from_str(content)?;
+                let user_ids = handle_add_participants(handler, &msg).await?;
+                Ok(serde_json::to_value(AddParticipantsResponse {
+                    ok: true,
+                    method: "add_participants".into(),
+                    chat_id: msg.chat_id,
+                    user_ids,
+                })
+                .unwrap())
+            }
+            _ => Err("Unknown what field! Known what fields for add: 'participants'".into()),
+        },
+        "kick" | "ban" | "unban" | "approve" | "decline" => {
+            let msg: ParticipantRequest = serde_json::from_str(content)?;
+            match method {
+                "unban" => handle_unban(handler, &msg).await?,
+                "approve" | "decline" => {
+                    handle_join_request(handler, &msg, method == "approve").await?
+                }
+                _ => handle_remove(handler, &msg, method == "ban").await?,
+            }
+            Ok(serde_json::to_value(ParticipantResponse {
+                ok: true,
+                method: method.into(),
+                chat_id: msg.chat_id,
+                user_id: msg.user_id,
+            })
+            .unwrap())
+        }
+        _ => Err("Unknown method".into()),
+    }
+}
+
+pub async fn handle(handler: &mut JsonHandler, method: &str) {
+    macros::require_auth!(handler, method);
+
+    let content = handler.utf8_content_unchecked().to_owned();
+    match on_group(handler, method, &content).await {
+        Ok(val) => handler.send_message(&val).await,
+        Err(err) => handler.send_error(method, err).await,
+    }
+}
@@ -1,6 +1,11 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::{
     db::{
-        chat::{chats::ChatsDB, drafts::DraftsDB, messages::MessagesDB},
+        chat::{
+            chats::ChatsDB, drafts::DraftsDB, invitations::InvitationsDB,
+            join_requests::JoinRequestsDB, messages::MessagesDB,
+        },
         internal::error::{PPError, PPResult},
         user::UsersDB,
     },
@@ -8,11 +13,11 @@ use crate::{
         handlers::json_handler::JsonHandler,
         methods::macros,
         types::{
-            chat::ChatDetailsResponse,
+            chat::{ChatDetailsResponse, Invitation, JoinRequest, Permission},
             request::join::JoinGroupRequest,
             response::{
-                events::NewParticipantEvent,
-                join::{JoinGroupResponse, JoinLinkNotFoundResponse},
+                events::{JoinRequestEvent, NewParticipantEvent},
+                join::{JoinGroupResponse, JoinLinkNotFoundResponse, JoinRequestResponse},
             },
             user::UserId,
         },
@@ -21,9 +26,83 @@ use crate::{
 
 enum JoinGroupResult {
     JoinGroupResponse(JoinGroupResponse),
+    JoinRequestResponse(JoinRequestResponse),
     JoinLinkNotFoundResponse(JoinLinkNotFoundResponse),
 }
 
+fn link_not_found() -> JoinGroupResult {
+    JoinGroupResult::JoinLinkNotFoundResponse(JoinLinkNotFoundResponse {
+        ok: true,
+        method: "join_invitation_link".into(),
+        code: 404,
+    })
+}
+
+/// Stores the request and notifies participants, who may invite users
+///
+/// Returns false if the link can't be used anymore
+async fn request_to_join(
+    handler: &JsonHandler,
+    self_user_id: &UserId,
+    invitation: &Invitation,
+    now: i64,
+) -> PPResult<bool> {
+    let chats_db: ChatsDB = handler.get_db();
+    let users_db: UsersDB = handler.get_db();
+    let join_requests_db: JoinRequestsDB = handler.get_db();
+
+    let chat_id = invitation.chat_id;
+    let request = JoinRequest {
+        chat_id,
+        user_id: self_user_id.as_i32_unchecked(),
+        link: invitation.link.clone(),
+        requested_at: now,
+    };
+    if !join_requests_db.add_request(&request).await? {
+        return Err("You have already requested to join this group!".into());
+    }
+    // Someone else took the last use meanwhile
+    if !handler
+        .get_db::<InvitationsDB>()
+        .use_invitation(invitation)
+        .await?
+    {
+        join_requests_db
+            .delete_request(chat_id, self_user_id)
+            .await?;
+        return Ok(false);
+    }
+
+    let user = users_db
+        .fetch_user(self_user_id)
+        .await?
+        .ok_or("User wasn't found!")?;
+    let mut receivers = vec![];
+    for participant in chats_db
+        .fetch_participants(chat_id)
+        .await?
+        .unwrap_or_default()
+    {
+        let can_invite = chats_db
+            .fetch_role(chat_id, &participant.into())
+            .await?
+            .is_some_and(|(_, permissions)| permissions.allows(Permission::Invite));
+        if can_invite {
+            receivers.push((
+                participant,
+                JoinRequestEvent {
+                    event: "join_request".into(),
+                    request: request.clone(),
+                    user: user.clone(),
+                },
+            ));
+        }
+    }
+    handler.send_events_to_connections(receivers);
+
+    Ok(true)
+}
+
 async fn on_join_group(
     msg: JoinGroupRequest,
     handler: &mut JsonHandler,
@@ -38,77 +117,112 @@ async fn on_join_group(
             .to_owned()
     };
 
-    if !msg.link.starts_with("+") {
-        return Err("Invitation link must start with '+'".into());
-    }
-
     let chats_db: ChatsDB = handler.get_db();
     let users_db: UsersDB = handler.get_db();
-    let chat = chats_db
-        .get_chat_by_invitation_hash(&self_user_id, msg.link)
-        .await?;
+    let invitations_db: InvitationsDB = handler.get_db();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
 
-    match chat {
-        Some((chat, chat_details)) => {
-            if users_db
-                .get_associated_chat_id(&self_user_id, chat.chat_id())
-                .await?
-                .is_some()
-            {
-                return Err(PPError::from("You have already joined this chat!"));
-            }
-            users_db
-                .add_associated_chat(&self_user_id, chat.chat_id(), chat.chat_id())
-                .await?;
-            chats_db
-                .add_participant(chat.chat_id(), &self_user_id)
-                .await?;
-            let self_info = users_db.fetch_user(&self_user_id).await?.unwrap();
-
-            // Send event to every user in the chat
-            // that new participant joined
-            for other in chat.participants() {
-                let self_info = self_info.clone();
-                let chat_id = chat.chat_id().clone();
-
-                handler.send_event_to_con_detached(
-                    other.user_id(),
-                    NewParticipantEvent {
-                        event: "new_participant".into(),
-                        chat_id,
-                        new_user: self_info,
-                    },
-                );
+    // Public groups are joined by their tag, others only by an invitation link
+    let (chat_id, invitation) = if msg.link.starts_with("@") {
+        match chats_db.fetch_public_group_id(&msg.link).await? {
+            Some(chat_id) => (chat_id, None),
+            None => return Ok(link_not_found()),
+        }
+    } else if msg.link.starts_with("+") {
+        match invitations_db
+            .fetch_invitation(&msg.link)
+            .await?
+            .filter(|invitation| invitation.is_usable(now))
+        {
+            Some(invitation) => (invitation.chat_id, Some(invitation)),
+            None => return Ok(link_not_found()),
+        }
+    } else {
+        return Err("Invitation link must start with '+' or be a tag of a public group".into());
+    };
+
+    if users_db
+        .get_associated_chat_id(&self_user_id, chat_id)
+        .await?
+        .is_some()
+    {
+        return Err(PPError::from("You have already joined this chat!"));
+    }
+    if chats_db.is_banned(chat_id, &self_user_id).await? {
+        return Err(PPError::from("You are banned from this group!"));
+    }
+
+    let Some((chat, chat_details)) = chats_db.fetch_chat(&self_user_id, chat_id).await? else {
+        return Ok(link_not_found());
+    };
+
+    if let Some(invitation) = invitation {
+        if invitation.requires_approval {
+            if !request_to_join(handler, &self_user_id, &invitation, now).await? {
+                return Ok(link_not_found());
             }
 
-            let messages_db: MessagesDB = handler.get_db();
-            let drafts_db: DraftsDB = handler.get_db();
-
-            let unread_count = messages_db
-                .fetch_unread_count(chat_details.chat_id)
-                .await?
-                .ok_or(PPError::from("Failed to fetch chat"))?;
-            let draft = drafts_db
-                .fetch_draft(&self_user_id, chat_details.chat_id)
-                .await?;
-            Ok(JoinGroupResult::JoinGroupResponse(JoinGroupResponse {
+            return Ok(JoinGroupResult::JoinRequestResponse(JoinRequestResponse {
                 ok: true,
-                method: "join_group".into(),
-                chat: ChatDetailsResponse {
-                    details: chat_details,
-                    unread_count,
-                    draft: draft.unwrap_or("".to_string()),
-                },
-            }))
+                method: "join_request".into(),
+                chat: chat_details,
+            }));
         }
-        None => Ok(JoinGroupResult::JoinLinkNotFoundResponse(
-            JoinLinkNotFoundResponse {
-                ok: true,
-                method: "join_invitation_link".into(),
-                code: 404,
+        if !invitations_db.use_invitation(&invitation).await? {
+            return Ok(link_not_found());
+        }
+    }
+
+    users_db
+        .add_associated_chat(&self_user_id, chat.chat_id(), chat.chat_id())
+        .await?;
+    chats_db.add_member(chat.chat_id(), &self_user_id).await?;
+    let self_info = users_db.fetch_user(&self_user_id).await?.unwrap();
+
+    // Send event to every user in the chat
+    // that new participant joined. Channels only count their subscribers
+    let participants: &[_] = if chat.is_channel() {
+        &[]
+    } else {
+        chat.participants()
+    };
+    for other in participants {
+        let self_info = self_info.clone();
+        let chat_id = chat.chat_id().clone();
+
+        handler.send_event_to_con_detached(
+            other.user_id(),
+            NewParticipantEvent {
+                event: "new_participant".into(),
+                chat_id,
+                new_user: self_info,
             },
-        )),
+        );
     }
+
+    let messages_db: MessagesDB = handler.get_db();
+    let drafts_db: DraftsDB = handler.get_db();
+
+    let unread_count = messages_db
+        .fetch_unread_count(chat_details.chat_id, &self_user_id)
+        .await?;
+    let draft = drafts_db
+        .fetch_draft(&self_user_id, chat_details.chat_id)
+        .await?;
+    Ok(JoinGroupResult::JoinGroupResponse(JoinGroupResponse {
+        ok: true,
+        method: "join_group".into(),
+        chat: ChatDetailsResponse {
+            details: chat_details,
+            unread_count,
+            draft: draft.unwrap_or("".to_string()),
+            pinned_message: chats_db.fetch_pinned(chat.chat_id()).await?.pop(),
+            auto_delete: chats_db.fetch_auto_delete(chat.chat_id()).await?,
+        },
+    }))
 }
 
 async fn on_join(handler: &mut JsonHandler) -> PPResult<JoinGroupResult> {
@@ -124,6 +238,7 @@ pub async fn handle(handler: &mut JsonHandler, method: &str) {
     match on_join(handler).await {
         Ok(msg) => match msg {
             JoinGroupResult::JoinGroupResponse(msg) => handler.send_message(&msg).await,
+            JoinGroupResult::JoinRequestResponse(msg) => handler.send_message(&msg).await,
             JoinGroupResult::JoinLinkNotFoundResponse(msg) => handler.send_message(&msg).await,
         },
         Err(err) => handler.send_error(method, err.into()).await,
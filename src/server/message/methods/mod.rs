@@ -6,6 +6,9 @@ pub mod send;
 pub mod bind;
 pub mod new;
 pub mod join;
+pub mod export;
+pub mod forward;
+pub mod group;
 
 #[macro_use] // This will allow macros to be imported into the scope
 pub mod macros {
@@ -1,19 +1,21 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use log::debug;
 
 use crate::{
     db::{
-        chat::{
-            chats::{ChatsDB, InvitationHash},
-            messages::MessagesDB,
-        },
+        chat::{chats::ChatsDB, invitations::InvitationsDB, messages::MessagesDB},
         internal::error::{PPError, PPResult},
         user::UsersDB,
     },
     server::message::{
         handlers::json_handler::JsonHandler,
-        methods::macros,
+        methods::{
+            group::{add_to_group, validate_new_participants},
+            macros,
+        },
         types::{
-            chat::{Chat, ChatDetails, ChatDetailsResponse},
+            chat::{Chat, ChatDetails, ChatDetailsResponse, Invitation, Permission},
             request::{
                 extract_what_field,
                 new::{NewGroupRequest, NewInvitationLinkRequest},
@@ -24,10 +26,15 @@ use crate::{
     },
 };
 
+const MAX_INVITATION_NAME_SIZE: usize = 32;
+
 /// Returns latest chat message id if sucessful
+///
+/// Participants of channels become their subscribers
 async fn handle_new_group(
     msg: NewGroupRequest,
     handler: &JsonHandler,
+    is_channel: bool,
 ) -> PPResult<(Chat, ChatDetails)> {
     let self_user_id: UserId = {
         handler
@@ -39,6 +46,15 @@ async fn handle_new_group(
             .to_owned()
     };
 
+    let delivery = handler.delivery();
+    let participants = validate_new_participants(
+        &delivery,
+        None,
+        &self_user_id,
+        msg.participants.as_deref().unwrap_or_default(),
+    )
+    .await?;
+
     let chats_db = handler.get_db::<ChatsDB>();
     let (chat, dt) = chats_db
         .create_group(
@@ -51,6 +67,8 @@ async fn handle_new_group(
                 is_group: true,
                 tag: msg.username,
                 photo: msg.avatar_hash,
+                is_public: msg.is_public.unwrap_or_default(),
+                is_channel,
             },
         )
         .await?;
@@ -58,6 +76,7 @@ async fn handle_new_group(
         .get_db::<UsersDB>()
         .add_associated_chat(&self_user_id, chat.chat_id(), chat.chat_id())
         .await?;
+    add_to_group(&delivery, chat.chat_id(), &participants).await?;
 
     Ok((chat, dt))
 }
@@ -65,7 +84,7 @@ async fn handle_new_group(
 async fn handle_new_invitation_link(
     msg: NewInvitationLinkRequest,
     handler: &JsonHandler,
-) -> PPResult<InvitationHash> {
+) -> PPResult<Invitation> {
     let self_user_id: UserId = {
         handler
             .session
@@ -96,8 +115,41 @@ async fn handle_new_invitation_link(
         return Err("Provided group doesn't exist!".into());
     }
     debug!("third");
+    db.check_permission(msg.chat_id, &self_user_id, Permission::Invite)
+        .await?;
 
-    db.create_invitation_hash(msg.chat_id).await
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    if msg.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err("expires_at must be in the future!".into());
+    }
+    if msg.max_uses == Some(0) {
+        return Err("max_uses must be positive!".into());
+    }
+    if msg
+        .name
+        .as_ref()
+        .is_some_and(|name| name.len() > MAX_INVITATION_NAME_SIZE)
+    {
+        return Err("Name of the invitation link too big".into());
+    }
+
+    handler
+        .get_db::<InvitationsDB>()
+        .create_invitation(Invitation {
+            link: Default::default(),
+            chat_id: msg.chat_id,
+            name: msg.name,
+            creator_id: self_user_id.as_i32_unchecked(),
+            created_at: now,
+            expires_at: msg.expires_at,
+            max_uses: msg.max_uses,
+            uses: 0,
+            requires_approval: msg.requires_approval.unwrap_or_default(),
+        })
+        .await
 }
 
 async fn on_new(handler: &mut JsonHandler) -> PPResult<()> {
@@ -107,7 +159,7 @@ async fn on_new(handler: &mut JsonHandler) -> PPResult<()> {
     match what_field.as_str() {
         "group" => match serde_json::from_str::<NewGroupRequest>(content) {
             Ok(msg) => {
-                let (_, chat_details) = handle_new_group(msg, handler).await?;
+                let (_, chat_details) = handle_new_group(msg, handler, false).await?;
                 handler
                     .send_message(&NewGroupResponse {
                         ok: true,
@@ -116,6 +168,27 @@ async fn on_new(handler: &mut JsonHandler) -> PPResult<()> {
                             details: chat_details,
                             unread_count: 0,
                             draft: "".into(),
+                            pinned_message: None,
+                            auto_delete: None,
+                        },
+                    })
+                    .await;
+            }
+            Err(err) => return Err(err.into()),
+        },
+        "channel" => match serde_json::from_str::<NewGroupRequest>(content) {
+            Ok(msg) => {
+                let (_, chat_details) = handle_new_group(msg, handler, true).await?;
+                handler
+                    .send_message(&NewGroupResponse {
+                        ok: true,
+                        method: "new_channel".into(),
+                        chat: ChatDetailsResponse {
+                            details: chat_details,
+                            unread_count: 0,
+                            draft: "".into(),
+                            pinned_message: None,
+                            auto_delete: None,
                         },
                     })
                     .await;
@@ -124,12 +197,13 @@ async fn on_new(handler: &mut JsonHandler) -> PPResult<()> {
         },
         "invitation_link" => match serde_json::from_str::<NewInvitationLinkRequest>(content) {
             Ok(msg) => {
-                let link = handle_new_invitation_link(msg, handler).await?;
+                let invitation = handle_new_invitation_link(msg, handler).await?;
                 handler
                     .send_message(&NewInvitationLinkResponse {
                         ok: true,
                         method: "new_invitation_link".into(),
-                        link,
+                        link: invitation.link.clone(),
+                        invitation,
                     })
                     .await;
             }
@@ -3,75 +3,57 @@ use tokio::sync::RwLock;
 
 use crate::{
     db::{
-        chat::{chats::ChatsDB, hashes::HashesDB, messages::MessagesDB},
-        internal::error::PPError,
+        chat::{
+            chats::ChatsDB, hashes::HashesDB, messages::MessagesDB, read_cursors::ReadCursorsDB,
+            scheduled::ScheduledMessagesDB,
+        },
+        internal::error::{PPError, PPResult},
         user::UsersDB,
     },
     server::{
         message::{
+            delivery::Delivery,
             handlers::json_handler::JsonHandler,
             methods::macros,
             types::{
-                chat::{ChatDetailsResponse, ChatId},
+                chat::{Chat, ChatDetailsResponse, ChatId, Permission},
+                message::{Message, ScheduledMessage},
                 request::send::{MessageId, SendMessageRequest},
                 response::{
                     events::{IsTypingEvent, NewChatEvent, NewMessageEvent},
-                    send::SendMessageResponse,
+                    send::{ScheduleMessageResponse, SendMessageResponse},
                 },
+                user::UserId,
             },
         },
         session::Session,
     },
 };
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-/// Returns latest chat message id if successful
-async fn handle_send_message(
-    session: Arc<RwLock<Session>>,
-    msg: SendMessageRequest,
-    handler: &JsonHandler,
-) -> Result<(MessageId, ChatId), PPError> {
-    let session = session.read().await;
-    let (self_user_id, _) = session.get_credentials_unchecked();
-    drop(session);
-
-    // TODO: sending messages on yourself is "Saved Messages"
-    if self_user_id.as_i32().unwrap() == msg.common.to {
-        return Err(PPError::from("You cannot send messages on yourself!"));
-    }
-
-    let users_db: UsersDB = handler.get_db();
-    let chats_db: ChatsDB = handler.get_db();
+/// Finds the chat by the public chat id, creating a private chat if it doesn't exist yet
+pub(crate) async fn get_or_create_chat(
+    delivery: &Delivery,
+    self_user_id: &UserId,
+    to: ChatId,
+) -> PPResult<Chat> {
+    let users_db: UsersDB = delivery.get_db();
+    let chats_db: ChatsDB = delivery.get_db();
 
     // Is Positive? Retrieve real Chat id by the given User Id
-    let maybe_chat = if msg.common.to.is_positive() {
-        users_db
-            .get_associated_chat_id(&self_user_id, msg.common.to)
-            .await?
+    let maybe_chat = if to.is_positive() {
+        users_db.get_associated_chat_id(self_user_id, to).await?
     } else {
-        match handler
-            .get_db::<ChatsDB>()
-            .chat_exists(msg.common.to)
-            .await?
-        {
-            true => Some(msg.common.to),
+        match chats_db.chat_exists(to).await? {
+            true => Some(to),
             false => return Err("No group found by the given chat id!".into()),
         }
     };
 
-    let hashes_db: HashesDB = handler.get_db();
-
-    if let Some(hashes) = msg.content.sha256_hashes.as_ref() {
-        for hash in hashes.iter() {
-            if !hashes_db.hash_exists(hash).await? {
-                return Err(format!("Provided SHA256 Hash: {} doesn't exist!", hash).into());
-            }
-        }
-    }
-
-    let associated_chat = match maybe_chat {
+    match maybe_chat {
         Some(existing_chat_id) => chats_db
-            .fetch_chat(&self_user_id, existing_chat_id)
+            .fetch_chat(self_user_id, existing_chat_id)
             .await?
             .map(|v| v.0)
             .ok_or(PPError::from("Failed to find Chat!")),
@@ -79,60 +61,196 @@ async fn handle_send_message(
         None => {
             debug!(
                 "Message was sent to: {}. Chat with this user wasn't found. Creating chat.",
-                msg.common.to
+                to
             );
 
-            if !users_db.exists(&msg.common.to.into()).await? {
+            if !users_db.exists(&to.into()).await? {
                 return Err(PPError::from("Target user_id doesn't exist!"));
             }
 
-            let (chat, mut chat_details) = handler
-                .get_db::<ChatsDB>()
-                .create_private(&self_user_id, &msg.common.to.into())
-                .await?;
+            let (chat, mut chat_details) =
+                chats_db.create_private(self_user_id, &to.into()).await?;
             users_db
-                .add_associated_chat(&self_user_id, msg.common.to, chat.chat_id())
-                .await
-                .unwrap();
-            users_db
-                .add_associated_chat(
-                    &msg.common.to.into(),
-                    self_user_id.as_i32_unchecked(),
-                    chat.chat_id(),
-                )
+                .add_associated_chat(self_user_id, to, chat.chat_id())
                 .await
                 .unwrap();
+            // Saved Messages are associated only once, under the own id
+            let is_saved_messages = self_user_id.as_i32_unchecked() == to;
+            if !is_saved_messages {
+                users_db
+                    .add_associated_chat(
+                        &to.into(),
+                        self_user_id.as_i32_unchecked(),
+                        chat.chat_id(),
+                    )
+                    .await
+                    .unwrap();
+            }
 
             chat_details.chat_id = self_user_id.as_i32().unwrap();
-            handler.send_event_to_con_detached(
-                msg.common.to,
-                NewChatEvent {
-                    event: "new_chat".into(),
-                    new_chat: ChatDetailsResponse {
-                        details: chat_details,
-                        unread_count: 0,
-                        draft: "".into(),
-                    },
+            let ev = NewChatEvent {
+                event: "new_chat".into(),
+                new_chat: ChatDetailsResponse {
+                    details: chat_details,
+                    unread_count: 0,
+                    draft: "".into(),
+                    pinned_message: None,
+                    auto_delete: None,
                 },
-            );
+            };
+            if is_saved_messages {
+                delivery.send_event_to_own_sessions(to, ev);
+            } else {
+                delivery.send_event_to_con_detached(to, ev);
+            }
 
             Ok(chat)
         }
-    }?;
+    }
+}
+
+/// Sends `new_message` event to every other participant of the chat
+///
+/// `db_message` must already have the chat id, relative to the receivers
+pub(crate) fn notify_new_message(
+    delivery: &Delivery,
+    chat: &Chat,
+    self_user_id: &UserId,
+    to: ChatId,
+    db_message: Message,
+) {
+    let ev = NewMessageEvent {
+        event: "new_message".into(),
+        new_message: db_message,
+    };
 
-    let messages_db: MessagesDB = handler.get_db();
+    if chat.is_channel() {
+        delivery.send_event_to_subscribers(
+            chat.chat_id(),
+            Some(self_user_id.as_i32_unchecked()),
+            ev,
+        );
+    } else if chat.is_group() {
+        let receivers: Vec<_> = chat
+            .participants()
+            .iter()
+            .filter(|&u| u.user_id() != self_user_id.as_i32_unchecked())
+            .map(|u| (u.user_id(), ev.clone()))
+            .collect();
+        delivery.send_events_to_connections(receivers);
+    } else if to == self_user_id.as_i32_unchecked() {
+        // Saved Messages are synced between the connections of the user
+        delivery.send_event_to_own_sessions(to, ev);
+    } else {
+        delivery.send_event_to_con_detached(to, ev);
+    }
+}
+
+/// Checks whether the user may post to the group. Private chats aren't restricted
+pub(crate) async fn check_send_permissions(
+    delivery: &Delivery,
+    self_user_id: &UserId,
+    chat_id: ChatId,
+    has_media: bool,
+) -> PPResult<()> {
+    if !chat_id.is_negative() {
+        return Ok(());
+    }
+
+    let chats_db: ChatsDB = delivery.get_db();
+    chats_db
+        .check_permission(chat_id, self_user_id, Permission::SendMessages)
+        .await?;
+    if has_media {
+        chats_db
+            .check_permission(chat_id, self_user_id, Permission::SendMedia)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Checks the message before sending or scheduling it
+async fn validate_message(handler: &JsonHandler, msg: &SendMessageRequest) -> PPResult<()> {
+    let hashes_db: HashesDB = handler.get_db();
+
+    if let Some(hashes) = msg.content.sha256_hashes.as_ref() {
+        for hash in hashes.iter() {
+            if !hashes_db.hash_exists(hash).await? {
+                return Err(format!("Provided SHA256 Hash: {} doesn't exist!", hash).into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Stores the message and sends `new_message` event to the receivers
+///
+/// Returns the chat and the message, which has the chat id relative to the receivers
+pub(crate) async fn deliver_message(
+    delivery: &Delivery,
+    self_user_id: &UserId,
+    msg: &SendMessageRequest,
+) -> PPResult<(Chat, Message)> {
+    let associated_chat = get_or_create_chat(delivery, self_user_id, msg.common.to).await?;
+    let has_media = msg
+        .content
+        .sha256_hashes
+        .as_ref()
+        .is_some_and(|hashes| !hashes.is_empty());
+    check_send_permissions(delivery, self_user_id, associated_chat.chat_id(), has_media).await?;
+
+    let messages_db: MessagesDB = delivery.get_db();
     let mut db_message = messages_db
-        .add_message(&msg, &self_user_id, associated_chat.chat_id())
+        .add_message(msg, self_user_id, associated_chat.chat_id())
+        .await?;
+    messages_db
+        .attach_reply_previews(
+            associated_chat.chat_id(),
+            std::slice::from_mut(&mut db_message),
+        )
         .await?;
     if !associated_chat.is_group() {
         db_message.chat_id = self_user_id.as_i32_unchecked();
     }
-    let message_id = db_message.message_id;
 
-    let ev = NewMessageEvent {
-        event: "new_message".into(),
-        new_message: db_message.clone(),
-    };
+    // Own messages are read by definition
+    delivery
+        .get_db::<ReadCursorsDB>()
+        .advance(
+            associated_chat.chat_id(),
+            self_user_id,
+            db_message.message_id,
+        )
+        .await?;
+
+    notify_new_message(
+        delivery,
+        &associated_chat,
+        self_user_id,
+        msg.common.to,
+        db_message.clone(),
+    );
+
+    Ok((associated_chat, db_message))
+}
+
+/// Returns latest chat message id if successful
+async fn handle_send_message(
+    session: Arc<RwLock<Session>>,
+    msg: SendMessageRequest,
+    handler: &JsonHandler,
+) -> Result<(MessageId, ChatId), PPError> {
+    let session = session.read().await;
+    let (self_user_id, _) = session.get_credentials_unchecked();
+    drop(session);
+
+    validate_message(handler, &msg).await?;
+
+    let (associated_chat, db_message) =
+        deliver_message(&handler.delivery(), &self_user_id, &msg).await?;
+    let message_id = db_message.message_id;
 
     let interrupt_typing_ev = IsTypingEvent {
         event: "is_typing".into(),
@@ -141,28 +259,70 @@ async fn handle_send_message(
         user_id: self_user_id.as_i32_unchecked(),
     };
 
-    if associated_chat.is_group() {
-        let receivers: Vec<_> = associated_chat
+    let is_typing_receivers = if associated_chat.is_group() {
+        associated_chat
             .participants()
             .iter()
             .filter(|&u| u.user_id() != self_user_id.as_i32_unchecked())
-            .map(|u| (u.user_id(), ev.clone()))
-            .collect();
-        let is_typing_receivers = receivers.iter().map(|u| u.0.into()).collect();
-
-        handler.send_events_to_connections(receivers);
-        handler
-            .send_is_typing(interrupt_typing_ev, is_typing_receivers)
-            .await;
+            .map(|u| u.user_id().into())
+            .collect()
+    } else if msg.common.to == self_user_id.as_i32_unchecked() {
+        // Nobody else is typing in Saved Messages
+        vec![]
     } else {
-        handler.send_event_to_con_detached(msg.common.to, ev);
+        vec![msg.common.to.into()]
+    };
+    handler
+        .send_is_typing(interrupt_typing_ev, is_typing_receivers)
+        .await;
+
+    Ok((message_id, msg.common.to))
+}
+
+/// Stores the message until `schedule_at`, the scheduler delivers it then
+async fn handle_schedule_message(
+    handler: &JsonHandler,
+    msg: &SendMessageRequest,
+    schedule_at: i64,
+) -> PPResult<ScheduledMessage> {
+    let self_user_id = {
+        let session = handler.session.read().await;
+        session.get_credentials_unchecked().0.to_owned()
+    };
+
+    validate_message(handler, msg).await?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    if schedule_at <= now {
+        return Err("schedule_at must be in the future!".into());
+    }
 
+    // The chat itself is created on delivery
+    let target_exists = if msg.common.to.is_positive() {
         handler
-            .send_is_typing(interrupt_typing_ev, vec![msg.common.to.into()])
-            .await;
+            .get_db::<UsersDB>()
+            .exists(&msg.common.to.into())
+            .await?
+    } else {
+        handler.get_db::<ChatsDB>().chat_exists(msg.common.to).await?
+    };
+    if !target_exists {
+        return Err("Target chat doesn't exist!".into());
     }
+    let has_media = msg
+        .content
+        .sha256_hashes
+        .as_ref()
+        .is_some_and(|hashes| !hashes.is_empty());
+    check_send_permissions(&handler.delivery(), &self_user_id, msg.common.to, has_media).await?;
 
-    Ok((message_id, msg.common.to))
+    handler
+        .get_db::<ScheduledMessagesDB>()
+        .schedule(msg, &self_user_id, schedule_at)
+        .await
 }
 
 pub async fn handle(handler: &mut JsonHandler, method: &str) {
@@ -171,6 +331,22 @@ pub async fn handle(handler: &mut JsonHandler, method: &str) {
     let content = handler.utf8_content_unchecked();
     match serde_json::from_str::<SendMessageRequest>(content) {
         Ok(msg) => match msg.common.method.as_str() {
+            "send_message" if msg.schedule_at.is_some() => {
+                let schedule_at = msg.schedule_at.unwrap();
+                match handle_schedule_message(handler, &msg, schedule_at).await {
+                    Ok(scheduled_message) => {
+                        let data = ScheduleMessageResponse {
+                            ok: true,
+                            method: "send_message".into(),
+                            scheduled_message,
+                        };
+                        handler.send_message(&data).await;
+                    }
+                    Err(err) => {
+                        handler.send_error(method, err).await;
+                    }
+                }
+            }
             "send_message" => {
                 match handle_send_message(Arc::clone(&handler.session), msg, handler).await {
                     Ok((latest_msg_id, target_chat_id)) => {
@@ -2,6 +2,7 @@ pub mod builder;
 pub mod types;
 pub mod methods;
 pub mod handlers;
+pub mod delivery;
 
 #[async_trait::async_trait]
 pub trait Handler {
@@ -6,6 +6,8 @@ use super::user::{User, UserId};
 
 pub type ChatId = i32;
 
+const SAVED_MESSAGES_NAME: &str = "Saved Messages";
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ChatDetails {
     pub name: String,
@@ -14,6 +16,12 @@ pub struct ChatDetails {
     pub color: Option<u32>,
     pub photo: Option<String>,
     pub tag: Option<String>,
+    /// Public groups can be found by `fetch groups` and joined by their tag
+    #[serde(default)]
+    pub is_public: bool,
+    /// Only admins post to channels, others are subscribers
+    #[serde(default)]
+    pub is_channel: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -22,6 +30,10 @@ pub struct ChatDetailsResponse {
     pub details: ChatDetails,
     pub unread_count: u64,
     pub draft: String,
+    /// The latest pinned message
+    pub pinned_message: Option<i32>,
+    /// Default expiry timer of new messages in seconds
+    pub auto_delete: Option<u32>,
 }
 
 impl ChatDetails {
@@ -32,6 +44,8 @@ impl ChatDetails {
         photo: Option<String>,
         color: Option<u32>,
         tag: Option<String>,
+        is_public: bool,
+        is_channel: bool,
     ) -> Self {
         Self {
             name,
@@ -40,6 +54,8 @@ impl ChatDetails {
             color,
             photo,
             tag,
+            is_public,
+            is_channel,
         }
     }
 
@@ -60,14 +76,22 @@ impl ChatDetails {
 pub struct Chat {
     chat_id: ChatId,
     is_group: bool,
+    is_channel: bool,
+    /// Only the owner and admins for channels, subscribers are stored separately
     participants: Vec<User>,
 }
 
 impl Chat {
-    pub fn construct(chat_id: i32, is_group: bool, participants: Vec<User>) -> Self {
+    pub fn construct(
+        chat_id: i32,
+        is_group: bool,
+        is_channel: bool,
+        participants: Vec<User>,
+    ) -> Self {
         Self {
             chat_id,
             is_group,
+            is_channel,
             participants,
         }
     }
@@ -76,6 +100,11 @@ impl Chat {
         self.is_group
     }
 
+    /// Channels are groups too
+    pub fn is_channel(&self) -> bool {
+        self.is_channel
+    }
+
     pub fn chat_id(&self) -> ChatId {
         self.chat_id
     }
@@ -88,18 +117,29 @@ impl Chat {
     pub async fn get_personal_chat_details(&self, relative_to: &UserId) -> PPResult<ChatDetails> {
         match self.is_group {
             false => {
-                if let Some(peer) = self
+                let self_id = relative_to.as_i32_unchecked();
+                // The user is the only participant of Saved Messages
+                let is_saved_messages = self
                     .participants
                     .iter()
-                    .find(|&participant| participant.user_id() != relative_to.as_i32_unchecked())
-                {
+                    .all(|participant| participant.user_id() == self_id);
+
+                if let Some(peer) = self.participants.iter().find(|&participant| {
+                    is_saved_messages || participant.user_id() != self_id
+                }) {
                     Ok(ChatDetails {
-                        name: peer.name().into(),
+                        name: if is_saved_messages {
+                            SAVED_MESSAGES_NAME.into()
+                        } else {
+                            peer.name().into()
+                        },
                         chat_id: self.chat_id,
                         is_group: self.is_group,
                         color: Some(peer.profile_color()),
                         photo: peer.photo().cloned(),
                         tag: Some(peer.username().into()),
+                        is_public: false,
+                        is_channel: false,
                     })
                 } else {
                     Err("Provided UserId wasn't found in the chat!".into())
@@ -109,3 +149,153 @@ impl Chat {
         }
     }
 }
+
+/// Role of a participant in a group
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Owner,
+    Admin,
+    Member,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GroupAdmin {
+    pub user_id: i32,
+    pub permissions: Permissions,
+}
+
+/// Single action in a group, that may be restricted
+#[derive(Debug, Clone, Copy)]
+pub enum Permission {
+    SendMessages,
+    SendMedia,
+    Invite,
+    Pin,
+    EditInfo,
+    DeleteMessages,
+}
+
+impl Permission {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Permission::SendMessages => "send messages",
+            Permission::SendMedia => "send media",
+            Permission::Invite => "invite users",
+            Permission::Pin => "pin messages",
+            Permission::EditInfo => "edit the group info",
+            Permission::DeleteMessages => "delete messages of others",
+        }
+    }
+
+    fn bit(&self) -> i32 {
+        1 << (*self as i32)
+    }
+}
+
+/// What a participant of a group may do
+///
+/// Stored as a bitmask, missing fields are false
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Permissions {
+    pub send_messages: bool,
+    pub send_media: bool,
+    pub invite: bool,
+    pub pin: bool,
+    pub edit_info: bool,
+    pub delete_messages: bool,
+}
+
+impl Permissions {
+    pub const ALL: Permissions = Permissions {
+        send_messages: true,
+        send_media: true,
+        invite: true,
+        pin: true,
+        edit_info: true,
+        delete_messages: true,
+    };
+
+    /// Members of a group, which didn't change them
+    pub const MEMBER_DEFAULT: Permissions = Permissions {
+        send_messages: true,
+        send_media: true,
+        invite: false,
+        pin: false,
+        edit_info: false,
+        delete_messages: false,
+    };
+
+    const FIELDS: [Permission; 6] = [
+        Permission::SendMessages,
+        Permission::SendMedia,
+        Permission::Invite,
+        Permission::Pin,
+        Permission::EditInfo,
+        Permission::DeleteMessages,
+    ];
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::SendMessages => self.send_messages,
+            Permission::SendMedia => self.send_media,
+            Permission::Invite => self.invite,
+            Permission::Pin => self.pin,
+            Permission::EditInfo => self.edit_info,
+            Permission::DeleteMessages => self.delete_messages,
+        }
+    }
+
+    pub fn to_bits(self) -> i32 {
+        Self::FIELDS
+            .iter()
+            .filter(|permission| self.allows(**permission))
+            .fold(0, |bits, permission| bits | permission.bit())
+    }
+
+    pub fn from_bits(bits: i32) -> Self {
+        let has = |permission: Permission| bits & permission.bit() != 0;
+        Permissions {
+            send_messages: has(Permission::SendMessages),
+            send_media: has(Permission::SendMedia),
+            invite: has(Permission::Invite),
+            pin: has(Permission::Pin),
+            edit_info: has(Permission::EditInfo),
+            delete_messages: has(Permission::DeleteMessages),
+        }
+    }
+}
+
+/// Link to join a group, `link` is the invitation hash starting with '+'
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Invitation {
+    pub link: String,
+    pub chat_id: ChatId,
+    pub name: Option<String>,
+    pub creator_id: i32,
+    pub created_at: i64,
+    /// Unix timestamp, the link never expires if not set
+    pub expires_at: Option<i64>,
+    /// Unlimited if not set
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+    /// Users, who join by the link, must be approved by an admin first
+    pub requires_approval: bool,
+}
+
+impl Invitation {
+    pub fn is_usable(&self, now: i64) -> bool {
+        self.expires_at.is_none_or(|expires_at| now < expires_at)
+            && self.max_uses.is_none_or(|max_uses| self.uses < max_uses)
+    }
+}
+
+/// Request to join a group by an invitation link, which requires approval
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct JoinRequest {
+    pub chat_id: ChatId,
+    pub user_id: i32,
+    pub link: String,
+    pub requested_at: i64,
+}
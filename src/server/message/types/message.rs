@@ -9,6 +9,101 @@ pub struct Message {
     pub chat_id: i32,
     pub is_edited: bool,
     pub date: i64,
+    /// Unix timestamp of the latest edit
+    #[serde(default)]
+    pub edited_at: Option<i64>,
+    pub reply_to: Option<i32>,
+    pub content: Option<String>,
+    pub sha256_hashes: Option<Vec<String>>,
+    #[serde(default)]
+    pub forwarded_from: Option<ForwardedFrom>,
+    /// Unix timestamp, after which the message is deleted
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Aggregated reactions, filled on fetching
+    #[serde(default)]
+    pub reactions: Vec<ReactionCount>,
+    /// Reaction of the user, who fetched the message
+    #[serde(default)]
+    pub my_reaction: Option<String>,
+    /// Replies to the message, filled on fetching. None if there are none
+    #[serde(default)]
+    pub thread: Option<ThreadInfo>,
+    /// Short version of the message `reply_to` points at, filled on fetching and sending.
+    /// None if the message isn't a reply, or the replied message was deleted
+    #[serde(default)]
+    pub reply_preview: Option<ReplyPreview>,
+}
+
+/// Quoted message, which is replied to
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ReplyPreview {
+    pub message_id: i32,
+    pub from_id: i32,
+    /// Beginning of the text, up to [`REPLY_PREVIEW_LENGTH`] characters
+    pub text: Option<String>,
+    /// Kind of the first attached file
+    pub media: Option<MediaKind>,
+}
+
+/// Characters of the replied text, kept in [`ReplyPreview`]
+pub const REPLY_PREVIEW_LENGTH: usize = 100;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Photo,
+    Video,
+    /// Any file, which isn't a photo or a video
+    Document,
+}
+
+/// Summary of the replies to a message
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ThreadInfo {
+    pub reply_count: u32,
+    pub last_reply_id: i32,
+    pub last_reply_from: i32,
+    pub last_reply_date: i64,
+    /// Replies after the read cursor of the user, who fetched the message, not sent by them
+    pub unread_count: u32,
+}
+
+/// Origin of a forwarded message
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ForwardedFrom {
+    /// The original sender
+    pub user_id: i32,
+    /// The original group. None if message was forwarded from a private chat
+    pub chat_id: Option<i32>,
+}
+
+/// Previous version of an edited message
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MessageRevision {
+    /// When this version was sent or edited
+    pub date: i64,
+    /// When this version was replaced
+    pub edited_at: i64,
+    pub content: Option<String>,
+    pub sha256_hashes: Option<Vec<String>>,
+    pub reply_to: Option<i32>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ReactionCount {
+    pub reaction: String,
+    pub count: u32,
+}
+
+/// Message waiting to be sent at `schedule_at`
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ScheduledMessage {
+    pub scheduled_id: i32,
+    /// Target chat, as it was given in `to`
+    pub chat_id: i32,
+    /// Unix timestamp
+    pub schedule_at: i64,
     pub reply_to: Option<i32>,
     pub content: Option<String>,
     pub sha256_hashes: Option<Vec<String>>,
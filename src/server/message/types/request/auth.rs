@@ -21,3 +21,10 @@ pub struct RegisterRequest {
     pub username: String,
     pub password: String
 }
+
+/// Second login step, if `login` responded with `need_2fa`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TwoFactorRequest {
+    pub method: String, // login_2fa
+    pub code: String // TOTP or recovery code
+}
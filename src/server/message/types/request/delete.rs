@@ -4,7 +4,9 @@ use serde::{Deserialize, Serialize};
 pub struct DeleteAllMessagesRequest {
     pub method: String, // delete
     pub what: String, // all_messages
-    pub chat_id: i32
+    pub chat_id: i32,
+    /// Clear the chat for every participant, otherwise only for yourself
+    pub for_everyone: Option<bool>
 }
 
 #[derive(Serialize, Deserialize)]
@@ -19,5 +21,31 @@ pub struct DeleteMessagesRequest {
     pub method: String, // delete
     pub what: String, // messages
     pub chat_id: i32,
-    pub message_ids: Vec<i32>
+    pub message_ids: Vec<i32>,
+    /// Delete the messages for every participant, otherwise only for yourself
+    pub for_everyone: Option<bool>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteSelfRequest {
+    pub method: String, // delete
+    pub what: String, // self
+    pub password: String,
+    /// Also delete messages sent in groups. Private chats are always deleted entirely
+    pub delete_messages: Option<bool>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteScheduledRequest {
+    pub method: String, // delete
+    pub what: String, // scheduled
+    pub scheduled_id: i32
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteInvitationLinkRequest {
+    pub method: String, // delete
+    pub what: String, // invitation_link
+    pub chat_id: i32,
+    pub link: String
 }
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::server::message::types::chat::{Permissions, Role};
+
 #[derive(Serialize, Deserialize)]
 pub struct EditSelfRequest {
     pub method: String,
@@ -9,6 +11,14 @@ pub struct EditSelfRequest {
     pub photo: Option<String>,
     pub profile_color: Option<u32>,
     pub password: Option<String>,
+    /// Required to change `password` or `username`
+    pub current_password: Option<String>,
+    /// Generates new TOTP secret, which must then be confirmed by `confirm_2fa`
+    pub enable_2fa: Option<bool>,
+    /// The 6-digit code from the authenticator app
+    pub confirm_2fa: Option<String>,
+    /// TOTP or recovery code
+    pub disable_2fa: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -38,3 +48,76 @@ pub struct EditMessageRequest {
     pub content: Option<String>,
     pub sha256_hashes: Option<Vec<String>>,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct EditReactionRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32,
+    pub message_id: i32,
+    /// Removes the reaction if null
+    pub reaction: Option<String>,
+}
+
+/// Used by both `pin` and `unpin`
+#[derive(Serialize, Deserialize)]
+pub struct PinMessageRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32,
+    pub message_id: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EditScheduledRequest {
+    pub method: String,
+    pub what: String,
+    pub scheduled_id: i32,
+    pub schedule_at: Option<i64>,
+    pub content: Option<String>,
+    pub sha256_hashes: Option<Vec<String>>,
+}
+
+/// Default expiry timer of new messages in the chat
+#[derive(Serialize, Deserialize)]
+pub struct EditAutoDeleteRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32,
+    /// Disables the timer if null
+    pub ttl_seconds: Option<u32>,
+}
+
+/// Promotes a participant of a group to admin or demotes back to member
+#[derive(Serialize, Deserialize)]
+pub struct EditRoleRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32,
+    pub user_id: i32,
+    pub role: Role,
+    /// Permissions of the admin, every permission if null
+    pub permissions: Option<Permissions>,
+}
+
+/// Permissions of the participants, who aren't admins
+#[derive(Serialize, Deserialize)]
+pub struct EditPermissionsRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32,
+    pub permissions: Permissions,
+}
+
+/// Empty `avatar_hash` or `tag` removes them
+#[derive(Serialize, Deserialize)]
+pub struct EditGroupRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32,
+    pub name: Option<String>,
+    pub avatar_hash: Option<String>,
+    pub tag: Option<String>,
+    /// Public groups require a tag
+    pub is_public: Option<bool>,
+}
@@ -26,7 +26,55 @@ pub struct FetchMessagesRequest {
     pub method: String,
     pub what: String,
     pub chat_id: i32,
-    pub range: [i32; 2]
+    /// Legacy `[start, end]` form, takes precedence over the cursor form
+    pub range: Option<[i32; 2]>,
+    /// Anchor message, the cursor returned by the previous fetch
+    pub message_id: Option<i32>,
+    /// Anchor unix timestamp, used if `message_id` isn't provided.
+    /// Without both the page starts from the latest message
+    /// (or the oldest one for `after`)
+    pub date: Option<i64>,
+    pub direction: Option<PageDirection>,
+    pub limit: Option<u32>
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PageDirection {
+    /// Messages older than the anchor
+    #[default]
+    Before,
+    /// Messages newer than the anchor
+    After,
+    /// The anchor itself and messages on both sides of it
+    Around
+}
+
+/// Full-text search over messages
+#[derive(Deserialize, Serialize)]
+pub struct FetchSearchRequest {
+    pub method: String,
+    pub what: String,
+    pub query: String,
+    /// Searches in all chats of the user if not provided
+    pub chat_id: Option<i32>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchPinnedRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchReadReceiptsRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32,
+    pub message_id: i32
 }
 
 #[derive(Deserialize, Serialize)]
@@ -43,3 +91,63 @@ pub struct FetchUsersRequest {
     pub what: String, // users
     pub query: String // @pep or pep or whatever
 }
+
+/// Fetches public groups by part of the tag or name
+#[derive(Deserialize, Serialize)]
+pub struct FetchGroupsRequest {
+    pub method: String, // fetch
+    pub what: String, // groups
+    pub query: String // @pep or pep or whatever
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchScheduledRequest {
+    pub method: String,
+    pub what: String,
+    /// All scheduled messages of the user if not provided
+    pub chat_id: Option<i32>
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchMessageHistoryRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32,
+    pub message_id: i32
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchRolesRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32
+}
+
+/// Used by `invitation_links` and `join_requests`, which require the `invite` permission
+#[derive(Deserialize, Serialize)]
+pub struct FetchGroupInvitesRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32
+}
+
+/// View counters of channel messages
+#[derive(Deserialize, Serialize)]
+pub struct FetchViewsRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32,
+    pub message_ids: Vec<i32>
+}
+
+/// Replies to the message, oldest first
+#[derive(Deserialize, Serialize)]
+pub struct FetchThreadRequest {
+    pub method: String,
+    pub what: String,
+    pub chat_id: i32,
+    pub message_id: i32,
+    /// Id of the last fetched reply, replies after it are returned
+    pub after: Option<i32>,
+    pub limit: Option<u32>
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct LeaveGroupRequest {
+    pub method: String, // leave
+    pub chat_id: i32
+}
+
+/// Used by `kick`, `ban`, `unban`, `approve` and `decline`
+#[derive(Serialize, Deserialize)]
+pub struct ParticipantRequest {
+    pub method: String,
+    pub chat_id: i32,
+    pub user_id: i32
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AddParticipantsRequest {
+    pub method: String, // add
+    pub what: String, // participants
+    pub chat_id: i32,
+    pub user_ids: Vec<i32>
+}
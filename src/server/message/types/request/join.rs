@@ -3,5 +3,5 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize)]
 pub struct JoinGroupRequest {
     pub method: String, // join
-    pub link: String // +Fnvlksdfjgnv
+    pub link: String // +Fnvlksdfjgnv or @public_group
 }
\ No newline at end of file
@@ -23,3 +23,4 @@ pub fn extract_what_field(message: &str) -> PPResult<String> {
 
     Ok(o.what)
 }
+pub mod group;
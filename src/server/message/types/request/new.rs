@@ -3,15 +3,27 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NewGroupRequest {
     pub method: String, // new
-    pub what: String, // group
+    pub what: String, // group or channel
     pub name: String, // SomeName123
     pub avatar_hash: Option<String>,
     pub username: Option<String>,
+    /// Public groups require `username`
+    pub is_public: Option<bool>,
+    /// Users to add to the group right away, besides the creator
+    pub participants: Option<Vec<i32>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NewInvitationLinkRequest {
     pub method: String, // new
     pub what: String, // invitation_link
-    pub chat_id: i32
+    pub chat_id: i32,
+    /// Shown to admins, to tell links apart
+    pub name: Option<String>,
+    /// Unix timestamp, the link never expires if not provided
+    pub expires_at: Option<i64>,
+    /// Unlimited if not provided
+    pub max_uses: Option<u32>,
+    /// Users, who join by the link, must be approved by an admin first
+    pub requires_approval: Option<bool>
 }
\ No newline at end of file
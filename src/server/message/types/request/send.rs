@@ -19,6 +19,20 @@ pub struct SendMessageRequest {
     #[serde(flatten)]
     pub common: CommonFields,
     pub content: MessageContent,
+    /// Unix timestamp, the message is stored as scheduled until then
+    pub schedule_at: Option<i64>,
+    /// Deletes the message after this many seconds, overrides the chat's default
+    pub ttl_seconds: Option<u32>,
 }
 
 pub type MessageId = i32;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForwardMessagesRequest {
+    pub method: String,
+    /// Chat, where the messages are taken from
+    pub from_chat_id: i32,
+    pub message_ids: Vec<MessageId>,
+    /// Target chat
+    pub to: i32,
+}
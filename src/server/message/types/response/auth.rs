@@ -12,4 +12,11 @@ pub struct RegisterResponse {
     pub method: String,
     pub user_id: i32,
     pub session_id: String
-}
\ No newline at end of file
+}
+/// Password is correct, but `login_2fa` with the code must follow
+#[derive(Serialize, Deserialize)]
+pub struct NeedTwoFactorResponse {
+    pub ok: bool,
+    pub method: String, // login
+    pub need_2fa: bool
+}
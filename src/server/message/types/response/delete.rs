@@ -4,7 +4,8 @@ use serde::{Deserialize, Serialize};
 pub struct DeleteAllMessagesResponse {
     pub ok: bool,
     pub method: String, // delete_all_messages
-    pub chat_id: i32
+    pub chat_id: i32,
+    pub for_everyone: bool
 }
 #[derive(Serialize, Deserialize)]
 pub struct DeleteChatResponse {
@@ -17,5 +18,24 @@ pub struct DeleteMessagesResponse {
     pub ok: bool,
     pub method: String, // delete_messages
     pub chat_id: i32,
-    pub message_ids: Vec<i32>
+    pub message_ids: Vec<i32>,
+    pub for_everyone: bool
+}
+#[derive(Serialize, Deserialize)]
+pub struct DeleteSelfResponse {
+    pub ok: bool,
+    pub method: String, // delete_self
+}
+#[derive(Serialize, Deserialize)]
+pub struct DeleteScheduledResponse {
+    pub ok: bool,
+    pub method: String, // delete_scheduled
+    pub scheduled_id: i32
+}
+#[derive(Serialize, Deserialize)]
+pub struct DeleteInvitationLinkResponse {
+    pub ok: bool,
+    pub method: String, // delete_invitation_link
+    pub chat_id: i32,
+    pub link: String
 }
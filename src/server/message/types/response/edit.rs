@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+use crate::server::message::types::{
+    chat::{ChatDetails, Permissions, Role},
+    message::ScheduledMessage,
+};
+
 #[derive(Serialize, Deserialize)]
 pub struct EditMessageResponse {
     pub ok: bool,
@@ -13,8 +18,76 @@ pub struct MarkAsReadResponse {
     pub chat_id: i32
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct EditReactionResponse {
+    pub ok: bool,
+    pub method: String, // edit_reaction
+    pub chat_id: i32,
+    pub message_id: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PinMessageResponse {
+    pub ok: bool,
+    pub method: String, // edit_pin or edit_unpin
+    pub chat_id: i32,
+    pub message_id: i32,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct EditDraftResponse {
     pub ok: bool,
     pub method: String,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct EditSelfResponse {
+    pub ok: bool,
+    pub method: String, // edit_self
+    /// Base32 TOTP secret, if `enable_2fa` was requested
+    pub totp_secret: Option<String>,
+    /// `otpauth://` uri for the authenticator apps, if `enable_2fa` was requested
+    pub totp_uri: Option<String>,
+    /// Shown only once, after `confirm_2fa`
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EditScheduledResponse {
+    pub ok: bool,
+    pub method: String, // edit_scheduled
+    pub scheduled_message: ScheduledMessage,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EditAutoDeleteResponse {
+    pub ok: bool,
+    pub method: String, // edit_auto_delete
+    pub chat_id: i32,
+    pub ttl_seconds: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EditRoleResponse {
+    pub ok: bool,
+    pub method: String, // edit_role
+    pub chat_id: i32,
+    pub user_id: i32,
+    pub role: Role,
+    pub permissions: Permissions,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EditPermissionsResponse {
+    pub ok: bool,
+    pub method: String, // edit_permissions
+    pub chat_id: i32,
+    pub permissions: Permissions,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EditGroupResponse {
+    pub ok: bool,
+    pub method: String, // edit_group
+    pub chat: ChatDetails,
+}
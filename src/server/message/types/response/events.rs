@@ -1,12 +1,12 @@
 use serde::{Deserialize, Serialize};
 
 use crate::server::message::types::{
-    chat::{ChatDetails, ChatDetailsResponse},
+    chat::{ChatDetails, ChatDetailsResponse, JoinRequest, Permissions, Role},
     message::Message,
     user::User,
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NewChatEvent {
     pub event: String,
     pub new_chat: ChatDetailsResponse,
@@ -28,10 +28,31 @@ pub struct EditMessageEvent {
 pub struct MarkAsReadEvent {
     pub event: String, // mark_as_read
     pub chat_id: i32,
-    pub message_ids: Vec<i32>
+    pub message_ids: Vec<i32>,
+    /// Who has read the messages, matters in groups
+    pub user_id: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReactionEvent {
+    pub event: String, // reaction
+    pub chat_id: i32,
+    pub message_id: i32,
+    pub user_id: i32,
+    /// None if the reaction was removed
+    pub reaction: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PinMessageEvent {
+    pub event: String, // pin_message
+    pub chat_id: i32,
+    pub message_id: i32,
+    /// False if the message was unpinned
+    pub is_pinned: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EditSelfEvent {
     pub event: String,
     pub new_profile: User,
@@ -43,21 +64,30 @@ pub struct DeleteAllMessagesEvent {
     pub chat_id: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DeleteMessagesEvent {
     pub event: String,
     pub chat_id: i32,
     pub message_ids: Vec<i32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Default expiry timer of the chat was changed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AutoDeleteEvent {
+    pub event: String, // auto_delete
+    pub chat_id: i32,
+    /// None if the timer was disabled
+    pub ttl_seconds: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DeleteMessageEvent {
     pub event: String, // delete_message
     pub chat_id: i32,
     pub message_id: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NewParticipantEvent {
     pub event: String,
     pub chat_id: i32,
@@ -71,3 +101,70 @@ pub struct IsTypingEvent {
     pub chat_id: i32,
     pub user_id: i32,
 }
+
+/// Sent to every peer of the deleted account
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccountDeletedEvent {
+    pub event: String, // account_deleted
+    /// Relative to the receiver: user_id of the deleted account for private chats, group id for groups
+    pub chat_id: i32,
+    pub user_id: i32,
+}
+
+/// Participant of a group was promoted or demoted
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoleEvent {
+    pub event: String, // role
+    pub chat_id: i32,
+    pub user_id: i32,
+    pub role: Role,
+    pub permissions: Permissions,
+}
+
+/// Permissions of the group members were changed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PermissionsEvent {
+    pub event: String, // permissions
+    pub chat_id: i32,
+    pub permissions: Permissions,
+}
+
+/// Sent to the rest of the group, when a participant leaves it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ParticipantLeftEvent {
+    pub event: String, // participant_left
+    pub chat_id: i32,
+    pub user_id: i32,
+}
+
+/// Sent to the rest of the group and to the removed participant
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ParticipantRemovedEvent {
+    pub event: String, // participant_removed
+    pub chat_id: i32,
+    pub user_id: i32,
+    /// Whether the participant can't join again
+    pub banned: bool,
+}
+
+/// Name, avatar or tag of the group was changed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GroupUpdatedEvent {
+    pub event: String, // group_updated
+    pub chat: ChatDetails,
+}
+
+/// Sent to participants, who may invite users, when someone asks to join
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JoinRequestEvent {
+    pub event: String, // join_request
+    pub request: JoinRequest,
+    pub user: User,
+}
+
+/// Sent to the user, whose join request was declined
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JoinRequestDeclinedEvent {
+    pub event: String, // join_request_declined
+    pub chat_id: i32,
+}
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportResponse {
+    pub ok: bool,
+    pub method: String,
+    /// Hash of the archive, which can be downloaded through the files server
+    pub sha256_hash: String,
+}
@@ -1,7 +1,9 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::server::message::types::{
-    chat::ChatDetailsResponse, message::Message, user::User
+    chat::{ChatDetails, ChatDetailsResponse, GroupAdmin, Invitation, JoinRequest, Permissions}, message::{Message, MessageRevision, ScheduledMessage}, user::User
 };
 
 #[derive(Serialize, Deserialize)]
@@ -39,6 +41,77 @@ pub struct FetchMessagesResponse {
     pub ok: bool,
     pub method: String,
     pub messages: Vec<Message>,
+    /// Absent for the legacy `range` form
+    pub cursor: Option<MessagesCursor>,
+    /// Whether there are more messages in the requested direction
+    pub has_more: Option<bool>,
+}
+
+/// Anchors to continue from, pass one as `message_id` with the matching direction
+///
+/// A side is `None` when there is nothing more there
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MessagesCursor {
+    pub before: Option<i32>,
+    pub after: Option<i32>,
+}
+
+/// Found messages, the most relevant first
+#[derive(Serialize, Deserialize)]
+pub struct FetchSearchResponse {
+    pub ok: bool,
+    pub method: String,
+    pub messages: Vec<Message>,
+}
+
+/// Pinned messages, the latest pinned first
+#[derive(Serialize, Deserialize)]
+pub struct FetchPinnedResponse {
+    pub ok: bool,
+    pub method: String,
+    pub messages: Vec<Message>,
+}
+
+/// Scheduled messages, the earliest first
+#[derive(Serialize, Deserialize)]
+pub struct FetchScheduledResponse {
+    pub ok: bool,
+    pub method: String,
+    pub messages: Vec<ScheduledMessage>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FetchReadReceiptsResponse {
+    pub ok: bool,
+    pub method: String,
+    pub chat_id: i32,
+    pub message_id: i32,
+    /// Users, who have read the message, except its sender
+    pub read_by: Vec<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FetchMessageHistoryResponse {
+    pub ok: bool,
+    pub method: String,
+    pub chat_id: i32,
+    pub message_id: i32,
+    /// Previous versions of the message, the oldest first
+    pub revisions: Vec<MessageRevision>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FetchRolesResponse {
+    pub ok: bool,
+    pub method: String,
+    pub chat_id: i32,
+    /// Groups, created before owners were stored, have no owner
+    pub owner_id: Option<i32>,
+    pub admins: Vec<GroupAdmin>,
+    /// Permissions of everyone else
+    pub member_permissions: Permissions,
+    /// Users, who can't join the group
+    pub banned: Vec<i32>,
 }
 
 /// Response on fetching users by search query
@@ -48,3 +121,50 @@ pub struct FetchUsersResponse {
     pub method: String,
     pub users: Vec<User>,
 }
+
+/// Response on fetching public groups by search query
+#[derive(Deserialize, Serialize)]
+pub struct FetchGroupsResponse {
+    pub ok: bool,
+    pub method: String,
+    pub groups: Vec<ChatDetails>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchInvitationLinksResponse {
+    pub ok: bool,
+    pub method: String,
+    pub chat_id: i32,
+    pub invitations: Vec<Invitation>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchJoinRequestsResponse {
+    pub ok: bool,
+    pub method: String,
+    pub chat_id: i32,
+    /// The oldest first
+    pub requests: Vec<JoinRequest>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchViewsResponse {
+    pub ok: bool,
+    pub method: String,
+    pub chat_id: i32,
+    /// Messages, which weren't viewed yet, are missing
+    pub views: HashMap<i32, u64>,
+    pub subscribers: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FetchThreadResponse {
+    pub ok: bool,
+    pub method: String,
+    pub chat_id: i32,
+    /// The message, replies were fetched to
+    pub message: Message,
+    /// The oldest first
+    pub replies: Vec<Message>,
+    pub has_more: bool,
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct LeaveGroupResponse {
+    pub ok: bool,
+    pub method: String, // leave
+    pub chat_id: i32
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ParticipantResponse {
+    pub ok: bool,
+    pub method: String, // kick, ban, unban, approve or decline
+    pub chat_id: i32,
+    pub user_id: i32
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AddParticipantsResponse {
+    pub ok: bool,
+    pub method: String, // add_participants
+    pub chat_id: i32,
+    /// Users, who weren't participants yet
+    pub user_ids: Vec<i32>
+}
@@ -9,6 +9,14 @@ pub struct JoinGroupResponse {
     pub chat: ChatDetailsResponse
 }
 
+/// The group is joined once an admin approves the request
+#[derive(Serialize, Deserialize)]
+pub struct JoinRequestResponse {
+    pub ok: bool, // true
+    pub method: String, // join_request
+    pub chat: ChatDetails
+}
+
 /// Workaround
 ///
 /// Not to send error, but rather to show that invitation link isn't valid
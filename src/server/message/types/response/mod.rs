@@ -7,4 +7,6 @@ pub mod auth;
 pub mod edit;
 pub mod delete;
 pub mod join;
-pub mod new;
\ No newline at end of file
+pub mod new;
+pub mod export;
+pub mod group;
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::server::message::types::chat::{ChatDetails, ChatDetailsResponse};
+use crate::server::message::types::chat::{ChatDetails, ChatDetailsResponse, Invitation};
 
 #[derive(Serialize, Deserialize)]
 pub struct NewGroupResponse {
@@ -13,5 +13,6 @@ pub struct NewGroupResponse {
 pub struct NewInvitationLinkResponse {
     pub ok: bool, // true
     pub method: String, // new_invitation_link
-    pub link: String // +SDJvnd
+    pub link: String, // +SDJvnd
+    pub invitation: Invitation
 }
@@ -1,5 +1,7 @@
 use serde::{Serialize, Deserialize};
 
+use crate::server::message::types::message::ScheduledMessage;
+
 #[derive(Serialize, Deserialize)]
 pub struct SendMessageResponse {
     pub ok: bool,
@@ -13,4 +15,21 @@ pub struct UploadFileResponse {
     pub ok: bool,
     pub method: String,
     pub sha256_hash: String
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ForwardMessagesResponse {
+    pub ok: bool,
+    pub method: String,
+    pub chat_id: i32,
+    /// Ids of the new messages in the target chat
+    pub message_ids: Vec<i32>,
+}
+
+/// Response on `send_message` with `schedule_at`
+#[derive(Serialize, Deserialize)]
+pub struct ScheduleMessageResponse {
+    pub ok: bool,
+    pub method: String,
+    pub scheduled_message: ScheduledMessage,
+}
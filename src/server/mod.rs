@@ -2,3 +2,5 @@ pub mod server;
 pub mod session;
 pub mod message;
 pub mod connection;
+pub mod rate_limiter;
+pub mod scheduler;
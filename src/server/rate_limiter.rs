@@ -0,0 +1,285 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use log::warn;
+
+use crate::db::internal::error::{PPError, PPResult};
+
+/// The first lockout, doubled on each further failure
+const BASE_LOCKOUT: Duration = Duration::from_secs(30);
+/// 1 hour
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+
+/// One failure is forgiven per this long, so old failures of an IP don't add up forever
+const FAILURE_DECAY: Duration = Duration::from_secs(10 * 60);
+
+/// Entries that weren't touched for this long are purged
+const STALE_AFTER: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// Limits of the [`RateLimiter`]
+///
+/// Read from the environment, every variable falls back to the default:
+/// * `RATE_LIMIT_ENABLED` - `false` disables throttling completely
+/// * `RATE_LIMIT_EXEMPT_LOOPBACK` - `true` doesn't apply per-IP limits to the loopback,
+///   e.g. the integration tests. Accounts are still throttled
+/// * `RATE_LIMIT_IP_CAPACITY`, `RATE_LIMIT_IP_REFILL_PER_SEC`
+/// * `RATE_LIMIT_ACCOUNT_CAPACITY`, `RATE_LIMIT_ACCOUNT_REFILL_PER_SEC`
+/// * `RATE_LIMIT_IP_FAILURES`, `RATE_LIMIT_ACCOUNT_FAILURES`
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub exempt_loopback: bool,
+    /// How many auth requests a single IP may burst
+    pub ip_capacity: f64,
+    /// Tokens regained by an IP per second
+    pub ip_refill_per_sec: f64,
+    /// How many auth requests may target a single account in a burst
+    pub account_capacity: f64,
+    /// Tokens regained by an account per second
+    pub account_refill_per_sec: f64,
+    /// Consecutive failures from an IP before it gets locked
+    pub ip_failures_threshold: u32,
+    /// Consecutive failures on an account before it gets locked
+    pub account_failures_threshold: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            exempt_loopback: false,
+            ip_capacity: 30.0,
+            ip_refill_per_sec: 0.5,
+            account_capacity: 10.0,
+            account_refill_per_sec: 0.1,
+            ip_failures_threshold: 20,
+            account_failures_threshold: 5,
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!("Invalid value of {}: {:?}, using the default", name, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+impl RateLimitConfig {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            enabled: env_or("RATE_LIMIT_ENABLED", default.enabled),
+            exempt_loopback: env_or("RATE_LIMIT_EXEMPT_LOOPBACK", default.exempt_loopback),
+            ip_capacity: env_or("RATE_LIMIT_IP_CAPACITY", default.ip_capacity),
+            ip_refill_per_sec: env_or("RATE_LIMIT_IP_REFILL_PER_SEC", default.ip_refill_per_sec),
+            account_capacity: env_or("RATE_LIMIT_ACCOUNT_CAPACITY", default.account_capacity),
+            account_refill_per_sec: env_or(
+                "RATE_LIMIT_ACCOUNT_REFILL_PER_SEC",
+                default.account_refill_per_sec,
+            ),
+            ip_failures_threshold: env_or("RATE_LIMIT_IP_FAILURES", default.ip_failures_threshold),
+            account_failures_threshold: env_or(
+                "RATE_LIMIT_ACCOUNT_FAILURES",
+                default.account_failures_threshold,
+            ),
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64) -> Self {
+        Self {
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes one token. If bucket is empty, returns the time until the next token
+    fn try_take(&mut self, capacity: f64, refill_per_sec: f64) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_per_sec).min(capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / refill_per_sec,
+        ))
+    }
+}
+
+#[derive(Default)]
+struct FailureState {
+    failures: u32,
+    locked_until: Option<Instant>,
+    last_failure: Option<Instant>,
+}
+
+impl FailureState {
+    fn remaining_lockout(&self) -> Option<Duration> {
+        self.locked_until
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Exponential lockout: `BASE_LOCKOUT * 2^(failures - threshold)`, capped at `MAX_LOCKOUT`
+    fn register_failure(&mut self, threshold: u32) {
+        let now = Instant::now();
+        if let Some(last_failure) = self.last_failure {
+            let forgiven = now.duration_since(last_failure).as_secs() / FAILURE_DECAY.as_secs();
+            self.failures = self
+                .failures
+                .saturating_sub(u32::try_from(forgiven).unwrap_or(u32::MAX));
+        }
+        self.failures += 1;
+        self.last_failure = Some(now);
+
+        if self.failures >= threshold {
+            let exponent = (self.failures - threshold).min(16);
+            let lockout = BASE_LOCKOUT.saturating_mul(1 << exponent).min(MAX_LOCKOUT);
+            self.locked_until = Some(now + lockout);
+        }
+    }
+}
+
+/// Throttles authentication attempts per IP and per account
+///
+/// Every attempt consumes a token from both IP and account buckets,
+/// failed attempts are additionally counted and lock the IP/account out
+/// with exponentially growing duration.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    ip_buckets: DashMap<IpAddr, TokenBucket>,
+    account_buckets: DashMap<String, TokenBucket>,
+    ip_failures: DashMap<IpAddr, FailureState>,
+    account_failures: DashMap<String, FailureState>,
+}
+
+fn rate_limited(retry_after: Duration) -> PPError {
+    // Round up, so client doesn't retry a moment too early
+    PPError::RateLimited(retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0))
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            ip_buckets: DashMap::new(),
+            account_buckets: DashMap::new(),
+            ip_failures: DashMap::new(),
+            account_failures: DashMap::new(),
+        }
+    }
+
+    fn is_ip_exempt(&self, ip: IpAddr) -> bool {
+        self.config.exempt_loopback && ip.is_loopback()
+    }
+
+    /// Must be called before processing an auth attempt
+    ///
+    /// `account` is the username, or the user_id in case of `auth`
+    pub fn check(&self, ip: IpAddr, account: Option<&str>) -> PPResult<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let ip_exempt = self.is_ip_exempt(ip);
+        if let Some(remaining) = self
+            .ip_failures
+            .get(&ip)
+            .filter(|_| !ip_exempt)
+            .and_then(|state| state.remaining_lockout())
+        {
+            return Err(rate_limited(remaining));
+        }
+
+        if let Some(account) = account {
+            if let Some(remaining) = self
+                .account_failures
+                .get(account)
+                .and_then(|state| state.remaining_lockout())
+            {
+                return Err(rate_limited(remaining));
+            }
+        }
+
+        let config = &self.config;
+        if !ip_exempt {
+            self.ip_buckets
+                .entry(ip)
+                .or_insert_with(|| TokenBucket::new(config.ip_capacity))
+                .try_take(config.ip_capacity, config.ip_refill_per_sec)
+                .map_err(rate_limited)?;
+        }
+
+        if let Some(account) = account {
+            self.account_buckets
+                .entry(account.to_owned())
+                .or_insert_with(|| TokenBucket::new(config.account_capacity))
+                .try_take(config.account_capacity, config.account_refill_per_sec)
+                .map_err(rate_limited)?;
+        }
+
+        Ok(())
+    }
+
+    /// Counts a failed attempt, possibly locking the IP or the account out
+    pub fn register_failure(&self, ip: IpAddr, account: Option<&str>) {
+        if !self.config.enabled {
+            return;
+        }
+
+        if !self.is_ip_exempt(ip) {
+            self.ip_failures
+                .entry(ip)
+                .or_default()
+                .register_failure(self.config.ip_failures_threshold);
+        }
+
+        if let Some(account) = account {
+            self.account_failures
+                .entry(account.to_owned())
+                .or_default()
+                .register_failure(self.config.account_failures_threshold);
+        }
+    }
+
+    /// Successful attempt resets the failures of the account
+    ///
+    /// Failures of the IP only decay with time, otherwise logging into an own account
+    /// would let the IP keep guessing passwords of the others
+    pub fn register_success(&self, account: Option<&str>) {
+        if let Some(account) = account {
+            self.account_failures.remove(account);
+        }
+    }
+
+    /// Removes entries that weren't used for a long time, so the maps don't grow forever
+    pub fn purge_stale(&self) {
+        let now = Instant::now();
+        let is_fresh = |last: Instant| now.duration_since(last) < STALE_AFTER;
+
+        self.ip_buckets.retain(|_, b| is_fresh(b.last_refill));
+        self.account_buckets.retain(|_, b| is_fresh(b.last_refill));
+        self.ip_failures
+            .retain(|_, f| f.remaining_lockout().is_some() || f.last_failure.is_some_and(is_fresh));
+        self.account_failures
+            .retain(|_, f| f.remaining_lockout().is_some() || f.last_failure.is_some_and(is_fresh));
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, error};
+
+use crate::{
+    db::{
+        chat::{
+            chats::ChatsDB,
+            messages::{expiry_minute, MessagesDB},
+            scheduled::ScheduledMessagesDB,
+        },
+        internal::error::PPResult,
+    },
+    server::message::{
+        delivery::Delivery,
+        methods::send::deliver_message,
+        types::{
+            chat::ChatId,
+            request::send::MessageId,
+            response::events::{DeleteMessagesEvent, NewMessageEvent},
+        },
+    },
+};
+
+/// How often due scheduled and expired messages are looked up
+pub const SCHEDULER_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// Delivers every scheduled message, whose time has come
+pub async fn deliver_due_messages(delivery: &Delivery) -> PPResult<()> {
+    let scheduled_db: ScheduledMessagesDB = delivery.get_db();
+    let now = now();
+
+    for (from_id, scheduled) in scheduled_db.fetch_due(now).await? {
+        if !scheduled_db
+            .claim(from_id, scheduled.scheduled_id, now)
+            .await?
+        {
+            continue;
+        }
+        debug!(
+            "Delivering scheduled message {} of {}",
+            scheduled.scheduled_id, from_id
+        );
+
+        // Target may be gone meanwhile, the message is dropped then
+        let (_, mut message) =
+            match deliver_message(delivery, &from_id.into(), &scheduled.to_send_request()).await {
+                Ok(delivered) => delivered,
+                Err(err) => {
+                    error!(
+                        "Failed to deliver scheduled message {} of {}: {}",
+                        scheduled.scheduled_id, from_id, err
+                    );
+                    continue;
+                }
+            };
+
+        // The sender wasn't the one who sent the request, so notify them too
+        message.chat_id = scheduled.chat_id;
+        delivery.send_event_to_con_detached(
+            from_id,
+            NewMessageEvent {
+                event: "new_message".into(),
+                new_message: message,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+/// Deletes expired messages and sends `delete_message` event to the participants
+///
+/// `next_minute` is the first expiry minute, that wasn't swept yet. It's looked up
+/// on the first call and advanced only after the due messages were deleted
+pub async fn delete_expired_messages(
+    delivery: &Delivery,
+    next_minute: &mut Option<i64>,
+) -> PPResult<()> {
+    let messages_db: MessagesDB = delivery.get_db();
+    let chats_db: ChatsDB = delivery.get_db();
+    let now = now();
+
+    let from_minute = match *next_minute {
+        Some(minute) => minute,
+        None => messages_db
+            .fetch_oldest_expiry_minute()
+            .await?
+            .unwrap_or(expiry_minute(now)),
+    };
+
+    let mut expired: BTreeMap<ChatId, Vec<MessageId>> = BTreeMap::new();
+    for (chat_id, message_id) in messages_db.fetch_expired(from_minute, now).await? {
+        expired.entry(chat_id).or_default().push(message_id);
+    }
+
+    for (chat_id, message_ids) in expired {
+        debug!("Deleting {} expired messages in {}", message_ids.len(), chat_id);
+        messages_db.delete_messages(chat_id, &message_ids).await?;
+        if chat_id.is_negative() && chats_db.is_channel(chat_id).await? {
+            delivery.send_event_to_subscribers(
+                chat_id,
+                None,
+                DeleteMessagesEvent {
+                    event: "delete_message".into(),
+                    chat_id,
+                    message_ids,
+                },
+            );
+            continue;
+        }
+
+        let participants = chats_db.fetch_participants(chat_id).await?.unwrap_or_default();
+        let receivers: Vec<_> = participants
+            .iter()
+            .map(|&user_id| {
+                // Private chats are seen by the id of the other participant
+                let public_chat_id = if chat_id.is_negative() {
+                    chat_id
+                } else {
+                    participants
+                        .iter()
+                        .copied()
+                        .find(|&other| other != user_id)
+                        .unwrap_or(user_id)
+                };
+
+                (
+                    user_id,
+                    DeleteMessagesEvent {
+                        event: "delete_message".into(),
+                        chat_id: public_chat_id,
+                        message_ids: message_ids.clone(),
+                    },
+                )
+            })
+            .collect();
+        delivery.send_events_to_connections(receivers);
+    }
+
+    // The current minute may still have messages, that expire later
+    *next_minute = Some(from_minute.max(expiry_minute(now)));
+
+    Ok(())
+}
@@ -2,7 +2,10 @@ use dashmap::DashMap;
 use log::debug;
 use log::error;
 use log::info;
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Weak},
+};
 use tokio::io::AsyncReadExt;
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
@@ -12,9 +15,13 @@ use tokio::sync::RwLock;
 use crate::db::bucket::DatabaseBucket;
 use crate::db::bucket::DatabasePool;
 use crate::db::internal::error::PPResult;
+use crate::fs::search;
 use crate::server::connection::TCPConnection;
+use crate::server::message::delivery::Delivery;
 use crate::server::message::handlers::files_handler::FilesHandler;
 use crate::server::message::Handler;
+use crate::server::rate_limiter::{RateLimitConfig, RateLimiter};
+use crate::server::scheduler::{self, SCHEDULER_POLL_INTERVAL};
 use crate::server::{message::handlers::json_handler::JsonHandler, session::Session};
 
 use super::message::handlers::json_handler::SessionArcRwLock;
@@ -25,8 +32,16 @@ const JSON_MESSAGE_ALLOCATION_SIZE: usize = 1024;
 /// 1 Mib
 pub const FILES_MESSAGE_ALLOCATION_SIZE: usize = 1024 * 1024;
 
+/// 10 minutes
+const RATE_LIMITER_PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
 pub(super) type Sessions = Arc<DashMap<i32, SessionArcRwLock>>;
 
+/// Every authenticated connection of each user, while [`Sessions`] keeps only the latest one
+///
+/// Weak, so closed connections aren't kept alive
+pub(super) type LiveSessions = Arc<DashMap<i32, Vec<Weak<RwLock<Session>>>>>;
+
 /// Two ports are available:
 /// 3000 - For Json Messages. The full message is stored in a `Vec`(on RAM) and handled after they are completely received
 /// 8080 - For Files Messages. The file message consists of metadata and the binary itself. After the metadata is sended, goes
@@ -35,7 +50,9 @@ pub struct Server {
     json_listener: TcpListener,
     file_listener: TcpListener,
     connections: Sessions,
+    live_sessions: LiveSessions,
     pool: DatabasePool,
+    rate_limiter: Arc<RateLimiter>,
 }
 
 impl Server {
@@ -50,7 +67,9 @@ impl Server {
             json_listener,
             file_listener,
             connections: Arc::new(DashMap::new()),
+            live_sessions: Arc::new(DashMap::new()),
             pool: DatabasePool::new().await,
+            rate_limiter: Arc::new(RateLimiter::new(RateLimitConfig::from_env())),
         })
     }
 
@@ -59,13 +78,22 @@ impl Server {
         socket: TcpStream,
         addr: SocketAddr,
         sessions: Sessions,
+        live_sessions: LiveSessions,
         bucket: DatabaseBucket,
+        rate_limiter: Arc<RateLimiter>,
     ) {
         debug!("[JSON] Connection established: {}", addr);
         let session = Arc::new(RwLock::new(Session::new(socket)));
 
-        let mut handler =
-            JsonHandler::new(Arc::clone(&session), Arc::clone(&sessions), bucket).await;
+        let mut handler = JsonHandler::new(
+            Arc::clone(&session),
+            Arc::clone(&sessions),
+            live_sessions,
+            bucket,
+            addr,
+            rate_limiter,
+        )
+        .await;
 
         let reader = handler.reader();
         let mut buffer = Box::new([0; JSON_MESSAGE_ALLOCATION_SIZE]);
@@ -126,10 +154,53 @@ impl Server {
                     self.json_listener,
                     Arc::clone(&pool),
                     Arc::clone(&self.connections),
+                    Arc::clone(&self.live_sessions),
+                    Arc::clone(&self.rate_limiter),
                 )
                 .await;
             });
 
+            scope.spawn(async {
+                let mut interval = tokio::time::interval(RATE_LIMITER_PURGE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    self.rate_limiter.purge_stale();
+                }
+            });
+
+            scope.spawn(async {
+                let mut interval = tokio::time::interval(search::COMMIT_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let result = async { search::message_index()?.commit().await }.await;
+                    if let Err(err) = result {
+                        error!("Error while committing the search index: {}", err);
+                    }
+                }
+            });
+
+            scope.spawn(async {
+                let bucket = pool.lock().await.get_available_bucket().await;
+                let delivery = Delivery::new(
+                    bucket,
+                    Arc::clone(&self.connections),
+                    Arc::clone(&self.live_sessions),
+                );
+                let mut interval = tokio::time::interval(SCHEDULER_POLL_INTERVAL);
+                let mut next_expiry_minute = None;
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = scheduler::deliver_due_messages(&delivery).await {
+                        error!("Error while delivering scheduled messages: {}", err);
+                    }
+                    if let Err(err) =
+                        scheduler::delete_expired_messages(&delivery, &mut next_expiry_minute).await
+                    {
+                        error!("Error while deleting expired messages: {}", err);
+                    }
+                }
+            });
+
             scope.spawn(async {
                 Self::poll_files_events(
                     self.file_listener,
@@ -146,6 +217,8 @@ impl Server {
         listener: TcpListener,
         pool: Arc<Mutex<DatabasePool>>,
         connections: Sessions,
+        live_sessions: LiveSessions,
+        rate_limiter: Arc<RateLimiter>,
     ) {
         moro::async_scope!(|scope| {
             loop {
@@ -160,7 +233,9 @@ impl Server {
                             socket,
                             addr,
                             Arc::clone(&connections),
+                            Arc::clone(&live_sessions),
                             available_bucket,
+                            Arc::clone(&rate_limiter),
                         ));
                     }
                     Err(err) => {
@@ -1,4 +1,8 @@
-use std::{fmt::Display, sync::Arc};
+use std::{
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use log::debug;
 use serde::Serialize;
@@ -9,6 +13,9 @@ use tokio::net::TcpStream;
 
 use super::{connection::TCPConnection, message::types::{request::auth::*, user::UserId}};
 
+/// How long the second login step may be completed after the password was verified
+const PENDING_TWO_FACTOR_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
 /// component for authenticated the `Session`
 pub struct AuthComponent {
     session_id: String,
@@ -26,9 +33,23 @@ impl AuthComponent {
         }
     }
 
-    pub async fn from_login(db: UsersDB, req: LoginRequest) -> PPResult<Self> {
+    /// Credentials are produced only if the user doesn't have two-factor authentication,
+    /// otherwise `from_two_factor` must follow
+    pub async fn from_login(db: UsersDB, req: LoginRequest) -> PPResult<LoginStep> {
         match db.login(&req.username, &req.password).await {
-            Ok((user_id, session_id)) => Ok(Self{
+            Ok((user_id, Some(session_id))) => Ok(LoginStep::Done(Self{
+                session_id,
+                user_id
+            })),
+            Ok((user_id, None)) => Ok(LoginStep::NeedTwoFactor(user_id)),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Second login step. `user_id` is the one, whose password was already verified
+    pub async fn from_two_factor(db: UsersDB, user_id: i32, req: TwoFactorRequest) -> PPResult<Self> {
+        match db.login_second_factor(user_id, &req.code).await {
+            Ok(session_id) => Ok(Self{
                 session_id,
                 user_id
             }),
@@ -51,11 +72,20 @@ impl AuthComponent {
     }
 }
 
+/// Result of the first login step
+pub enum LoginStep {
+    Done(AuthComponent),
+    /// Password is correct, but the account requires the second factor
+    NeedTwoFactor(i32),
+}
+
 #[derive(Debug)]
 pub struct Session {
     session_id: Option<String>,
     user_id: Option<i32>,
-    connections: Vec<Arc<TCPConnection>>
+    connections: Vec<Arc<TCPConnection>>,
+    /// user_id and the time, the password was verified at, while waiting for the second factor
+    pending_two_factor: Option<(i32, Instant)>
 }
 
 impl Session {
@@ -65,7 +95,8 @@ impl Session {
         Session {
             session_id: None,
             user_id: None,
-            connections: vec![Arc::new(main_connection)]
+            connections: vec![Arc::new(main_connection)],
+            pending_two_factor: None
         }
     }
 
@@ -77,6 +108,24 @@ impl Session {
         let (s_id, u_id) = auth_component.get_credentials();
         self.session_id = Some(s_id);
         self.user_id = Some(u_id);
+        self.pending_two_factor = None;
+    }
+
+    /// e.g. if the session was invalidated by the password change
+    pub fn deauthenticate(&mut self) {
+        self.session_id = None;
+        self.user_id = None;
+    }
+
+    pub fn set_pending_two_factor(&mut self, user_id: i32) {
+        self.pending_two_factor = Some((user_id, Instant::now()));
+    }
+
+    /// user_id that waits for the second login step, if it didn't time out
+    pub fn pending_two_factor(&self) -> Option<i32> {
+        self.pending_two_factor
+            .filter(|(_, since)| since.elapsed() < PENDING_TWO_FACTOR_TIMEOUT)
+            .map(|(user_id, _)| user_id)
     }
 
     pub fn session_id(&self) -> Option<&String> {
@@ -155,3 +155,334 @@ async fn auth() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn login_lockout() -> Result<(), Box<dyn Error>> {
+    let mut con = TestConnection::new("3000").await?;
+    let username = generate_random_string(8);
+
+    con.send_message(&json!({
+        "method": "register",
+        "username": format!("@{}", username),
+        "name": "I am gay",
+        "password": "asd"
+    })).await?;
+    ok(con.receive_response().await?)?;
+    drop(con);
+
+    let mut con = TestConnection::new("3000").await?;
+    for _ in 0..5 {
+        con.send_message(&json!({
+            "method": "login",
+            "username": format!("@{}", username),
+            "password": "wrong"
+        })).await?;
+        nok(con.receive_response().await?)?;
+    }
+
+    // Even the right password is rejected while locked out
+    con.send_message(&json!({
+        "method": "login",
+        "username": format!("@{}", username),
+        "password": "asd"
+    })).await?;
+    let response = con.receive_response().await?;
+    println!("{}", response);
+    let val = serde_json::from_str::<Value>(&response)?;
+    assert!(val.get("retry_after").unwrap().as_u64().unwrap() > 0);
+    nok(response)?;
+
+    // Other spellings of the username are locked out too
+    con.send_message(&json!({
+        "method": "login",
+        "username": username.to_uppercase(),
+        "password": "asd"
+    })).await?;
+    let response = con.receive_response().await?;
+    let val = serde_json::from_str::<Value>(&response)?;
+    assert!(val.get("retry_after").unwrap().as_u64().unwrap() > 0);
+    nok(response)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn login_two_factor() -> Result<(), Box<dyn Error>> {
+    let mut con = TestConnection::new("3000").await?;
+    let username = generate_random_string(8);
+
+    con.send_message(&json!({
+        "method": "register",
+        "username": format!("@{}", username),
+        "name": "I am gay",
+        "password": "asd"
+    })).await?;
+    ok(con.receive_response().await?)?;
+
+    con.send_message(&json!({
+        "method": "edit",
+        "what": "self",
+        "enable_2fa": true
+    })).await?;
+    let response = con.receive_response().await?;
+    println!("{}", response);
+    let val = serde_json::from_str::<Value>(&response)?;
+    let secret = val.get("totp_secret").unwrap().as_str().unwrap().to_owned();
+    ok(response)?;
+
+    let totp = totp_rs::TOTP::new(
+        totp_rs::Algorithm::SHA1,
+        6,
+        1,
+        30,
+        totp_rs::Secret::Encoded(secret).to_bytes().unwrap(),
+    )?;
+
+    con.send_message(&json!({
+        "method": "edit",
+        "what": "self",
+        "confirm_2fa": totp.generate_current()?
+    })).await?;
+    let response = con.receive_response().await?;
+    println!("{}", response);
+    let val = serde_json::from_str::<Value>(&response)?;
+    assert!(!val.get("recovery_codes").unwrap().as_array().unwrap().is_empty());
+    ok(response)?;
+    drop(con);
+
+    let mut con = TestConnection::new("3000").await?;
+    con.send_message(&json!({
+        "method": "login",
+        "username": format!("@{}", username),
+        "password": "asd"
+    })).await?;
+    let response = con.receive_response().await?;
+    println!("{}", response);
+    let val = serde_json::from_str::<Value>(&response)?;
+    assert!(val.get("need_2fa").unwrap().as_bool().unwrap());
+    assert!(val.get("session_id").is_none());
+    ok(response)?;
+
+    con.send_message(&json!({
+        "method": "login_2fa",
+        "code": "000000x"
+    })).await?;
+    nok(con.receive_response().await?)?;
+
+    // The current code was spent on confirming, the next one is accepted as a clock drift
+    let next_code = totp.generate(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() + 30);
+    con.send_message(&json!({
+        "method": "login_2fa",
+        "code": next_code
+    })).await?;
+    let response = con.receive_response().await?;
+    println!("{}", response);
+    let val = serde_json::from_str::<Value>(&response)?;
+    assert!(val.get("session_id").is_some());
+    ok(response)?;
+    drop(con);
+
+    // Used code can't be replayed
+    let mut con = TestConnection::new("3000").await?;
+    con.send_message(&json!({
+        "method": "login",
+        "username": format!("@{}", username),
+        "password": "asd"
+    })).await?;
+    ok(con.receive_response().await?)?;
+    con.send_message(&json!({
+        "method": "login_2fa",
+        "code": next_code
+    })).await?;
+    nok(con.receive_response().await?)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn edit_credentials() -> Result<(), Box<dyn Error>> {
+    let taken = generate_random_string(8);
+    let mut con = TestConnection::new("3000").await?;
+    con.send_message(&json!({
+        "method": "register",
+        "username": format!("@{}", taken),
+        "name": "I am gay",
+        "password": "asd"
+    })).await?;
+    ok(con.receive_response().await?)?;
+    drop(con);
+
+    let username = generate_random_string(8);
+    let mut con = TestConnection::new("3000").await?;
+    con.send_message(&json!({
+        "method": "register",
+        "username": format!("@{}", username),
+        "name": "I am gay",
+        "password": "asd"
+    })).await?;
+    ok(con.receive_response().await?)?;
+
+    // No current password
+    con.send_message(&json!({
+        "method": "edit",
+        "what": "self",
+        "password": "new"
+    })).await?;
+    nok(con.receive_response().await?)?;
+
+    // Wrong current password
+    con.send_message(&json!({
+        "method": "edit",
+        "what": "self",
+        "password": "new",
+        "current_password": "wrong"
+    })).await?;
+    nok(con.receive_response().await?)?;
+
+    // Already taken username
+    con.send_message(&json!({
+        "method": "edit",
+        "what": "self",
+        "username": format!("@{}", taken),
+        "current_password": "asd"
+    })).await?;
+    nok(con.receive_response().await?)?;
+
+    // Username requires the password too
+    let username = generate_random_string(8);
+    con.send_message(&json!({
+        "method": "edit",
+        "what": "self",
+        "username": format!("@{}", username)
+    })).await?;
+    nok(con.receive_response().await?)?;
+
+    // Nothing is changed, if any field is invalid
+    con.send_message(&json!({
+        "method": "edit",
+        "what": "self",
+        "username": format!("@{}", username),
+        "photo": "missing",
+        "current_password": "asd"
+    })).await?;
+    nok(con.receive_response().await?)?;
+
+    let mut other = TestConnection::new("3000").await?;
+    other.send_message(&json!({
+        "method": "login",
+        "username": format!("@{}", username),
+        "password": "asd"
+    })).await?;
+    nok(other.receive_response().await?)?;
+
+    con.send_message(&json!({
+        "method": "edit",
+        "what": "self",
+        "username": format!("@{}", username),
+        "current_password": "asd"
+    })).await?;
+    ok(con.receive_response().await?)?;
+
+    let mut other = TestConnection::new("3000").await?;
+    other.send_message(&json!({
+        "method": "login",
+        "username": format!("@{}", username),
+        "password": "asd"
+    })).await?;
+    ok(other.receive_response().await?)?;
+
+    con.send_message(&json!({
+        "method": "edit",
+        "what": "self",
+        "password": "new",
+        "current_password": "asd"
+    })).await?;
+    ok(con.receive_response().await?)?;
+    drop(con);
+
+    // Other connections are logged out
+    other.send_message(&json!({
+        "method": "fetch",
+        "what": "chats"
+    })).await?;
+    nok(other.receive_response().await?)?;
+
+    let mut con = TestConnection::new("3000").await?;
+    con.send_message(&json!({
+        "method": "login",
+        "username": format!("@{}", username),
+        "password": "new"
+    })).await?;
+    ok(con.receive_response().await?)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn delete_self() -> Result<(), Box<dyn Error>> {
+    let username = generate_random_string(8);
+    let mut con = TestConnection::new("3000").await?;
+    con.send_message(&json!({
+        "method": "register",
+        "username": format!("@{}", username),
+        "name": "I am gay",
+        "password": "asd"
+    })).await?;
+    ok(con.receive_response().await?)?;
+
+    con.send_message(&json!({
+        "method": "export"
+    })).await?;
+    let response = con.receive_response().await?;
+    println!("{}", response);
+    let value: Value = serde_json::from_str(&response)?;
+    let export_hash = value.get("sha256_hash").and_then(|v| v.as_str()).unwrap().to_owned();
+
+    let mut other = TestConnection::new("3000").await?;
+    other.send_message(&json!({
+        "method": "login",
+        "username": format!("@{}", username),
+        "password": "asd"
+    })).await?;
+    ok(other.receive_response().await?)?;
+
+    con.send_message(&json!({
+        "method": "delete",
+        "what": "self",
+        "password": "wrong"
+    })).await?;
+    nok(con.receive_response().await?)?;
+
+    con.send_message(&json!({
+        "method": "delete",
+        "what": "self",
+        "password": "asd"
+    })).await?;
+    ok(con.receive_response().await?)?;
+    drop(con);
+
+    // Other connections are logged out
+    other.send_message(&json!({
+        "method": "fetch",
+        "what": "chats"
+    })).await?;
+    nok(other.receive_response().await?)?;
+
+    // The export is removed with the account
+    let mut files = TestConnection::new("8080").await?;
+    files.send_message(&json!({
+        "method": "download_metadata",
+        "sha256_hash": export_hash
+    })).await?;
+    nok(files.receive_response().await?)?;
+
+    let mut con = TestConnection::new("3000").await?;
+    con.send_message(&json!({
+        "method": "login",
+        "username": format!("@{}", username),
+        "password": "asd"
+    })).await?;
+    nok(con.receive_response().await?)?;
+
+    Ok(())
+}
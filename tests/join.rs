@@ -1,6 +1,6 @@
 use std::error::Error;
 
-use common::{generate_random_string, ok, TestConnection};
+use common::{generate_random_string, nok, ok, TestConnection};
 use serde_json::{json, Value};
 
 mod common;
@@ -76,3 +76,1040 @@ async fn join() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn roles() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    }))
+    .await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "new",
+        "what": "group",
+        "name": "TestGroup"
+    }))
+    .await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let chat_id = v.get("chat").unwrap().get("chat_id").unwrap().as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "new",
+        "what": "invitation_link",
+        "chat_id": chat_id
+    }))
+    .await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let link = v.get("link").unwrap().as_str().unwrap().to_owned();
+
+    let mut m = TestConnection::new("3000").await?;
+    m.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    }))
+    .await?;
+    let r = m.receive_response().await?;
+    ok(r.clone())?;
+    let member_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    m.send_message(&json!({
+        "method": "join",
+        "link": link
+    }))
+    .await?;
+    ok(m.receive_response().await?)?;
+    // new_participant event
+    c.receive_response().await?;
+
+    // Members can't invite by default
+    m.send_message(&json!({
+        "method": "new",
+        "what": "invitation_link",
+        "chat_id": chat_id
+    }))
+    .await?;
+    nok(m.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "permissions",
+        "chat_id": chat_id,
+        "permissions": {
+            "send_messages": false
+        }
+    }))
+    .await?;
+    ok(c.receive_response().await?)?;
+    let r = m.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("permissions"));
+
+    m.send_message(&json!({
+        "method": "send_message",
+        "to": chat_id,
+        "content": {
+            "text": "Muted"
+        }
+    }))
+    .await?;
+    nok(m.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "role",
+        "chat_id": chat_id,
+        "user_id": member_id,
+        "role": "admin",
+        "permissions": {
+            "send_messages": true,
+            "invite": true
+        }
+    }))
+    .await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    ok(r)?;
+    let r = m.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("role"));
+    assert_eq!(v.get("role").unwrap().as_str(), Some("admin"));
+
+    m.send_message(&json!({
+        "method": "new",
+        "what": "invitation_link",
+        "chat_id": chat_id
+    }))
+    .await?;
+    ok(m.receive_response().await?)?;
+
+    // Admins can't change roles
+    m.send_message(&json!({
+        "method": "edit",
+        "what": "role",
+        "chat_id": chat_id,
+        "user_id": member_id,
+        "role": "member"
+    }))
+    .await?;
+    nok(m.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "roles",
+        "chat_id": chat_id
+    }))
+    .await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let v: Value = serde_json::from_str(&r)?;
+    let admins = v.get("admins").unwrap().as_array().unwrap();
+    assert_eq!(admins.len(), 1);
+    assert_eq!(admins[0].get("user_id").unwrap().as_i64(), Some(member_id));
+    assert_eq!(
+        v.get("member_permissions").unwrap().get("send_messages").unwrap().as_bool(),
+        Some(false)
+    );
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "role",
+        "chat_id": chat_id,
+        "user_id": member_id,
+        "role": "member"
+    }))
+    .await?;
+    ok(c.receive_response().await?)?;
+    m.receive_response().await?;
+
+    m.send_message(&json!({
+        "method": "new",
+        "what": "invitation_link",
+        "chat_id": chat_id
+    }))
+    .await?;
+    nok(m.receive_response().await?)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn owner_deletes_account() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    }))
+    .await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "new",
+        "what": "group",
+        "name": "TestGroup"
+    }))
+    .await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let chat_id = v.get("chat").unwrap().get("chat_id").unwrap().as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "new",
+        "what": "invitation_link",
+        "chat_id": chat_id
+    }))
+    .await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let link = v.get("link").unwrap().as_str().unwrap().to_owned();
+
+    let mut m = TestConnection::new("3000").await?;
+    m.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    }))
+    .await?;
+    let r = m.receive_response().await?;
+    ok(r.clone())?;
+    let member_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    m.send_message(&json!({
+        "method": "join",
+        "link": link
+    }))
+    .await?;
+    ok(m.receive_response().await?)?;
+    // new_participant event
+    c.receive_response().await?;
+
+    c.send_message(&json!({
+        "method": "delete",
+        "what": "self",
+        "password": "pwd"
+    }))
+    .await?;
+    ok(c.receive_response().await?)?;
+
+    let r = m.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("account_deleted"));
+    // The only participant left becomes the owner
+    let r = m.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("role"));
+    assert_eq!(v.get("user_id").unwrap().as_i64(), Some(member_id));
+    assert_eq!(v.get("role").unwrap().as_str(), Some("owner"));
+
+    m.send_message(&json!({
+        "method": "fetch",
+        "what": "roles",
+        "chat_id": chat_id
+    }))
+    .await?;
+    let r = m.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("owner_id").unwrap().as_i64(), Some(member_id));
+
+    m.send_message(&json!({
+        "method": "edit",
+        "what": "permissions",
+        "chat_id": chat_id,
+        "permissions": {
+            "send_messages": false
+        }
+    }))
+    .await?;
+    ok(m.receive_response().await?)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn leave_kick_ban() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    }))
+    .await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "new",
+        "what": "group",
+        "name": "TestGroup"
+    }))
+    .await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let chat_id = v.get("chat").unwrap().get("chat_id").unwrap().as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "new",
+        "what": "invitation_link",
+        "chat_id": chat_id
+    }))
+    .await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let link = v.get("link").unwrap().as_str().unwrap().to_owned();
+
+    let mut m = TestConnection::new("3000").await?;
+    m.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    }))
+    .await?;
+    let r = m.receive_response().await?;
+    ok(r.clone())?;
+    let member_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    m.send_message(&json!({
+        "method": "join",
+        "link": link
+    }))
+    .await?;
+    ok(m.receive_response().await?)?;
+    // new_participant event
+    c.receive_response().await?;
+
+    // The owner can't leave
+    c.send_message(&json!({
+        "method": "leave",
+        "chat_id": chat_id
+    }))
+    .await?;
+    nok(c.receive_response().await?)?;
+
+    m.send_message(&json!({
+        "method": "leave",
+        "chat_id": chat_id
+    }))
+    .await?;
+    ok(m.receive_response().await?)?;
+    let r = c.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("participant_left"));
+    assert_eq!(v.get("user_id").unwrap().as_i64(), Some(member_id));
+
+    m.send_message(&json!({
+        "method": "join",
+        "link": link
+    }))
+    .await?;
+    ok(m.receive_response().await?)?;
+    c.receive_response().await?;
+
+    c.send_message(&json!({
+        "method": "ban",
+        "chat_id": chat_id,
+        "user_id": member_id
+    }))
+    .await?;
+    ok(c.receive_response().await?)?;
+    let r = m.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("participant_removed"));
+    assert_eq!(v.get("banned").unwrap().as_bool(), Some(true));
+
+    // The group is gone from the chats of the removed participant
+    m.send_message(&json!({
+        "method": "fetch",
+        "what": "chats"
+    }))
+    .await?;
+    let r = m.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert!(v.get("chats").unwrap().as_array().unwrap().is_empty());
+
+    m.send_message(&json!({
+        "method": "join",
+        "link": link
+    }))
+    .await?;
+    nok(m.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "unban",
+        "chat_id": chat_id,
+        "user_id": member_id
+    }))
+    .await?;
+    ok(c.receive_response().await?)?;
+
+    m.send_message(&json!({
+        "method": "join",
+        "link": link
+    }))
+    .await?;
+    ok(m.receive_response().await?)?;
+    c.receive_response().await?;
+
+    c.send_message(&json!({
+        "method": "kick",
+        "chat_id": chat_id,
+        "user_id": member_id
+    }))
+    .await?;
+    ok(c.receive_response().await?)?;
+    let r = m.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("participant_removed"));
+
+    // Removed participant can't read the group anymore
+    m.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": chat_id
+    }))
+    .await?;
+    nok(m.receive_response().await?)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn add_participants() -> Result<(), Box<dyn Error>> {
+    let mut users = vec![];
+    let mut user_ids = vec![];
+    for _ in 0..3 {
+        let mut c = TestConnection::new("3000").await?;
+        c.send_message(&json!({
+            "method": "register",
+            "name": "a",
+            "username": format!("@{}", generate_random_string(10)),
+            "password": "pwd"
+        }))
+        .await?;
+        let r = c.receive_response().await?;
+        ok(r.clone())?;
+        user_ids.push(serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap());
+        users.push(c);
+    }
+    let [owner, first, second] = &mut users[..] else {
+        unreachable!()
+    };
+
+    owner
+        .send_message(&json!({
+            "method": "new",
+            "what": "group",
+            "name": "TestGroup",
+            "participants": [user_ids[1]]
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let chat_id = v.get("chat").unwrap().get("chat_id").unwrap().as_i64().unwrap();
+
+    let r = first.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("new_chat"));
+    assert_eq!(v.get("new_chat").unwrap().get("chat_id").unwrap().as_i64(), Some(chat_id));
+
+    // Members can't invite by default
+    first
+        .send_message(&json!({
+            "method": "add",
+            "what": "participants",
+            "chat_id": chat_id,
+            "user_ids": [user_ids[2]]
+        }))
+        .await?;
+    nok(first.receive_response().await?)?;
+
+    owner
+        .send_message(&json!({
+            "method": "add",
+            "what": "participants",
+            "chat_id": chat_id,
+            "user_ids": [user_ids[1], user_ids[2]]
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    println!("{}", r);
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("user_ids").unwrap(), &json!([user_ids[2]]));
+
+    let r = second.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("new_chat"));
+
+    let r = first.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("new_participant"));
+    assert_eq!(v.get("new_user").unwrap().get("user_id").unwrap().as_i64(), Some(user_ids[2]));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn edit_group() -> Result<(), Box<dyn Error>> {
+    let mut users = vec![];
+    let mut user_ids = vec![];
+    for _ in 0..2 {
+        let mut c = TestConnection::new("3000").await?;
+        c.send_message(&json!({
+            "method": "register",
+            "name": "a",
+            "username": format!("@{}", generate_random_string(10)),
+            "password": "pwd"
+        }))
+        .await?;
+        let r = c.receive_response().await?;
+        ok(r.clone())?;
+        user_ids.push(serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap());
+        users.push(c);
+    }
+    let [owner, member] = &mut users[..] else {
+        unreachable!()
+    };
+
+    owner
+        .send_message(&json!({
+            "method": "new",
+            "what": "group",
+            "name": "TestGroup",
+            "participants": [user_ids[1]]
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let chat_id = v.get("chat").unwrap().get("chat_id").unwrap().as_i64().unwrap();
+    member.receive_response().await?;
+
+    let tag = format!("@{}", generate_random_string(10));
+    owner
+        .send_message(&json!({
+            "method": "edit",
+            "what": "group",
+            "chat_id": chat_id,
+            "name": "Renamed",
+            "tag": tag
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("chat").unwrap().get("name").unwrap().as_str(), Some("Renamed"));
+
+    let r = member.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("group_updated"));
+    assert_eq!(v.get("chat").unwrap().get("chat_id").unwrap().as_i64(), Some(chat_id));
+    assert_eq!(v.get("chat").unwrap().get("tag").unwrap().as_str(), Some(tag.as_str()));
+
+    // Members can't edit info by default
+    member
+        .send_message(&json!({
+            "method": "edit",
+            "what": "group",
+            "chat_id": chat_id,
+            "name": "Nope"
+        }))
+        .await?;
+    nok(member.receive_response().await?)?;
+
+    // Tags are validated like usernames
+    owner
+        .send_message(&json!({
+            "method": "edit",
+            "what": "group",
+            "chat_id": chat_id,
+            "tag": "éabc"
+        }))
+        .await?;
+    nok(owner.receive_response().await?)?;
+
+    // Avatar must be uploaded
+    owner
+        .send_message(&json!({
+            "method": "edit",
+            "what": "group",
+            "chat_id": chat_id,
+            "avatar_hash": "0".repeat(64)
+        }))
+        .await?;
+    nok(owner.receive_response().await?)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn public_groups() -> Result<(), Box<dyn Error>> {
+    let mut users = vec![];
+    for _ in 0..2 {
+        let mut c = TestConnection::new("3000").await?;
+        c.send_message(&json!({
+            "method": "register",
+            "name": "a",
+            "username": format!("@{}", generate_random_string(10)),
+            "password": "pwd"
+        }))
+        .await?;
+        ok(c.receive_response().await?)?;
+        users.push(c);
+    }
+    let [owner, other] = &mut users[..] else {
+        unreachable!()
+    };
+
+    // Public groups must have a tag
+    owner
+        .send_message(&json!({
+            "method": "new",
+            "what": "group",
+            "name": "TestGroup",
+            "is_public": true
+        }))
+        .await?;
+    nok(owner.receive_response().await?)?;
+
+    let tag = format!("@{}", generate_random_string(10));
+    owner
+        .send_message(&json!({
+            "method": "new",
+            "what": "group",
+            "name": "TestGroup",
+            "username": tag,
+            "is_public": true
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let chat_id = v.get("chat").unwrap().get("chat_id").unwrap().as_i64().unwrap();
+    assert_eq!(v.get("chat").unwrap().get("is_public").unwrap().as_bool(), Some(true));
+
+    // Tags are unique
+    owner
+        .send_message(&json!({
+            "method": "new",
+            "what": "group",
+            "name": "TestGroup",
+            "username": tag
+        }))
+        .await?;
+    nok(owner.receive_response().await?)?;
+
+    other
+        .send_message(&json!({
+            "method": "fetch",
+            "what": "groups",
+            "query": tag
+        }))
+        .await?;
+    let r = other.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let groups = v.get("groups").unwrap().as_array().unwrap();
+    assert!(groups.iter().any(|g| g.get("chat_id").unwrap().as_i64() == Some(chat_id)));
+
+    // Part of the tag is enough
+    other
+        .send_message(&json!({
+            "method": "fetch",
+            "what": "groups",
+            "query": format!("@{}", &tag[3..8])
+        }))
+        .await?;
+    let r = other.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    let groups = v.get("groups").unwrap().as_array().unwrap();
+    assert!(groups.iter().any(|g| g.get("chat_id").unwrap().as_i64() == Some(chat_id)));
+
+    other
+        .send_message(&json!({
+            "method": "fetch",
+            "what": "groups",
+            "query": "@"
+        }))
+        .await?;
+    let r = other.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert!(v.get("groups").unwrap().as_array().unwrap().is_empty());
+
+    other
+        .send_message(&json!({
+            "method": "join",
+            "link": tag
+        }))
+        .await?;
+    let r = other.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("chat").unwrap().get("chat_id").unwrap().as_i64(), Some(chat_id));
+
+    // Private groups can't be joined by the tag
+    let private_tag = format!("@{}", generate_random_string(10));
+    owner.receive_response().await?; // new_participant
+    owner
+        .send_message(&json!({
+            "method": "new",
+            "what": "group",
+            "name": "TestGroup",
+            "username": private_tag
+        }))
+        .await?;
+    ok(owner.receive_response().await?)?;
+
+    other
+        .send_message(&json!({
+            "method": "join",
+            "link": private_tag
+        }))
+        .await?;
+    let r = other.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("code").unwrap().as_u64(), Some(404));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invitation_links() -> Result<(), Box<dyn Error>> {
+    let mut users = vec![];
+    let mut user_ids = vec![];
+    for _ in 0..3 {
+        let mut c = TestConnection::new("3000").await?;
+        c.send_message(&json!({
+            "method": "register",
+            "name": "a",
+            "username": format!("@{}", generate_random_string(10)),
+            "password": "pwd"
+        }))
+        .await?;
+        let r = c.receive_response().await?;
+        ok(r.clone())?;
+        user_ids.push(serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap());
+        users.push(c);
+    }
+    let [owner, first, second] = &mut users[..] else {
+        unreachable!()
+    };
+
+    owner
+        .send_message(&json!({
+            "method": "new",
+            "what": "group",
+            "name": "TestGroup"
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let chat_id = v.get("chat").unwrap().get("chat_id").unwrap().as_i64().unwrap();
+
+    owner
+        .send_message(&json!({
+            "method": "new",
+            "what": "invitation_link",
+            "chat_id": chat_id,
+            "max_uses": 1
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let single_use = v.get("link").unwrap().as_str().unwrap().to_owned();
+
+    first
+        .send_message(&json!({
+            "method": "join",
+            "link": single_use
+        }))
+        .await?;
+    ok(first.receive_response().await?)?;
+    owner.receive_response().await?; // new_participant
+
+    // The link is used up
+    second
+        .send_message(&json!({
+            "method": "join",
+            "link": single_use
+        }))
+        .await?;
+    let r = second.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("code").unwrap().as_u64(), Some(404));
+
+    owner
+        .send_message(&json!({
+            "method": "new",
+            "what": "invitation_link",
+            "chat_id": chat_id,
+            "name": "Approval",
+            "requires_approval": true
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let approval = v.get("link").unwrap().as_str().unwrap().to_owned();
+
+    second
+        .send_message(&json!({
+            "method": "join",
+            "link": approval
+        }))
+        .await?;
+    let r = second.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("method").unwrap().as_str(), Some("join_request"));
+
+    let r = owner.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("join_request"));
+    assert_eq!(v.get("user").unwrap().get("user_id").unwrap().as_i64(), Some(user_ids[2]));
+
+    owner
+        .send_message(&json!({
+            "method": "approve",
+            "chat_id": chat_id,
+            "user_id": user_ids[2]
+        }))
+        .await?;
+    ok(owner.receive_response().await?)?;
+
+    let r = second.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("event").unwrap().as_str(), Some("new_chat"));
+
+    owner
+        .send_message(&json!({
+            "method": "fetch",
+            "what": "invitation_links",
+            "chat_id": chat_id
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("invitations").unwrap().as_array().unwrap().len(), 2);
+
+    owner
+        .send_message(&json!({
+            "method": "delete",
+            "what": "invitation_link",
+            "chat_id": chat_id,
+            "link": approval
+        }))
+        .await?;
+    ok(owner.receive_response().await?)?;
+
+    owner
+        .send_message(&json!({
+            "method": "fetch",
+            "what": "invitation_links",
+            "chat_id": chat_id
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("invitations").unwrap().as_array().unwrap().len(), 1);
+
+    // Members can't see the links
+    first
+        .send_message(&json!({
+            "method": "fetch",
+            "what": "invitation_links",
+            "chat_id": chat_id
+        }))
+        .await?;
+    nok(first.receive_response().await?)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn channels() -> Result<(), Box<dyn Error>> {
+    let mut users = vec![];
+    for _ in 0..3 {
+        let mut c = TestConnection::new("3000").await?;
+        c.send_message(&json!({
+            "method": "register",
+            "name": "a",
+            "username": format!("@{}", generate_random_string(10)),
+            "password": "pwd"
+        }))
+        .await?;
+        ok(c.receive_response().await?)?;
+        users.push(c);
+    }
+    let [owner, first, second] = &mut users[..] else {
+        unreachable!()
+    };
+
+    let tag = format!("@{}", generate_random_string(10));
+    owner
+        .send_message(&json!({
+            "method": "new",
+            "what": "channel",
+            "name": "TestChannel",
+            "username": tag,
+            "is_public": true
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(v.get("method").unwrap().as_str(), Some("new_channel"));
+    let chat_id = v.get("chat").unwrap().get("chat_id").unwrap().as_i64().unwrap();
+    assert_eq!(v.get("chat").unwrap().get("is_channel").unwrap().as_bool(), Some(true));
+
+    for subscriber in [&mut *first, &mut *second] {
+        subscriber
+            .send_message(&json!({
+                "method": "join",
+                "link": tag
+            }))
+            .await?;
+        ok(subscriber.receive_response().await?)?;
+    }
+
+    // Only admins post
+    first
+        .send_message(&json!({
+            "method": "send_message",
+            "to": chat_id,
+            "content": {
+                "text": "Subscriber"
+            }
+        }))
+        .await?;
+    nok(first.receive_response().await?)?;
+
+    // Permissions of members don't apply to subscribers
+    owner
+        .send_message(&json!({
+            "method": "edit",
+            "what": "permissions",
+            "chat_id": chat_id,
+            "permissions": {
+                "send_messages": true,
+                "invite": true
+            }
+        }))
+        .await?;
+    ok(owner.receive_response().await?)?;
+    first
+        .send_message(&json!({
+            "method": "new",
+            "what": "invitation_link",
+            "chat_id": chat_id
+        }))
+        .await?;
+    nok(first.receive_response().await?)?;
+
+    owner
+        .send_message(&json!({
+            "method": "send_message",
+            "to": chat_id,
+            "content": {
+                "text": "Announcement"
+            }
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    let message_id = v.get("message_id").unwrap().as_i64().unwrap();
+
+    for subscriber in [&mut *first, &mut *second] {
+        let r = subscriber.receive_response().await?;
+        let v: Value = serde_json::from_str(&r)?;
+        assert_eq!(v.get("event").unwrap().as_str(), Some("new_message"));
+    }
+
+    // Reading twice counts a single view, messages that don't exist aren't viewed
+    for _ in 0..2 {
+        first
+            .send_message(&json!({
+                "method": "edit",
+                "what": "is_unread",
+                "chat_id": chat_id,
+                "message_ids": [message_id, message_id + 1]
+            }))
+            .await?;
+        ok(first.receive_response().await?)?;
+    }
+
+    owner
+        .send_message(&json!({
+            "method": "fetch",
+            "what": "views",
+            "chat_id": chat_id,
+            "message_ids": [message_id, message_id + 1]
+        }))
+        .await?;
+    let r = owner.receive_response().await?;
+    ok(r.clone())?;
+    let v: Value = serde_json::from_str(&r)?;
+    assert_eq!(
+        v.get("views").unwrap().get(message_id.to_string()).unwrap().as_u64(),
+        Some(1)
+    );
+    assert!(v.get("views").unwrap().get((message_id + 1).to_string()).is_none());
+    assert_eq!(v.get("subscribers").unwrap().as_u64(), Some(3));
+
+    // Reactions reach every subscriber
+    second
+        .send_message(&json!({
+            "method": "edit",
+            "what": "reaction",
+            "chat_id": chat_id,
+            "message_id": message_id,
+            "reaction": "👍"
+        }))
+        .await?;
+    ok(second.receive_response().await?)?;
+    for subscriber in [&mut *owner, &mut *first] {
+        let r = subscriber.receive_response().await?;
+        let v: Value = serde_json::from_str(&r)?;
+        assert_eq!(v.get("event").unwrap().as_str(), Some("reaction"));
+    }
+
+    // Subscribers learn about the deleted account of the staff
+    owner
+        .send_message(&json!({
+            "method": "delete",
+            "what": "self",
+            "password": "pwd"
+        }))
+        .await?;
+    ok(owner.receive_response().await?)?;
+    for subscriber in [&mut *first, &mut *second] {
+        let r = subscriber.receive_response().await?;
+        let v: Value = serde_json::from_str(&r)?;
+        assert_eq!(v.get("event").unwrap().as_str(), Some("account_deleted"));
+        assert_eq!(v.get("chat_id").unwrap().as_i64(), Some(chat_id));
+    }
+
+    Ok(())
+}
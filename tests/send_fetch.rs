@@ -1,6 +1,6 @@
 use std::error::Error;
 
-use common::{generate_random_string, ok, TestConnection};
+use common::{generate_random_string, nok, ok, TestConnection};
 use serde_json::{json, Value};
 
 mod common;
@@ -95,3 +95,1385 @@ async fn download_file() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn concurrent_send() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let sender = format!("@{}", generate_random_string(10));
+    let mut first = TestConnection::new("3000").await?;
+    first.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": sender,
+        "password": "pwd"
+    })).await?;
+    ok(first.receive_response().await?)?;
+
+    let mut second = TestConnection::new("3000").await?;
+    second.send_message(&json!({
+        "method": "login",
+        "username": sender,
+        "password": "pwd"
+    })).await?;
+    ok(second.receive_response().await?)?;
+
+    // Creates the chat
+    first.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "Hello"
+        }
+    })).await?;
+    ok(first.receive_response().await?)?;
+
+    let message = json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "Test"
+        }
+    });
+    first.send_message(&message).await?;
+    second.send_message(&message).await?;
+
+    let (first_resp, second_resp) = (first.receive_response().await?, second.receive_response().await?);
+    println!("{}\n{}", first_resp, second_resp);
+    let first_id = serde_json::from_str::<Value>(&first_resp)?.get("message_id").unwrap().as_i64();
+    let second_id = serde_json::from_str::<Value>(&second_resp)?.get("message_id").unwrap().as_i64();
+    assert_ne!(first_id, second_id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_cursor() -> Result<(), Box<dyn Error>> {
+    let receiver = format!("@{}", generate_random_string(10));
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": receiver,
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let sender = format!("@{}", generate_random_string(10));
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": sender,
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let sender_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    let mut last_id = 0;
+    for _ in 0..2 {
+        c.send_message(&json!({
+            "method": "send_message",
+            "to": user_id,
+            "content": {
+                "text": "Test"
+            }
+        })).await?;
+        let r = c.receive_response().await?;
+        ok(r.clone())?;
+        last_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+    }
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "login",
+        "username": receiver,
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    let unread_count = |resp: &str| -> Result<u64, Box<dyn Error>> {
+        let val = serde_json::from_str::<Value>(resp)?;
+        Ok(val.get("chats").unwrap().as_array().unwrap()[0].get("unread_count").unwrap().as_u64().unwrap())
+    };
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "chats"
+    })).await?;
+    assert_eq!(unread_count(&c.receive_response().await?)?, 2);
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "is_unread",
+        "chat_id": sender_id,
+        "message_ids": [last_id + 100]
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "chats"
+    })).await?;
+    assert_eq!(unread_count(&c.receive_response().await?)?, 0);
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": sender_id,
+        "range": [last_id, 0]
+    })).await?;
+    let val = serde_json::from_str::<Value>(&c.receive_response().await?)?;
+    assert_eq!(val.get("messages").unwrap().as_array().unwrap()[0].get("is_unread").unwrap().as_bool(), Some(false));
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "read_receipts",
+        "chat_id": sender_id,
+        "message_id": last_id
+    })).await?;
+    let val = serde_json::from_str::<Value>(&c.receive_response().await?)?;
+    assert_eq!(val.get("read_by").unwrap().as_array().unwrap().len(), 1);
+    drop(c);
+
+    // Cursor stopped at the latest message, so the next one is unread
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "login",
+        "username": sender,
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "Test"
+        }
+    })).await?;
+    ok(c.receive_response().await?)?;
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "login",
+        "username": receiver,
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "chats"
+    })).await?;
+    assert_eq!(unread_count(&c.receive_response().await?)?, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reactions() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "Test"
+        }
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let message_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "reaction",
+        "chat_id": user_id,
+        "message_id": message_id,
+        "reaction": "not an emoji"
+    })).await?;
+    nok(c.receive_response().await?)?;
+
+    for reaction in ["привет", "👍👍"] {
+        c.send_message(&json!({
+            "method": "edit",
+            "what": "reaction",
+            "chat_id": user_id,
+            "message_id": message_id,
+            "reaction": reaction
+        })).await?;
+        nok(c.receive_response().await?)?;
+    }
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "reaction",
+        "chat_id": user_id,
+        "message_id": message_id,
+        "reaction": "👍"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": user_id,
+        "range": [message_id, 0]
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let message = &val.get("messages").unwrap().as_array().unwrap()[0];
+    assert_eq!(message.get("my_reaction").unwrap().as_str(), Some("👍"));
+    assert_eq!(message.get("reactions").unwrap().as_array().unwrap()[0].get("count").unwrap().as_u64(), Some(1));
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "reaction",
+        "chat_id": user_id,
+        "message_id": message_id,
+        "reaction": null
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn forward() -> Result<(), Box<dyn Error>> {
+    let mut user_ids = vec![];
+    for _ in 0..2 {
+        let mut c = TestConnection::new("3000").await?;
+        c.send_message(&json!({
+            "method": "register",
+            "name": "a",
+            "username": format!("@{}", generate_random_string(10)),
+            "password": "pwd"
+        })).await?;
+        let r = c.receive_response().await?;
+        ok(r.clone())?;
+        user_ids.push(serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap());
+    }
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let self_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_ids[0],
+        "content": {
+            "text": "Forward me"
+        }
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let message_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "forward",
+        "from_chat_id": user_ids[0],
+        "message_ids": [message_id],
+        "to": user_ids[1]
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    ok(r.clone())?;
+    let forwarded_id = serde_json::from_str::<Value>(&r)?.get("message_ids").unwrap()[0].as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": user_ids[1],
+        "range": [forwarded_id, 0]
+    })).await?;
+    let val = serde_json::from_str::<Value>(&c.receive_response().await?)?;
+    let message = &val.get("messages").unwrap().as_array().unwrap()[0];
+    assert_eq!(message.get("content").unwrap().as_str(), Some("Forward me"));
+    assert_eq!(message.get("forwarded_from").unwrap().get("user_id").unwrap().as_i64(), Some(self_id));
+
+    // The copy of an expiring message expires with it
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_ids[0],
+        "content": {
+            "text": "Self-destructing"
+        },
+        "ttl_seconds": 3600
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let message_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "forward",
+        "from_chat_id": user_ids[0],
+        "message_ids": [message_id],
+        "to": user_ids[1]
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let forwarded_id = serde_json::from_str::<Value>(&r)?.get("message_ids").unwrap()[0].as_i64().unwrap();
+
+    let mut expires_at = vec![];
+    for (chat_id, message_id) in [(user_ids[0], message_id), (user_ids[1], forwarded_id)] {
+        c.send_message(&json!({
+            "method": "fetch",
+            "what": "messages",
+            "chat_id": chat_id,
+            "range": [message_id, 0]
+        })).await?;
+        let val = serde_json::from_str::<Value>(&c.receive_response().await?)?;
+        let message = &val.get("messages").unwrap().as_array().unwrap()[0];
+        expires_at.push(message.get("expires_at").unwrap().as_i64().unwrap());
+    }
+    assert!(expires_at[1] <= expires_at[0]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pin_message() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "On-call: me"
+        }
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let message_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+
+    let pin = json!({
+        "method": "edit",
+        "what": "pin",
+        "chat_id": user_id,
+        "message_id": message_id
+    });
+    c.send_message(&pin).await?;
+    ok(c.receive_response().await?)?;
+    // Already pinned
+    c.send_message(&pin).await?;
+    nok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "pinned",
+        "chat_id": user_id
+    })).await?;
+    let val = serde_json::from_str::<Value>(&c.receive_response().await?)?;
+    assert_eq!(val.get("messages").unwrap().as_array().unwrap().len(), 1);
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "chats"
+    })).await?;
+    let val = serde_json::from_str::<Value>(&c.receive_response().await?)?;
+    assert_eq!(val.get("chats").unwrap()[0].get("pinned_message").unwrap().as_i64(), Some(message_id));
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "unpin",
+        "chat_id": user_id,
+        "message_id": message_id
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn search_messages() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    let word = generate_random_string(12);
+    for text in [format!("the {} is here", word), "nothing".to_string()] {
+        c.send_message(&json!({
+            "method": "send_message",
+            "to": user_id,
+            "content": {
+                "text": text
+            }
+        })).await?;
+        ok(c.receive_response().await?)?;
+    }
+    // The index is committed periodically
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "search",
+        "query": word
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let messages = val.get("messages").unwrap().as_array().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].get("chat_id").unwrap().as_i64(), Some(user_id));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn paginate_messages() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    for i in 0..5 {
+        c.send_message(&json!({
+            "method": "send_message",
+            "to": user_id,
+            "content": {
+                "text": format!("{}", i)
+            }
+        })).await?;
+        ok(c.receive_response().await?)?;
+    }
+
+    // Latest two
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": user_id,
+        "limit": 2
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let messages = val.get("messages").unwrap().as_array().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(val.get("has_more").unwrap().as_bool(), Some(true));
+    let cursor = val.get("cursor").unwrap().get("before").unwrap().as_i64().unwrap();
+    assert!(val.get("cursor").unwrap().get("after").unwrap().is_null());
+
+    // The rest
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": user_id,
+        "message_id": cursor,
+        "direction": "before",
+        "limit": 10
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let messages = val.get("messages").unwrap().as_array().unwrap();
+    assert_eq!(messages.len(), 3);
+    assert_eq!(val.get("has_more").unwrap().as_bool(), Some(false));
+    let oldest = messages[2].get("message_id").unwrap().as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": user_id,
+        "message_id": oldest + 2,
+        "direction": "around",
+        "limit": 3
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let ids: Vec<i64> = val
+        .get("messages")
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m.get("message_id").unwrap().as_i64().unwrap())
+        .collect();
+    assert_eq!(ids, vec![oldest + 3, oldest + 2, oldest + 1]);
+    assert_eq!(val.get("has_more").unwrap().as_bool(), Some(true));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn scheduled_messages() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "Too late"
+        },
+        "schedule_at": now - 10
+    })).await?;
+    nok(c.receive_response().await?)?;
+
+    let mut scheduled_ids = vec![];
+    for text in ["Later", "Never"] {
+        c.send_message(&json!({
+            "method": "send_message",
+            "to": user_id,
+            "content": {
+                "text": text
+            },
+            "schedule_at": now + 3600
+        })).await?;
+        let r = c.receive_response().await?;
+        ok(r.clone())?;
+        let val = serde_json::from_str::<Value>(&r)?;
+        scheduled_ids.push(val.get("scheduled_message").unwrap().get("scheduled_id").unwrap().as_i64().unwrap());
+    }
+
+    c.send_message(&json!({
+        "method": "delete",
+        "what": "scheduled",
+        "scheduled_id": scheduled_ids[1]
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "scheduled",
+        "scheduled_id": scheduled_ids[0],
+        "schedule_at": now + 2,
+        "content": "Now"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "scheduled",
+        "chat_id": user_id
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let messages = val.get("messages").unwrap().as_array().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].get("content").unwrap().as_str(), Some("Now"));
+
+    // Delivered to the sender as well
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    assert_eq!(val.get("event").unwrap().as_str(), Some("new_message"));
+    assert_eq!(val.get("new_message").unwrap().get("content").unwrap().as_str(), Some("Now"));
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "scheduled"
+    })).await?;
+    let r = c.receive_response().await?;
+    let val = serde_json::from_str::<Value>(&r)?;
+    assert!(val.get("messages").unwrap().as_array().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn expiring_messages() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "Invalid"
+        },
+        "ttl_seconds": 0
+    })).await?;
+    nok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "Stays"
+        }
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "auto_delete",
+        "chat_id": user_id,
+        "ttl_seconds": 1
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "Gone"
+        }
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    // The sweeper runs every few seconds, the sender is notified too
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    assert_eq!(val.get("event").unwrap().as_str(), Some("delete_message"));
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": user_id
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let messages = val.get("messages").unwrap().as_array().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].get("content").unwrap().as_str(), Some("Stays"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn saved_messages() -> Result<(), Box<dyn Error>> {
+    let username = format!("@{}", generate_random_string(10));
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": username,
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let self_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    let mut other = TestConnection::new("3000").await?;
+    other.send_message(&json!({
+        "method": "login",
+        "username": username,
+        "password": "pwd"
+    })).await?;
+    ok(other.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": self_id,
+        "content": {
+            "text": "Note"
+        }
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let message_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+
+    // Other connections of the user are synced, the sending one only gets the response
+    let event = serde_json::from_str::<Value>(&other.receive_response().await?)?;
+    assert_eq!(event.get("event").unwrap().as_str(), Some("new_chat"));
+    let event = serde_json::from_str::<Value>(&other.receive_response().await?)?;
+    assert_eq!(event.get("event").unwrap().as_str(), Some("new_message"));
+    assert_eq!(event.get("new_message").unwrap().get("chat_id").unwrap().as_i64(), Some(self_id));
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "chats"
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let chats = val.get("chats").unwrap().as_array().unwrap();
+    assert_eq!(chats.len(), 1);
+    assert_eq!(chats[0].get("chat_id").unwrap().as_i64(), Some(self_id));
+    assert_eq!(chats[0].get("name").unwrap().as_str(), Some("Saved Messages"));
+    assert_eq!(chats[0].get("unread_count").unwrap().as_u64(), Some(0));
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": self_id
+    })).await?;
+    let r = c.receive_response().await?;
+    let val = serde_json::from_str::<Value>(&r)?;
+    assert_eq!(val.get("messages").unwrap().as_array().unwrap().len(), 1);
+
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "message",
+        "chat_id": self_id,
+        "message_id": message_id,
+        "content": "Edited note"
+    })).await?;
+    ok(c.receive_response().await?)?;
+    let event = serde_json::from_str::<Value>(&other.receive_response().await?)?;
+    assert_eq!(event.get("event").unwrap().as_str(), Some("edit_message"));
+
+    c.send_message(&json!({
+        "method": "delete",
+        "what": "messages",
+        "chat_id": self_id,
+        "message_ids": [message_id],
+        "for_everyone": true
+    })).await?;
+    ok(c.receive_response().await?)?;
+    let event = serde_json::from_str::<Value>(&other.receive_response().await?)?;
+    assert_eq!(event.get("event").unwrap().as_str(), Some("delete_message"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn edit_history() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "First"
+        }
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let message_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+
+    for text in ["Second", "Third"] {
+        c.send_message(&json!({
+            "method": "edit",
+            "what": "message",
+            "chat_id": user_id,
+            "message_id": message_id,
+            "content": text
+        })).await?;
+        ok(c.receive_response().await?)?;
+    }
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "message_history",
+        "chat_id": user_id,
+        "message_id": message_id
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let revisions = val.get("revisions").unwrap().as_array().unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0].get("content").unwrap().as_str(), Some("First"));
+    assert_eq!(revisions[1].get("content").unwrap().as_str(), Some("Second"));
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": user_id,
+        "range": [message_id, 0]
+    })).await?;
+    let r = c.receive_response().await?;
+    let val = serde_json::from_str::<Value>(&r)?;
+    let message = &val.get("messages").unwrap().as_array().unwrap()[0];
+    assert_eq!(message.get("content").unwrap().as_str(), Some("Third"));
+    assert!(message.get("edited_at").unwrap().is_i64());
+
+    // History goes away with the message
+    c.send_message(&json!({
+        "method": "delete",
+        "what": "messages",
+        "chat_id": user_id,
+        "message_ids": [message_id],
+        "for_everyone": true
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "message_history",
+        "chat_id": user_id,
+        "message_id": message_id
+    })).await?;
+    nok(c.receive_response().await?)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn delete_for_me_and_everyone() -> Result<(), Box<dyn Error>> {
+    let mut receiver = TestConnection::new("3000").await?;
+    receiver.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = receiver.receive_response().await?;
+    ok(r.clone())?;
+    let receiver_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    let mut sender = TestConnection::new("3000").await?;
+    sender.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = sender.receive_response().await?;
+    ok(r.clone())?;
+    let sender_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    let mut message_ids = vec![];
+    for text in ["One", "Two"] {
+        sender.send_message(&json!({
+            "method": "send_message",
+            "to": receiver_id,
+            "content": {
+                "text": text
+            }
+        })).await?;
+        let r = sender.receive_response().await?;
+        ok(r.clone())?;
+        message_ids.push(serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap());
+        // new_message event
+        receiver.receive_response().await?;
+    }
+
+    // Not the author
+    receiver.send_message(&json!({
+        "method": "delete",
+        "what": "messages",
+        "chat_id": sender_id,
+        "message_ids": [message_ids[0]],
+        "for_everyone": true
+    })).await?;
+    nok(receiver.receive_response().await?)?;
+
+    receiver.send_message(&json!({
+        "method": "delete",
+        "what": "messages",
+        "chat_id": sender_id,
+        "message_ids": [message_ids[0]]
+    })).await?;
+    ok(receiver.receive_response().await?)?;
+
+    receiver.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": sender_id
+    })).await?;
+    let r = receiver.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let messages = val.get("messages").unwrap().as_array().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].get("content").unwrap().as_str(), Some("Two"));
+
+    // Hidden message can't be forwarded by the one who hid it
+    receiver.send_message(&json!({
+        "method": "forward",
+        "from_chat_id": sender_id,
+        "message_ids": [message_ids[0]],
+        "to": sender_id
+    })).await?;
+    nok(receiver.receive_response().await?)?;
+
+    sender.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": receiver_id
+    })).await?;
+    let r = sender.receive_response().await?;
+    let val = serde_json::from_str::<Value>(&r)?;
+    assert_eq!(val.get("messages").unwrap().as_array().unwrap().len(), 2);
+
+    sender.send_message(&json!({
+        "method": "delete",
+        "what": "messages",
+        "chat_id": receiver_id,
+        "message_ids": [message_ids[1]],
+        "for_everyone": true
+    })).await?;
+    ok(sender.receive_response().await?)?;
+
+    let r = receiver.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    assert_eq!(val.get("event").unwrap().as_str(), Some("delete_message"));
+    assert_eq!(val.get("chat_id").unwrap().as_i64(), Some(sender_id));
+
+    receiver.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": sender_id
+    })).await?;
+    let r = receiver.receive_response().await?;
+    let val = serde_json::from_str::<Value>(&r)?;
+    assert!(val.get("messages").unwrap().as_array().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn delete_all_messages_private() -> Result<(), Box<dyn Error>> {
+    let receiver_name = format!("@{}", generate_random_string(10));
+    let mut receiver = TestConnection::new("3000").await?;
+    receiver.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": receiver_name,
+        "password": "pwd"
+    })).await?;
+    let r = receiver.receive_response().await?;
+    ok(r.clone())?;
+    let receiver_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    let mut sender = TestConnection::new("3000").await?;
+    sender.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = sender.receive_response().await?;
+    ok(r.clone())?;
+    let sender_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    sender.send_message(&json!({
+        "method": "send_message",
+        "to": receiver_id,
+        "content": {
+            "text": "One"
+        }
+    })).await?;
+    ok(sender.receive_response().await?)?;
+    // new_message event
+    receiver.receive_response().await?;
+
+    receiver.send_message(&json!({
+        "method": "send_message",
+        "to": sender_id,
+        "content": {
+            "text": "Two"
+        }
+    })).await?;
+    let r = receiver.receive_response().await?;
+    ok(r.clone())?;
+    let own_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+    sender.receive_response().await?;
+
+    let mut other = TestConnection::new("3000").await?;
+    other.send_message(&json!({
+        "method": "login",
+        "username": receiver_name,
+        "password": "pwd"
+    })).await?;
+    ok(other.receive_response().await?)?;
+
+    // Only own messages are deleted for everyone, the rest is hidden for the receiver
+    receiver.send_message(&json!({
+        "method": "delete",
+        "what": "all_messages",
+        "chat_id": sender_id,
+        "for_everyone": true
+    })).await?;
+    ok(receiver.receive_response().await?)?;
+
+    let r = sender.receive_response().await?;
+    let val = serde_json::from_str::<Value>(&r)?;
+    assert_eq!(val.get("event").unwrap().as_str(), Some("delete_message"));
+    assert_eq!(val.get("message_ids").unwrap(), &json!([own_id]));
+
+    // Other connection of the receiver learns about both, in any order
+    let mut events = vec![];
+    for _ in 0..2 {
+        let val = serde_json::from_str::<Value>(&other.receive_response().await?)?;
+        events.push(val.get("event").unwrap().as_str().unwrap().to_owned());
+    }
+    events.sort();
+    assert_eq!(events, ["delete_all_messages", "delete_message"]);
+
+    sender.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": receiver_id
+    })).await?;
+    let val = serde_json::from_str::<Value>(&sender.receive_response().await?)?;
+    let messages = val.get("messages").unwrap().as_array().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].get("content").unwrap().as_str(), Some("One"));
+
+    receiver.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": sender_id
+    })).await?;
+    let val = serde_json::from_str::<Value>(&receiver.receive_response().await?)?;
+    assert!(val.get("messages").unwrap().as_array().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn threads() -> Result<(), Box<dyn Error>> {
+    let receiver = format!("@{}", generate_random_string(10));
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": receiver,
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let sender_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "Root"
+        }
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let root_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+
+    let mut reply_ids = vec![];
+    for text in ["First", "Second", "Third"] {
+        c.send_message(&json!({
+            "method": "send_message",
+            "to": user_id,
+            "reply_to": root_id,
+            "content": {
+                "text": text
+            }
+        })).await?;
+        let r = c.receive_response().await?;
+        ok(r.clone())?;
+        reply_ids.push(serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap());
+    }
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "thread",
+        "chat_id": user_id,
+        "message_id": root_id,
+        "limit": 2
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let val = serde_json::from_str::<Value>(&r)?;
+    let replies = val.get("replies").unwrap().as_array().unwrap();
+    assert_eq!(replies.len(), 2);
+    assert_eq!(replies[0].get("content").unwrap().as_str(), Some("First"));
+    assert_eq!(val.get("has_more").unwrap().as_bool(), Some(true));
+    let thread = val.get("message").unwrap().get("thread").unwrap();
+    assert_eq!(thread.get("reply_count").unwrap().as_u64(), Some(3));
+    assert_eq!(thread.get("last_reply_id").unwrap().as_i64(), Some(reply_ids[2]));
+    // Own replies are read by definition
+    assert_eq!(thread.get("unread_count").unwrap().as_u64(), Some(0));
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "thread",
+        "chat_id": user_id,
+        "message_id": root_id,
+        "after": reply_ids[1]
+    })).await?;
+    let r = c.receive_response().await?;
+    let val = serde_json::from_str::<Value>(&r)?;
+    assert_eq!(val.get("replies").unwrap().as_array().unwrap().len(), 1);
+    assert_eq!(val.get("has_more").unwrap().as_bool(), Some(false));
+
+    // Deleted replies leave the thread
+    c.send_message(&json!({
+        "method": "delete",
+        "what": "messages",
+        "chat_id": user_id,
+        "message_ids": [reply_ids[2]],
+        "for_everyone": true
+    })).await?;
+    ok(c.receive_response().await?)?;
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "login",
+        "username": receiver,
+        "password": "pwd"
+    })).await?;
+    ok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": sender_id,
+        "range": [root_id, 0]
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let thread = val.get("messages").unwrap().as_array().unwrap()[0].get("thread").unwrap().clone();
+    assert_eq!(thread.get("reply_count").unwrap().as_u64(), Some(2));
+    assert_eq!(thread.get("unread_count").unwrap().as_u64(), Some(2));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reply_previews() -> Result<(), Box<dyn Error>> {
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let user_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+    drop(c);
+
+    let mut c = TestConnection::new("3000").await?;
+    c.send_message(&json!({
+        "method": "register",
+        "name": "a",
+        "username": format!("@{}", generate_random_string(10)),
+        "password": "pwd"
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let sender_id = serde_json::from_str::<Value>(&r)?.get("user_id").unwrap().as_i64().unwrap();
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "content": {
+            "text": "a".repeat(150)
+        }
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let root_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+
+    // Replies must point at an existing message of the same chat
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "reply_to": root_id + 100,
+        "content": {
+            "text": "Nowhere"
+        }
+    })).await?;
+    nok(c.receive_response().await?)?;
+
+    c.send_message(&json!({
+        "method": "send_message",
+        "to": user_id,
+        "reply_to": root_id,
+        "content": {
+            "text": "Reply"
+        }
+    })).await?;
+    let r = c.receive_response().await?;
+    ok(r.clone())?;
+    let reply_id = serde_json::from_str::<Value>(&r)?.get("message_id").unwrap().as_i64().unwrap();
+
+    // Only earlier messages can be replied to
+    c.send_message(&json!({
+        "method": "edit",
+        "what": "message",
+        "chat_id": user_id,
+        "message_id": root_id,
+        "reply_to": reply_id
+    })).await?;
+    nok(c.receive_response().await?)?;
+
+    // Rejected edits don't leave revisions behind
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "message_history",
+        "chat_id": user_id,
+        "message_id": root_id
+    })).await?;
+    let val = serde_json::from_str::<Value>(&c.receive_response().await?)?;
+    assert!(val.get("revisions").unwrap().as_array().unwrap().is_empty());
+
+    c.send_message(&json!({
+        "method": "fetch",
+        "what": "messages",
+        "chat_id": user_id,
+        "range": [reply_id, 0]
+    })).await?;
+    let r = c.receive_response().await?;
+    println!("{}", r);
+    let val = serde_json::from_str::<Value>(&r)?;
+    let preview = val.get("messages").unwrap().as_array().unwrap()[0].get("reply_preview").unwrap().clone();
+    assert_eq!(preview.get("message_id").unwrap().as_i64(), Some(root_id));
+    assert_eq!(preview.get("from_id").unwrap().as_i64(), Some(sender_id));
+    assert_eq!(preview.get("text").unwrap().as_str().unwrap().len(), 100);
+    assert!(preview.get("media").unwrap().is_null());
+
+    Ok(())
+}